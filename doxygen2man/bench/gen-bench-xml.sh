@@ -0,0 +1,164 @@
+#!/bin/sh
+#
+# Copyright (C) 2026 the libqb authors.  All rights reserved.
+#
+# This software licensed under GPL-2.0+
+#
+# Generate a synthetic doxygen XML tree for a header with a large
+# number of functions (and one structure for every ten of them),
+# for benchmarking doxygen2man.
+#
+# usage: gen-bench-xml.sh <number of functions> <output dir>
+#
+
+if [ $# -ne 2 ]; then
+	echo "usage: $0 <number of functions> <output dir>" >&2
+	exit 1
+fi
+
+count=$1
+outdir=$2
+
+mkdir -p "$outdir" || exit 1
+
+gen_struct()
+{
+	s=$1
+	cat <<ENDSTRUCT
+<?xml version='1.0' encoding='UTF-8' standalone='no'?>
+<doxygen version="1.8.20">
+  <compounddef id="structbench__struct__$s" kind="struct" language="C++" prot="public">
+    <compoundname>bench_struct_$s</compoundname>
+      <sectiondef kind="public-attrib">
+      <memberdef kind="variable" id="structbench__struct__${s}_1a0" prot="public" static="no" mutable="no">
+        <type>int32_t</type>
+        <definition>int32_t bench_struct_$s::id</definition>
+        <argsstring></argsstring>
+        <name>id</name>
+        <briefdescription>
+<para>Identifier </para>
+        </briefdescription>
+        <detaileddescription>
+        </detaileddescription>
+      </memberdef>
+      <memberdef kind="variable" id="structbench__struct__${s}_1a1" prot="public" static="no" mutable="no">
+        <type>void *</type>
+        <definition>void* bench_struct_$s::data</definition>
+        <argsstring></argsstring>
+        <name>data</name>
+        <briefdescription>
+<para>User data </para>
+        </briefdescription>
+        <detaileddescription>
+        </detaileddescription>
+      </memberdef>
+      </sectiondef>
+    <briefdescription>
+<para>Benchmark structure $s. </para>
+    </briefdescription>
+    <detaileddescription>
+    </detaileddescription>
+  </compounddef>
+</doxygen>
+ENDSTRUCT
+}
+
+gen_function()
+{
+	f=$1
+	s=$(($f / 10))
+	cat <<ENDFUNC
+      <memberdef kind="function" id="bench_8h_1a$f" prot="public" static="no" const="no" explicit="no" inline="no" virt="non-virtual">
+        <type>int32_t</type>
+        <definition>int32_t bench_function_$f</definition>
+        <argsstring>(struct bench_struct_$s *s, const char *name, size_t len)</argsstring>
+        <name>bench_function_$f</name>
+        <param>
+          <type>struct <ref refid="structbench__struct__$s" kindref="compound">bench_struct_$s</ref> *</type>
+          <declname>s</declname>
+        </param>
+        <param>
+          <type>const char *</type>
+          <declname>name</declname>
+        </param>
+        <param>
+          <type>size_t</type>
+          <declname>len</declname>
+        </param>
+        <briefdescription>
+<para>Benchmark function number $f. </para>
+        </briefdescription>
+        <detaileddescription>
+<para>This function does nothing useful but has a reasonably typical amount of documentation, including a reference to <computeroutput>bench_function_0</computeroutput>.</para>
+<para><parameterlist kind="param"><parameteritem>
+<parameternamelist>
+<parametername>s</parametername>
+</parameternamelist>
+<parameterdescription>
+<para>the structure </para>
+</parameterdescription>
+</parameteritem>
+<parameteritem>
+<parameternamelist>
+<parametername>name</parametername>
+</parameternamelist>
+<parameterdescription>
+<para>a name </para>
+</parameterdescription>
+</parameteritem>
+<parameteritem>
+<parameternamelist>
+<parametername>len</parametername>
+</parameternamelist>
+<parameterdescription>
+<para>length of name </para>
+</parameterdescription>
+</parameteritem>
+</parameterlist>
+<simplesect kind="return"><para>0 on success or -errno </para>
+</simplesect>
+</para>
+        </detaileddescription>
+        <location file="bench.h" line="$(($f * 20 + 10))" column="9"/>
+      </memberdef>
+ENDFUNC
+}
+
+s=0
+while [ $s -le $(($count / 10)) ]; do
+	gen_struct $s > "$outdir/structbench__struct__$s.xml"
+	s=$(($s + 1))
+done
+
+{
+	cat <<ENDHEAD
+<?xml version='1.0' encoding='UTF-8' standalone='no'?>
+<doxygen version="1.8.20">
+  <compounddef id="bench_8h" kind="file" language="C++">
+    <compoundname>bench.h</compoundname>
+ENDHEAD
+	s=0
+	while [ $s -le $(($count / 10)) ]; do
+		echo "    <innerclass refid=\"structbench__struct__$s\" prot=\"public\">bench_struct_$s</innerclass>"
+		s=$(($s + 1))
+	done
+	cat <<ENDSECT
+      <sectiondef kind="func">
+ENDSECT
+	f=0
+	while [ $f -lt $count ]; do
+		gen_function $f
+		f=$(($f + 1))
+	done
+	cat <<ENDTAIL
+      </sectiondef>
+    <briefdescription>
+<para>Synthetic header for benchmarking. </para>
+    </briefdescription>
+    <detaileddescription>
+    </detaileddescription>
+    <location file="bench.h"/>
+  </compounddef>
+</doxygen>
+ENDTAIL
+} > "$outdir/bench_8h.xml"
@@ -0,0 +1,46 @@
+#!/bin/sh
+#
+# Copyright (C) 2026 the libqb authors.  All rights reserved.
+#
+# This software licensed under GPL-2.0+
+#
+# Time doxygen2man parsing and rendering a synthetic header
+#
+# usage: run-bench.sh [<doxygen2man binary> [<number of functions>]]
+#
+
+d2m=${1:-./doxygen2man}
+count=${2:-500}
+srcdir=$(dirname "$0")
+
+tmpdir=$(mktemp -d "${TMPDIR:-/tmp}/doxygen2man-bench.XXXXXX") || exit 1
+trap 'rm -rf "$tmpdir"' EXIT
+
+"$srcdir/gen-bench-xml.sh" "$count" "$tmpdir/xml" || exit 1
+mkdir "$tmpdir/man"
+
+# Nanoseconds where date knows %N (GNU, busybox), elsewhere it prints a
+# literal N and the times are only good to the second
+now()
+{
+	t=$(date +%s%N)
+	case "$t" in
+	*N) echo $((${t%N} * 1000000000)) ;;
+	*) echo "$t" ;;
+	esac
+}
+
+report()
+{
+	echo "$1: $count functions in $((($3 - $2) / 1000000)) ms"
+}
+
+start=$(now)
+"$d2m" -a -q -d "$tmpdir/xml" bench_8h.xml > /dev/null || exit 1
+end=$(now)
+report "parse (ascii output)" $start $end
+
+start=$(now)
+"$d2m" -m -g -P -q -d "$tmpdir/xml" -o "$tmpdir/man" bench_8h.xml || exit 1
+end=$(now)
+report "render man pages" $start $end
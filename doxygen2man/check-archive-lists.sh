@@ -0,0 +1,52 @@
+#!/bin/sh
+#
+# Copyright (C) 2026 the libqb authors.  All rights reserved.
+#
+# This software licensed under GPL-2.0+
+#
+# With --output-archive the pages are written to a temporary directory,
+# check that --manifest, --install-metadata and --emit-install-rules list
+# them by their paths in the archive rather than in that directory.
+#
+# usage: check-archive-lists.sh [<doxygen2man binary> [<fixtures xml dir>]]
+#
+
+d2m=${1:-./doxygen2man}
+xmldir=${2:-$(dirname "$0")/fixtures/xml}
+
+tmpdir=$(mktemp -d "${TMPDIR:-/tmp}/doxygen2man-check.XXXXXX") || exit 1
+trap 'rm -rf "$tmpdir"' EXIT
+
+errors=0
+
+check()
+{
+	name=$1
+	shift
+
+	TMPDIR="$tmpdir" "$d2m" -q -m "$@" --output-archive "$tmpdir/$name.tar" \
+		--manifest "$tmpdir/$name.manifest" --install-metadata "$tmpdir/$name.meta" \
+		--emit-install-rules make -d "$xmldir" fixture_8h.xml structfixture__widget.xml \
+		> "$tmpdir/$name.rules" || { echo "$name: doxygen2man failed"; errors=$((errors + 1)); return; }
+	tar -tf "$tmpdir/$name.tar" > "$tmpdir/$name.contents" || { errors=$((errors + 1)); return; }
+
+	# Every page in the lists has to be in the archive, under the same name
+	for page in $(grep -v '^#' "$tmpdir/$name.manifest" | cut -f1) \
+		    $(grep '^page' "$tmpdir/$name.meta" | cut -f2) \
+		    $(awk '/_pages = \\$/ { p = 1; next } /^$/ { p = 0 } p { print $1 }' "$tmpdir/$name.rules"); do
+		if ! grep -qxF "$page" "$tmpdir/$name.contents"; then
+			echo "$name: $page is not in the archive"
+			errors=$((errors + 1))
+		fi
+	done
+	if [ -z "$(grep -v '^#' "$tmpdir/$name.manifest")" ]; then
+		echo "$name: empty manifest"
+		errors=$((errors + 1))
+	fi
+}
+
+check plain
+check man-dirs --man-dirs
+
+echo "check-archive-lists: $errors error$([ $errors = 1 ] || echo s)"
+[ $errors = 0 ]
@@ -0,0 +1,103 @@
+#!/bin/sh
+#
+# Copyright (C) 2026 the libqb authors.  All rights reserved.
+#
+# This software licensed under GPL-2.0+
+#
+# Build the fixture's pages in a small CMake project with the module that
+# "--emit-rules cmake" prints, and check that a second build doesn't run
+# doxygen2man again and that touching one XML file reruns it for just that
+# header. Skipped when cmake isn't installed. Doesn't run doxygen, the
+# fixture XML is used as it is.
+#
+# usage: check-cmake-module.sh [<doxygen2man binary> [<fixtures xml dir>]]
+#
+
+d2m=${1:-./doxygen2man}
+xmldir=${2:-$(dirname "$0")/fixtures/xml}
+
+if ! command -v cmake > /dev/null 2>&1; then
+	echo "check-cmake-module: cmake is not installed, skipped"
+	exit 0
+fi
+
+case "$d2m" in
+/*) ;;
+*) d2m="$(pwd)/$d2m" ;;
+esac
+
+tmpdir=$(mktemp -d "${TMPDIR:-/tmp}/doxygen2man-cmake.XXXXXX") || exit 1
+trap 'rm -rf "$tmpdir"' EXIT
+
+errors=0
+
+fail()
+{
+	echo "check-cmake-module: $*"
+	errors=$((errors + 1))
+}
+
+mkdir "$tmpdir/xml" "$tmpdir/src" "$tmpdir/build"
+cp "$xmldir"/*.xml "$tmpdir/xml" || exit 1
+
+# --emit-rules takes the headers from index.xml, which doxygen would have written
+cat > "$tmpdir/xml/index.xml" <<'EOF'
+<?xml version='1.0' encoding='UTF-8' standalone='no'?>
+<doxygenindex version="1.8.20">
+  <compound refid="fixture_8h" kind="file"><name>fixture.h</name>
+  </compound>
+  <compound refid="escape_8h" kind="file"><name>escape.h</name>
+  </compound>
+</doxygenindex>
+EOF
+
+"$d2m" --emit-rules cmake -d "$tmpdir/xml" -o "$tmpdir/man" > "$tmpdir/src/Doxygen2Man.cmake" || {
+	fail "--emit-rules cmake failed"
+	exit 1
+}
+
+cat > "$tmpdir/src/CMakeLists.txt" <<'EOF'
+cmake_minimum_required(VERSION 3.9)
+project(doxygen2man_check NONE)
+include("${CMAKE_CURRENT_SOURCE_DIR}/Doxygen2Man.cmake")
+doxygen2man_add_pages(man ALL FLAGS -g)
+EOF
+
+build()
+{
+	(cd "$tmpdir/build" && cmake --build .) > "$tmpdir/build.log" 2>&1 || {
+		cat "$tmpdir/build.log"
+		fail "$1 build failed"
+	}
+}
+
+(cd "$tmpdir/build" && cmake -DDOXYGEN2MAN_EXECUTABLE="$d2m" ../src) > "$tmpdir/cmake.log" 2>&1 || {
+	cat "$tmpdir/cmake.log"
+	fail "cmake failed"
+	exit 1
+}
+
+build first
+for page in fixture_widget_create.3 escape_split.3 fixture.h.3 escape.h.3; do
+	[ -f "$tmpdir/man/$page" ] || fail "$page wasn't written"
+done
+for list in man-fixture_8h.list man-escape_8h.list; do
+	[ -f "$tmpdir/build/$list" ] || fail "$list wasn't written"
+done
+
+# The manifests are rewritten on every run, so they show which headers were done
+sleep 1
+touch "$tmpdir/stamp"
+sleep 1
+build second
+if [ -n "$(find "$tmpdir/build" -name '*.list' -newer "$tmpdir/stamp")" ]; then
+	fail "doxygen2man ran again with nothing changed"
+fi
+
+touch "$tmpdir/xml/escape_8h.xml"
+build third
+[ "$tmpdir/build/man-escape_8h.list" -nt "$tmpdir/stamp" ] || fail "escape.h wasn't done again after its XML changed"
+[ "$tmpdir/build/man-fixture_8h.list" -nt "$tmpdir/stamp" ] && fail "fixture.h was done again when only escape.h changed"
+
+echo "check-cmake-module: $errors error$([ $errors = 1 ] || echo s)"
+[ $errors = 0 ]
@@ -0,0 +1,55 @@
+#!/bin/sh
+#
+# Copyright (C) 2026 the libqb authors.  All rights reserved.
+#
+# This software licensed under GPL-2.0+
+#
+# The escape.h fixture has text with backslashes, minus signs and lines
+# that start with . or ', check that they come out escaped so that nroff
+# prints them rather than acting on them.
+#
+# usage: check-escapes.sh [<doxygen2man binary> [<fixtures xml dir>]]
+#
+
+d2m=${1:-./doxygen2man}
+xmldir=${2:-$(dirname "$0")/fixtures/xml}
+
+tmpdir=$(mktemp -d "${TMPDIR:-/tmp}/doxygen2man-escapes.XXXXXX") || exit 1
+trap 'rm -rf "$tmpdir"' EXIT
+
+errors=0
+
+"$d2m" -q -m -P -d "$xmldir" -o "$tmpdir" escape_8h.xml || {
+	echo "check-escapes: doxygen2man failed"
+	exit 1
+}
+
+# The text keeps the space doxygen leaves at the end of a paragraph
+page="$tmpdir/escape_split.txt"
+sed 's/ *$//' "$tmpdir/escape_split.3" > "$page"
+
+# Lines the page has to have, as they are
+while IFS= read -r line; do
+	if ! grep -qxF -- "$line" "$page"; then
+		printf 'missing: %s\n' "$line"
+		errors=$((errors + 1))
+	fi
+done <<'EOF'
+escape_split \- Split a path like C:\etemp\enew at \-sep.
+\fBpath \fP\fIthe path, eg \e\eserver\eshare \fP
+\fBsep  \fP\fIthe separator, 1\-255 \fP
+\&.profile and .config are skipped, as is anything after \--.
+\&'quoted' parts are kept whole, and a \e before the separator escapes it, see \fB\-\-no\-split\fP.
+n = escape_split("a\e\eb", \[aq]\e\e');
+\[char46]x = \-n;
+\[aq]c';
+\fB\-1         \fPthe path ends in a \e
+EOF
+
+# Nothing from the text may be left where nroff would read it as a request
+if grep -nE "^[.'](profile|quoted|x|c')" "$page"; then
+	errors=$((errors + 1))
+fi
+
+echo "check-escapes: $errors error$([ $errors = 1 ] || echo s)"
+[ $errors = 0 ]
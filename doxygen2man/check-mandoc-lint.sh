@@ -0,0 +1,47 @@
+#!/bin/sh
+#
+# Copyright (C) 2026 the libqb authors.  All rights reserved.
+#
+# This software licensed under GPL-2.0+
+#
+# Generate the fixture's pages in each --dialect and check them with
+# "mandoc -Tlint". Skipped when mandoc isn't installed.
+#
+# usage: check-mandoc-lint.sh [<doxygen2man binary> [<fixtures xml dir>]]
+#
+
+d2m=${1:-./doxygen2man}
+xmldir=${2:-$(dirname "$0")/fixtures/xml}
+
+if ! command -v mandoc > /dev/null 2>&1; then
+	echo "check-mandoc-lint: mandoc is not installed, skipped"
+	exit 0
+fi
+
+tmpdir=$(mktemp -d "${TMPDIR:-/tmp}/doxygen2man-lint.XXXXXX") || exit 1
+trap 'rm -rf "$tmpdir"' EXIT
+
+errors=0
+
+for dialect in man groff; do
+	mkdir "$tmpdir/$dialect"
+	"$d2m" -q -m -g -P --struct-pages --enum-pages --constants-page \
+		--index-page fixture_index --deprecated-page fixture-deprecated \
+		--header-includes --dialect "$dialect" -d "$xmldir" -o "$tmpdir/$dialect" \
+		fixture_8h.xml structfixture__widget.xml escape_8h.xml || {
+		echo "$dialect: doxygen2man failed"
+		errors=$((errors + 1))
+		continue
+	}
+	for page in "$tmpdir/$dialect"/*; do
+		# Warnings and worse, the style messages are a matter of taste
+		mandoc -Tlint -Wwarning "$page" > "$tmpdir/lint.out" 2>&1
+		if [ -s "$tmpdir/lint.out" ]; then
+			sed "s|^$tmpdir/||" "$tmpdir/lint.out"
+			errors=$((errors + 1))
+		fi
+	done
+done
+
+echo "check-mandoc-lint: $errors error$([ $errors = 1 ] || echo s)"
+[ $errors = 0 ]
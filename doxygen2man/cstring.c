@@ -104,6 +104,18 @@ cstring_t cstring_from_chars(const char* chars)
 	return cstring_append_chars(new_string, chars);
 }
 
+/* '\0' if the string is empty */
+char cstring_last_char(cstring_t cstring)
+{
+	struct cstring_header *h = (struct cstring_header *)(char *)cstring;
+
+	if (!h || !h->used) {
+		return '\0';
+	}
+	assert(h->checker == CHECKER_WORD);
+	return h->the_string[h->used - 1];
+}
+
 void cstring_free(cstring_t cstring)
 {
 	struct cstring_header *h = (struct cstring_header *)(char *)cstring;
@@ -9,6 +9,7 @@ cstring_t cstring_dup(cstring_t string);
 char *cstring_to_chars(cstring_t cstring);
 cstring_t cstring_append_chars(cstring_t cstring, const char *newstring);
 cstring_t cstring_append_cstring(cstring_t cstring, cstring_t newstring);
+char cstring_last_char(cstring_t cstring);
 void cstring_free(cstring_t cstring);
 
 #endif
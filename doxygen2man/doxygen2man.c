@@ -21,8 +21,13 @@
 #define _XOPEN_SOURCE
 #define _XOPEN_SOURCE_EXTENDED
 #include <stdlib.h>
+#include <stdarg.h>
 #include <sys/time.h>
 #include <sys/stat.h>
+#include <sys/mman.h>
+#include <sys/wait.h>
+#include <sys/file.h>
+#include <sys/ioctl.h>
 #include <time.h>
 #include <stdio.h>
 #include <limits.h>
@@ -30,7 +35,20 @@
 #include <getopt.h>
 #include <errno.h>
 #include <ctype.h>
+#include <stdint.h>
+#include <inttypes.h>
+#include <wchar.h>
+#include <locale.h>
+#include <dirent.h>
+#include <glob.h>
+#include <fnmatch.h>
+#include <ftw.h>
+#include <regex.h>
+#include <unistd.h>
+#include <fcntl.h>
+#include <pthread.h>
 #include <libxml/tree.h>
+#include <libxml/encoding.h>
 #include <qb/qblist.h>
 #include <qb/qbmap.h>
 #include "cstring.h"
@@ -43,41 +61,665 @@
  */
 #define LINE_LENGTH 80
 
+/* The most that the width options and -j/--write-threads will take */
+#define LINE_LENGTH_MAX 1000
+#define MAX_THREADS 256
+
+#ifndef FIXTURE_DIR
+#define FIXTURE_DIR "./fixtures/xml/"
+#endif
+
+#ifndef DOXYGEN2MAN_VERSION
+#define DOXYGEN2MAN_VERSION "unknown"
+#endif
+
+/* Long-only options start after the range of the single-character ones */
+enum {
+	OPT_SELFTEST = 256,
+	OPT_CONFIG,
+	OPT_BUGS_URL,
+	OPT_BUGS_EMAIL,
+	OPT_EXAMPLES_DIR,
+	OPT_FORCE,
+	OPT_OLD_MANIFEST,
+	OPT_ALIAS_STUBS,
+	OPT_LIST_SYMBOLS,
+	OPT_KEEP_GOING,
+	OPT_MANIFEST,
+	OPT_EMIT_INSTALL_RULES,
+	OPT_LINT_OUTPUT,
+	OPT_STRICT,
+	OPT_PREVIEW,
+	OPT_NO_CLOBBER,
+	OPT_BACKUP,
+	OPT_CLEAN,
+	OPT_INCLUDE_SYMBOLS,
+	OPT_EXCLUDE_SYMBOLS,
+	OPT_SYMBOL_PREFIX,
+	OPT_SORT,
+	OPT_SEE_ALSO_GROUP,
+	OPT_SEE_ALSO,
+	OPT_TITLE_CASE,
+	OPT_TH_SOURCE,
+	OPT_TH_MANUAL,
+	OPT_GENERATED_COMMENT,
+	OPT_NO_GENERATED_COMMENT,
+	OPT_LICENSE_FILE,
+	OPT_REQUIRE_SPDX,
+	OPT_SYNOPSIS_WIDTH,
+	OPT_MAX_PARAM_TYPE,
+	OPT_EXAMPLE_MACROS,
+	OPT_EXCLUDE_STATIC,
+	OPT_EXCLUDE_INLINE,
+	OPT_EXPORTED_SYMBOLS,
+	OPT_SYMBOL_INFO,
+	OPT_WHATIS_FILE,
+	OPT_INDEX_PAGE,
+	OPT_OUTPUT_ARCHIVE,
+	OPT_MAN_DIRS,
+	OPT_FHS_LAYOUT,
+	OPT_JSON_INDEX,
+	OPT_TAGS_FILE,
+	OPT_GENERATE_TAGFILE,
+	OPT_TAGFILE,
+	OPT_XML_ENCODING,
+	OPT_EMIT_RULES,
+	OPT_INSTALL_METADATA,
+	OPT_PAGE_HOOK,
+	OPT_COMBINE_BY_PREFIX,
+	OPT_ALIAS_LINKS,
+	OPT_STRUCT_PAGES,
+	OPT_ENUM_PAGES,
+	OPT_CONSTANTS_PAGE,
+	OPT_CONSTANTS_ALL,
+	OPT_DEPRECATED_PAGE,
+	OPT_TODO_REPORT,
+	OPT_TODO_FORMAT,
+	OPT_HEADER_INCLUDES,
+	OPT_REFERENCES,
+	OPT_SOURCE_LOCATION,
+	OPT_SOURCE_REVISION,
+	OPT_COLOPHON,
+	OPT_PACKAGE_VERSION,
+	OPT_VERIFY_REPRODUCIBLE,
+	OPT_NO_DATES,
+	OPT_DATE_FROM_HEADER,
+	OPT_LINT_DOCS,
+	OPT_NO_LINT,
+	OPT_LINT_LEVEL,
+	OPT_SPELL_CHECK,
+	OPT_SPELL_WORDS,
+	OPT_WRAP_WIDTH,
+	OPT_DIALECT,
+	OPT_STYLE,
+	OPT_NO_PAGER,
+	OPT_TEXT_PAGES,
+	OPT_ALL,
+	OPT_SYMBOL_DB,
+	OPT_WRITE_THREADS,
+};
+
+/* The long names are also the keys used in config files */
+static struct option long_options[] = {
+	{"ascii", no_argument, NULL, 'a'},
+	{"man", no_argument, NULL, 'm'},
+	{"params", no_argument, NULL, 'P'},
+	{"general", no_argument, NULL, 'g'},
+	{"quiet", no_argument, NULL, 'q'},
+	{"verbose", no_argument, NULL, 'v'},
+	{"header-copyright", no_argument, NULL, 'c'},
+	{"header-src-dir", required_argument, NULL, 'O'},
+	{"section", required_argument, NULL, 's'},
+	{"package", required_argument, NULL, 'p'},
+	{"header", required_argument, NULL, 'H'},
+	{"include", required_argument, NULL, 'I'},
+	{"include-prefix", required_argument, NULL, 'i'},
+	{"company", required_argument, NULL, 'C'},
+	{"date", required_argument, NULL, 'D'},
+	{"start-year", required_argument, NULL, 'S'},
+	{"year", required_argument, NULL, 'Y'},
+	{"output-dir", required_argument, NULL, 'o'},
+	{"output-archive", required_argument, NULL, OPT_OUTPUT_ARCHIVE},
+	{"man-dirs", no_argument, NULL, OPT_MAN_DIRS},
+	{"fhs-layout", no_argument, NULL, OPT_FHS_LAYOUT},
+	{"json-index", required_argument, NULL, OPT_JSON_INDEX},
+	{"todo-report", required_argument, NULL, OPT_TODO_REPORT},
+	{"todo-format", required_argument, NULL, OPT_TODO_FORMAT},
+	{"tags-file", required_argument, NULL, OPT_TAGS_FILE},
+	{"generate-tagfile", required_argument, NULL, OPT_GENERATE_TAGFILE},
+	{"tagfile", required_argument, NULL, OPT_TAGFILE},
+	{"xml-encoding", required_argument, NULL, OPT_XML_ENCODING},
+	{"emit-rules", required_argument, NULL, OPT_EMIT_RULES},
+	{"install-metadata", required_argument, NULL, OPT_INSTALL_METADATA},
+	{"page-hook", required_argument, NULL, OPT_PAGE_HOOK},
+	{"combine-by-prefix", required_argument, NULL, OPT_COMBINE_BY_PREFIX},
+	{"xml-dir", required_argument, NULL, 'd'},
+	{"jobs", required_argument, NULL, 'j'},
+	{"synopsis-width", required_argument, NULL, OPT_SYNOPSIS_WIDTH},
+	{"max-param-type", required_argument, NULL, OPT_MAX_PARAM_TYPE},
+	{"example-macros", no_argument, NULL, OPT_EXAMPLE_MACROS},
+	{"header-includes", no_argument, NULL, OPT_HEADER_INCLUDES},
+	{"references", no_argument, NULL, OPT_REFERENCES},
+	{"source-location", no_argument, NULL, OPT_SOURCE_LOCATION},
+	{"config", required_argument, NULL, OPT_CONFIG},
+	{"bugs-url", required_argument, NULL, OPT_BUGS_URL},
+	{"bugs-email", required_argument, NULL, OPT_BUGS_EMAIL},
+	{"license-file", required_argument, NULL, OPT_LICENSE_FILE},
+	{"require-spdx", no_argument, NULL, OPT_REQUIRE_SPDX},
+	{"examples-dir", required_argument, NULL, OPT_EXAMPLES_DIR},
+	{"struct-pages", no_argument, NULL, OPT_STRUCT_PAGES},
+	{"enum-pages", no_argument, NULL, OPT_ENUM_PAGES},
+	{"constants-page", no_argument, NULL, OPT_CONSTANTS_PAGE},
+	{"constants-all", no_argument, NULL, OPT_CONSTANTS_ALL},
+	{"force", no_argument, NULL, OPT_FORCE},
+	{"no-clobber", no_argument, NULL, OPT_NO_CLOBBER},
+	{"backup", no_argument, NULL, OPT_BACKUP},
+	{"old-manifest", required_argument, NULL, OPT_OLD_MANIFEST},
+	{"alias-stubs", no_argument, NULL, OPT_ALIAS_STUBS},
+	{"alias-links", required_argument, NULL, OPT_ALIAS_LINKS},
+	{"clean", no_argument, NULL, OPT_CLEAN},
+	{"include-symbols", required_argument, NULL, OPT_INCLUDE_SYMBOLS},
+	{"exclude-symbols", required_argument, NULL, OPT_EXCLUDE_SYMBOLS},
+	{"symbol-prefix", required_argument, NULL, OPT_SYMBOL_PREFIX},
+	{"exclude-static", no_argument, NULL, OPT_EXCLUDE_STATIC},
+	{"exclude-inline", no_argument, NULL, OPT_EXCLUDE_INLINE},
+	{"exported-symbols", required_argument, NULL, OPT_EXPORTED_SYMBOLS},
+	{"symbol-info", required_argument, NULL, OPT_SYMBOL_INFO},
+	{"sort", required_argument, NULL, OPT_SORT},
+	{"see-also-group", no_argument, NULL, OPT_SEE_ALSO_GROUP},
+	{"see-also", required_argument, NULL, OPT_SEE_ALSO},
+	{"title-case", required_argument, NULL, OPT_TITLE_CASE},
+	{"th-source", required_argument, NULL, OPT_TH_SOURCE},
+	{"package-version", required_argument, NULL, OPT_PACKAGE_VERSION},
+	{"no-dates", no_argument, NULL, OPT_NO_DATES},
+	{"date-from-header", no_argument, NULL, OPT_DATE_FROM_HEADER},
+	{"th-manual", required_argument, NULL, OPT_TH_MANUAL},
+	{"generated-comment", required_argument, NULL, OPT_GENERATED_COMMENT},
+	{"no-generated-comment", no_argument, NULL, OPT_NO_GENERATED_COMMENT},
+	{"source-revision", required_argument, NULL, OPT_SOURCE_REVISION},
+	{"colophon", no_argument, NULL, OPT_COLOPHON},
+	{"list-symbols", no_argument, NULL, OPT_LIST_SYMBOLS},
+	{"keep-going", no_argument, NULL, OPT_KEEP_GOING},
+	{"manifest", required_argument, NULL, OPT_MANIFEST},
+	{"whatis-file", required_argument, NULL, OPT_WHATIS_FILE},
+	{"index-page", required_argument, NULL, OPT_INDEX_PAGE},
+	{"deprecated-page", required_argument, NULL, OPT_DEPRECATED_PAGE},
+	{"emit-install-rules", required_argument, NULL, OPT_EMIT_INSTALL_RULES},
+	{"lint-output", no_argument, NULL, OPT_LINT_OUTPUT},
+	{"strict", no_argument, NULL, OPT_STRICT},
+	{"lint-docs", no_argument, NULL, OPT_LINT_DOCS},
+	{"no-lint", required_argument, NULL, OPT_NO_LINT},
+	{"lint-level", required_argument, NULL, OPT_LINT_LEVEL},
+	{"spell-check", required_argument, NULL, OPT_SPELL_CHECK},
+	{"spell-words", required_argument, NULL, OPT_SPELL_WORDS},
+	{"wrap-width", required_argument, NULL, OPT_WRAP_WIDTH},
+	{"dialect", required_argument, NULL, OPT_DIALECT},
+	{"style", required_argument, NULL, OPT_STYLE},
+	{"no-pager", no_argument, NULL, OPT_NO_PAGER},
+	{"text-pages", no_argument, NULL, OPT_TEXT_PAGES},
+	{"all", no_argument, NULL, OPT_ALL},
+	{"symbol-db", required_argument, NULL, OPT_SYMBOL_DB},
+	{"write-threads", required_argument, NULL, OPT_WRITE_THREADS},
+	{"preview", required_argument, NULL, OPT_PREVIEW},
+	{"selftest", no_argument, NULL, OPT_SELFTEST},
+	{"verify-reproducible", no_argument, NULL, OPT_VERIFY_REPRODUCIBLE},
+	{"help", no_argument, NULL, 'h'},
+	{NULL, 0, NULL, 0}
+};
+
+/*
+ * Things that can go wrong while generating pages, in order of badness.
+ * The worst one seen decides whether the run has failed.
+ */
+enum d2m_error {
+	D2M_OK = 0,
+	D2M_ERR_DOC,	/* Something missing from the doxygen comments, the page still gets written */
+	D2M_ERR_LINT,	/* A generated page failed a check */
+	D2M_ERR_PARSE,	/* An XML file could not be read */
+	D2M_ERR_IO,	/* An output file could not be written */
+};
+
+/*
+ * Exit codes, so that build systems can tell what happened. The numbers
+ * aren't in order of how bad things are, see exit_code_severity().
+ */
+#define EXIT_USAGE	1	/* Bad options, nothing done */
+#define EXIT_LINT	2
+#define EXIT_PARSE	3
+#define EXIT_IO		4
+#define EXIT_PARTIAL	5	/* --keep-going hit errors but still wrote some pages */
+
+/* Progress messages, -q turns them all off and each -v adds a level */
+enum log_level {
+	LOG_QUIET = 0,
+	LOG_INFO,	/* What pages are being written */
+	LOG_VERBOSE,	/* -v, why things are (or aren't) happening */
+	LOG_DEBUG,	/* -vv, the XML we're looking at */
+};
+
+enum install_rules {
+	RULES_NONE = 0,
+	RULES_MAKE,
+	RULES_MESON,
+	RULES_CMAKE,
+};
+
+/* What an alias for a page is, --alias-links */
+enum alias_link {
+	ALIAS_SO = 0,
+	ALIAS_SYMLINK,
+	ALIAS_HARDLINK,
+};
+
+/* --todo-format */
+enum todo_format {
+	TODO_TEXT = 0,
+	TODO_JSON,
+};
+
+/* The checks on the doxygen comments, see --lint-docs and --lint-level */
+enum lint_check {
+	LINT_UNDOCUMENTED = 0,	/* No detailed description */
+	LINT_BRIEF,		/* No brief description */
+	LINT_PARAMS,		/* \param names against the parameters */
+	LINT_RETURN,		/* A return value documented for non-void functions, and only them */
+	LINT_DEPRECATED,	/* @deprecated without saying what to use instead */
+	LINT_SPELLING,		/* Words --spell-check doesn't know */
+	LINT_NUM_CHECKS,
+};
+
+/* --lint-level, LINT_DEFAULT is whatever --lint-docs says */
+enum lint_level {
+	LINT_DEFAULT = 0,
+	LINT_OFF,
+	LINT_WARN,
+	LINT_ERROR,
+};
+
+/* The order of pages and of SEE ALSO lists */
+enum sort_order {
+	SORT_SOURCE = 0,
+	SORT_ALPHA,
+	SORT_GROUP,
+};
+
+/* --dialect, the macro package the pages are written in */
+enum dialect_id {
+	DIALECT_MAN = 0,	/* The classic man(7) macros, that every formatter knows */
+	DIALECT_GROFF,		/* man(7) with the groff extensions: .SY/.YS and .EX/.EE */
+};
+
+/*
+ * The macros that differ between the dialects. The text in between uses
+ * \f font escapes and a few plain roff requests (.nh, .ad, .sp, .br),
+ * which both of them understand.
+ */
+struct output_dialect {
+	enum dialect_id id;
+	const char *name;
+	const char *section;		/* .SH */
+	const char *subsection;		/* .SS */
+	const char *paragraph;		/* .PP */
+	const char *nofill_start;	/* Declarations, laid out as they are */
+	const char *nofill_end;
+	const char *code_start;		/* @code and @verbatim blocks */
+	const char *code_end;
+	const char *bullet_item;	/* An item of a bulleted list */
+	const char *item_paragraph;	/* A further paragraph in a list item */
+	const char *bold;		/* Macros that set their arguments in a font */
+	const char *italic;
+	const char *xref;		/* A reference to another page */
+};
+
+/* --style, the kinds of text that can be given a font of their own */
+enum style_class {
+	STYLE_TYPE = 0,		/* Parameter and return types in the SYNOPSIS */
+	STYLE_FUNCTION,		/* The function's name in the SYNOPSIS */
+	STYLE_PARAM,		/* Parameter names, in the SYNOPSIS and where \p or \c name one */
+	STYLE_CODE,		/* Any other \c and \p */
+	STYLE_EMPHASIS,		/* \a, \e and \em */
+	STYLE_REF,		/* References to other functions in the text */
+	STYLE_TITLE,		/* \par titles */
+	STYLE_NUM_CLASSES,
+};
+
+static const struct output_dialect dialects[] = {
+	[DIALECT_MAN] = {
+		DIALECT_MAN, "man", ".SH", ".SS", ".PP", ".nf", ".fi", ".nf", ".fi",
+		".IP \\(bu 2", ".IP", "B", "I", "BR",
+	},
+	[DIALECT_GROFF] = {
+		DIALECT_GROFF, "groff", ".SH", ".SS", ".PP", ".nf", ".fi", ".EX", ".EE",
+		".IP \\(bu 2", ".IP", "B", "I", "BR",
+	},
+};
+
 static int print_ascii = 1;
 static int print_man = 0;
 static int print_params = 0;
 static int print_general = 0;
 static int num_functions = 0;
-static int quiet = 0;
+static int verbosity = LOG_INFO;
 static int use_header_copyright = 0;
+static int selftest = 0;
+static int verify_reproducible = 0;
+/* The second --verify-reproducible run uses a different size, so hashtables come out in another order */
+static int hashtable_size = 10;
+static int xml_dir_set = 0;
+static int all_headers_wanted = 0; /* --all */
+static int force = 0;
+static int no_clobber = 0;
+static int backup = 0;
+static int alias_stubs = 0;
+static enum alias_link alias_links = ALIAS_SO;
+static int clean = 0;
+static int list_symbols = 0;
+static int keep_going = 0;
+static int lint_output = 0;
+static int lint_docs = 0;
+static enum lint_level lint_levels[LINT_NUM_CHECKS];
+static const char *lint_check_names[LINT_NUM_CHECKS] = {"undocumented", "brief", "params", "return", "deprecated",
+							 "spelling"};
+static const char *spell_command = NULL;
+static const char *spell_words_file = NULL;
+static qb_map_t *spell_words;
+static int lint_strict = 0;
+static enum install_rules install_rules = RULES_NONE;
+static enum install_rules build_rules = RULES_NONE; /* --emit-rules */
+static enum sort_order sort_order = SORT_SOURCE;
+static int see_also_group = 0;
+static unsigned int pages_written = 0;
+static int num_jobs = 1;
+static int write_threads = 0; /* 0 writes each page as it is generated */
+static int synopsis_width = LINE_LENGTH;
+static int max_param_type = 0; /* 0 means synopsis_width */
+static int wrap_width = 0; /* --wrap-width, 0 leaves text lines alone */
+static int term_width = 80; /* of -a output */
+static int term_fonts = 0; /* -a output is going to a terminal */
+static int no_pager = 0;
+static int text_pages = 0; /* -a writes <name>.txt files rather than to stdout */
+static const struct output_dialect *dialect = &dialects[DIALECT_MAN];
+static const char *style_class_names[STYLE_NUM_CLASSES] = {"type", "function", "param", "code", "emphasis",
+							   "ref", "title"};
+static const char *styles[STYLE_NUM_CLASSES] = {"\\fB", "\\fB", "\\fI", "\\fB", "\\fB", "\\fI", "\\fB"};
+static int example_macros = 0; /* .EX/.EE rather than .nf/.fi for code */
+static int header_includes = 0;
+static int references = 0;
+static int source_location = 0;
+static int job_slot = 0;
+
+/* What each -j worker did with its share of a file's pages */
+struct job_result {
+	unsigned int pages_written;
+	enum d2m_error worst_error;
+	unsigned int page_counter;
+	int finished;
+};
+static struct job_result *job_results; /* shared with the workers */
+static unsigned int page_counter = 0;
 static const char *man_section="3";
 static const char *package_name="Package";
 static const char *header="Programmer's Manual";
+static const char *th_source = NULL;
+static const char *package_version = NULL;
+static const char *th_manual = NULL;
+static const char *generated_comment = NULL;
+static int no_generated_comment = 0;
+static int title_asis = 0;
 static const char *company="Red Hat";
 static const char *output_dir="./";
 static const char *xml_dir = "./xml/";
-static const char *xml_file;
 static const char *manpage_date = NULL;
 static const char *headerfile = NULL;
+static const char *user_headerfile = NULL;
 static const char *header_prefix = "";
 static const char *header_src_dir = "./";
-static char header_copyright[256] = "\0";
+static const char *bugs_url = NULL;
+static const char *bugs_email = NULL;
+static const char *license_file = NULL;
+static char *license_text = NULL;
+static const char *source_revision = NULL;
+static char git_revision[256];
+static int colophon = 0;
+static int require_spdx = 0;
+static const char *examples_dir = NULL;
+static int struct_pages = 0;
+static int enum_pages = 0;
+static int constants_page = 0;
+static int constants_all = 0;
+static const char *old_manifest = NULL;
+static const char *manifest_file = NULL;
+static const char *whatis_file = NULL;
+static const char *install_metadata = NULL;
+static const char *page_hook = NULL;
+static const char *json_index_file = NULL;
+static const char *todo_report = NULL;
+static enum todo_format todo_format = TODO_TEXT;
+static const char *tags_file = NULL;
+static const char *generate_tagfile = NULL;
+static const char *symbol_db_file = NULL;
+static const char *xml_encoding = NULL;	/* NULL uses the XML declaration */
+static const char *index_page = NULL;
+static const char *deprecated_page = NULL;
+static qb_map_t *deprecated_map;
+static const char *output_archive = NULL;
+static int man_dirs = 0;
+static int compress = 0;
+static const char *page_suffix = "";	/* for the file names in lists of pages */
+static char archive_dir[PATH_MAX];
+static char man_dir_path[PATH_MAX * 2];
+static const char *man_base_dir = NULL;	/* --man-dirs, where the man<section> directories are */
+static const char *preview_name = NULL;
+static const char *include_symbols = NULL;
+static const char *exclude_symbols = NULL;
+static regex_t include_symbols_re;
+static regex_t exclude_symbols_re;
+static const char *symbol_prefix = NULL;
+static const char *combine_prefix = NULL;
+static int exclude_static = 0;
+static int exclude_inline = 0;
+static const char *exported_symbols_file = NULL;
+static const char *symbol_info_file = NULL;
+static const char *current_xml_file = NULL;
+static char source_file[PATH_MAX];	/* where the symbol whose page is being written is declared */
+static int source_line;
+static enum d2m_error worst_error = D2M_OK;
+static char header_copyright[4096] = "\0";
+static char header_spdx[256] = "\0";
+static int date_from_header = 0;
+static char header_date[64] = "\0";
 static long manpage_year = LONG_MIN;
 static long start_year = 2010;
+static int no_dates = 0;
+static uint64_t options_hash;
+static uint64_t xml_file_hash;
 static struct qb_list_head params_list;
 static struct qb_list_head retval_list;
+static struct qb_list_head combined_list;
+static struct qb_list_head examples_list;
+static struct qb_list_head defines_list;
+static struct qb_list_head includes_list;
+static struct qb_list_head includedby_list;
+static QB_LIST_DECLARE(uses_list);
+static QB_LIST_DECLARE(used_by_list);
+static QB_LIST_DECLARE(extra_see_also_list);
+static QB_LIST_DECLARE(exported_symbols_list);
+static QB_LIST_DECLARE(todo_list);
+static QB_LIST_DECLARE(spell_list);
+static qb_map_t *exported_symbols_map;
+static qb_map_t *symbol_info_map;
+static qb_map_t *external_symbols;
+static qb_map_t *symbol_db;
+static qb_map_t *db_see_also;	/* the pages of other headers' symbols that this page refers to */
 static qb_map_t *function_map;
+static qb_map_t *member_groups;
+static qb_map_t *header_functions;	/* --references, refid to name */
 static qb_map_t *structures_map;
 static qb_map_t *used_structures_map;
+static qb_map_t *all_pages;
+static qb_map_t *all_headers;
+static qb_map_t *index_symbols;
+static char *see_also_functions;
+static size_t see_also_len;
+static struct function_info **see_also_order;
+static size_t see_also_count;
+static size_t see_also_names;
 
 struct param_info {
 	char *paramname;
 	char *paramtype;
 	char *paramdesc;
+	char *paramvalue; /* an enumerator's initializer, without the '=' */
 	struct param_info *next;
 	struct qb_list_head list;
 };
 
+/*
+ * Where each function appears in the SEE ALSO text so that it can leave
+ * itself out of its own page
+ */
+struct function_info {
+	char *name;
+	char *group;
+	xmlNode *node;
+	unsigned int seq;
+	size_t see_also_start;
+	size_t see_also_end;
+	struct function_info *combined_with;	/* --combine-by-prefix, whose page it's on */
+};
+
+/* --combine-by-prefix, another function on the page being printed */
+struct combined_function {
+	char *name;
+	char *def;
+	char *brief;
+	char *detailed;
+	char *returntext;
+	struct qb_list_head notes;
+	struct qb_list_head params;
+	struct qb_list_head retvals;
+	struct qb_list_head list;
+};
+
+/* Every page the run writes, keyed by file name */
+struct page_info {
+	char *header;
+	const char *kind;
+	char *brief;		/* for --whatis-file */
+	char *alias_of;		/* the page a --combine-by-prefix alias points at */
+};
+
+/* A documented symbol for --json-index, --tags-file and --generate-tagfile, keyed by name */
+struct index_symbol {
+	const char *kind;
+	char *header;
+	char *brief;
+	char *signature;
+	char *arglist;		/* functions only, the end of the signature */
+	char *page;		/* NULL if it's not on any page */
+	char *file;		/* where it's declared, from <location> */
+	int line;
+};
+
+/* A @note, a function can have more than one */
+struct note_info {
+	char *text;
+	struct qb_list_head list;
+};
+
+struct example_info {
+	char *pagename;
+	char *brief;
+	struct qb_list_head list;
+};
+
+/* A #define for --constants-page */
+struct define_info {
+	char *name;	/* with the arguments of a function-like macro */
+	char *value;
+	char *desc;
+	size_t prefix_len;
+	int printed;
+	struct qb_list_head list;
+};
+
+/* --header-includes, one of the project's headers that the header includes or is included by */
+struct include_info {
+	char *name;	/* as doxygen has it, eg qb/qbdefs.h */
+	int local;	/* #include "name" rather than <name> */
+	struct qb_list_head list;
+};
+
+/* --references, a documented function that a function calls or is called by */
+struct reference_info {
+	char *name;
+	struct qb_list_head list;
+};
+
+/* --see-also, page is NULL for entries that go on every page */
+struct extra_see_also {
+	char *page;
+	char *name;
+	char *section;
+	struct qb_list_head list;
+};
+
+/* From --exported-symbols, in the order they are in the file */
+struct exported_symbol {
+	char *name;		/* can be a wildcard in a version script */
+	char *version;		/* version node, NULL for a plain list */
+	int documented;
+	struct qb_list_head list;
+};
+
+/* A symbol in another project, from a --tagfile */
+struct external_symbol {
+	char *file;		/* the page (or HTML file) it's documented in */
+	char *anchor;
+	char *location;		/* where that project's documentation is, can be NULL */
+};
+
+/* A symbol from the --symbol-db, documented by this run or an earlier one */
+struct db_symbol {
+	char *kind;
+	char *header;
+	char *page;		/* eg qb_log_init.3, NULL if it's not on a page */
+};
+
+/* From --symbol-info */
+struct symbol_info {
+	char *version;
+	char *status;
+};
+
+/* --deprecated-page, a deprecated symbol from any of the headers */
+struct deprecated_info {
+	char *header;
+	char *page;		/* the page it's on, with the section, or NULL */
+	char *text;		/* the @deprecated text, ready for the page */
+	char *replacement;
+	char *version;
+};
+
+/* --todo-report, a @todo or @bug from any of the headers, in the order they were read */
+struct todo_item {
+	char *kind;		/* todo or bug */
+	char *symbol;
+	char *header;
+	char *file;
+	int line;
+	char *text;
+	struct qb_list_head list;
+};
+
+/* --spell-check, the words in a description and the symbol it's for */
+struct spell_text {
+	xmlNode *node;
+	char *symbol;
+	char *text;
+	struct qb_list_head list;
+};
+
 struct struct_info {
 	enum {STRUCTINFO_STRUCT, STRUCTINFO_ENUM} kind;
 	char *structname;
@@ -87,18 +729,170 @@ struct struct_info {
 	struct qb_list_head list;
 };
 
-static cstring_t get_texttree(int *type, xmlNode *cur_node, char **returntext, char **notetext);
+static cstring_t get_texttree(int *type, xmlNode *cur_node, char **returntext, struct qb_list_head *notes);
 static void traverse_node(xmlNode *parentnode, const char *leafname, void (do_members(xmlNode*, void*)), void *arg);
-static cstring_t get_text(xmlNode *cur_node, char **returntext, char **notetext);
+static cstring_t get_text(xmlNode *cur_node, char **returntext, struct qb_list_head *notes);
+static xmlNode *find_xrefsect(xmlNode *node, const char *kind);
+static void parse_deprecation(const char *text, char **replacement, char **version);
+
+/*
+ * All errors come through here so that they say which file (and symbol, if
+ * there is one) they are about, and so we know how the run went at the end.
+ */
+static void report_error(enum d2m_error err, const char *file, const char *symbol, const char *fmt, ...)
+	__attribute__((format(printf, 4, 5)));
+
+static void report_error(enum d2m_error err, const char *file, const char *symbol, const char *fmt, ...)
+{
+	va_list ap;
+
+	if (err > worst_error) {
+		worst_error = err;
+	}
+
+	fprintf(stderr, "%s: ", err == D2M_ERR_DOC ? "Warning" : "Error");
+	if (file) {
+		fprintf(stderr, "%s: ", file);
+	}
+	if (symbol) {
+		fprintf(stderr, "%s: ", symbol);
+	}
+	va_start(ap, fmt);
+	vfprintf(stderr, fmt, ap);
+	va_end(ap);
+	fprintf(stderr, "\n");
+}
+
+/*
+ * Normal progress goes to stdout as it always has, the chattier levels
+ * go to stderr so they don't get mixed up with --list-symbols output
+ */
+static void log_msg(enum log_level level, const char *fmt, ...)
+	__attribute__((format(printf, 2, 3)));
+
+static void log_msg(enum log_level level, const char *fmt, ...)
+{
+	va_list ap;
+
+	if (level > verbosity) {
+		return;
+	}
+	va_start(ap, fmt);
+	vfprintf(level > LOG_INFO ? stderr : stdout, fmt, ap);
+	va_end(ap);
+}
 
 static void free_paraminfo(struct param_info *pi)
 {
 	free(pi->paramname);
 	free(pi->paramtype);
 	free(pi->paramdesc);
+	free(pi->paramvalue);
 	free(pi);
 }
 
+static void free_struct_info(struct struct_info *si)
+{
+	struct qb_list_head *iter;
+	struct qb_list_head *tmp;
+	struct param_info *pi;
+
+	qb_list_for_each_safe(iter, tmp, &si->params_list) {
+		pi = qb_list_entry(iter, struct param_info, list);
+		qb_list_del(&pi->list);
+		free_paraminfo(pi);
+	}
+	free(si->structname);
+	free(si->description);
+	free(si->brief_description);
+	free(si);
+}
+
+/* QB_MAP_NOTIFY_FREE callbacks, so the maps own their keys and values */
+static void map_free_entry(uint32_t event, char *key, void *old_value, void *value, void *user_data)
+{
+	free(key);
+	free(old_value);
+}
+
+/* Ordered, so that the STRUCTURES section comes out the same every time */
+static qb_map_t *new_used_structures_map(void)
+{
+	qb_map_t *map = qb_skiplist_create();
+
+	qb_map_notify_add(map, NULL, map_free_entry, QB_MAP_NOTIFY_FREE, NULL);
+	return map;
+}
+
+static void map_free_struct(uint32_t event, char *key, void *old_value, void *value, void *user_data)
+{
+	free(key);
+	free_struct_info(old_value);
+}
+
+static void map_free_function(uint32_t event, char *key, void *old_value, void *value, void *user_data)
+{
+	struct function_info *fi = old_value;
+
+	/* key is fi->name */
+	free(fi->name);
+	free(fi->group);
+	free(fi);
+}
+
+static void map_free_page(uint32_t event, char *key, void *old_value, void *value, void *user_data)
+{
+	struct page_info *pi = old_value;
+
+	free(key);
+	if (pi) {
+		free(pi->header);
+		free(pi->brief);
+		free(pi->alias_of);
+		free(pi);
+	}
+}
+
+static void map_free_index_symbol(uint32_t event, char *key, void *old_value, void *value, void *user_data)
+{
+	struct index_symbol *is = old_value;
+
+	free(key);
+	if (is) {
+		free(is->header);
+		free(is->brief);
+		free(is->signature);
+		free(is->arglist);
+		free(is->page);
+		free(is->file);
+		free(is);
+	}
+}
+
+static void map_free_external_symbol(uint32_t event, char *key, void *old_value, void *value, void *user_data)
+{
+	struct external_symbol *es = old_value;
+
+	free(key);
+	free(es->file);
+	free(es->anchor);
+	free(es->location);
+	free(es);
+}
+
+static void map_free_db_symbol(uint32_t event, char *key, void *old_value, void *value, void *user_data)
+{
+	struct db_symbol *ds = old_value;
+
+	free(key);
+	if (ds) {
+		free(ds->kind);
+		free(ds->header);
+		free(ds->page);
+		free(ds);
+	}
+}
+
 static char *get_attr(xmlNode *node, const char *tag)
 {
 	xmlAttr *this_attr;
@@ -121,10 +915,12 @@ static cstring_t get_child(xmlNode *node, const char *tag)
 
 	for (this_node = node->children; this_node; this_node = this_node->next) {
 		if ((strcmp( (char*)this_node->name, "declname") == 0)) {
+			free(declname);
 			declname = strdup((char*)this_node->children->content);
 		}
 
 		if ((this_node->type == XML_ELEMENT_NODE && this_node->children) && ((strcmp((char *)this_node->name, tag) == 0))) {
+			free(refid);
 			refid = NULL;
 			for (child = this_node->children; child; child = child->next) {
 				if (child->content) {
@@ -135,14 +931,19 @@ static cstring_t get_child(xmlNode *node, const char *tag)
 					if (child->children->content) {
 						buffer = cstring_append_chars(buffer, (char *)child->children->content);
 					}
+					free(refid);
 					refid = get_attr(child, "refid");
 				}
 			}
 		}
 		if (declname && refid) {
+			/* The map owns them now */
 			qb_map_put(used_structures_map, refid, declname);
+			declname = refid = NULL;
 		}
 	}
+	free(declname);
+	free(refid);
 	return buffer;
 }
 
@@ -160,6 +961,140 @@ static struct param_info *find_param_by_name(struct qb_list_head *list, const ch
 	return NULL;
 }
 
+/*
+ * Return the number of columns a string will take up on the page.
+ * Roff escapes that don't print (font changes etc) are ignored and
+ * UTF-8 characters are counted by their display width rather than
+ * the number of bytes they need.
+ * If visible_end is not NULL it is set to the offset just after the last
+ * printing character, so that trailing markup can be skipped over.
+ */
+static int markup_width(const char *string, size_t *visible_end)
+{
+	const unsigned char *c = (const unsigned char *)string;
+	int width = 0;
+	int last_width = 0;
+
+	if (visible_end) {
+		*visible_end = 0;
+	}
+
+	while (*c) {
+		if (visible_end && width != last_width) {
+			*visible_end = (const char *)c - string;
+			last_width = width;
+		}
+
+		if (*c == '\\') {
+			c++;
+			switch (*c) {
+			case '\0':
+				continue;
+			case 'f':
+			case '*':
+				/* Font change or string interpolation: \fB, \f(CW, \f[CW] */
+				c++;
+				if (*c == '(') {
+					c += (c[1] && c[2]) ? 3 : strlen((char *)c);
+				} else if (*c == '[') {
+					while (*c && *c != ']') {
+						c++;
+					}
+					if (*c) {
+						c++;
+					}
+				} else if (*c) {
+					c++;
+				}
+				break;
+			case '(':
+				/* Two character special: \(em */
+				width++;
+				c += (c[1] && c[2]) ? 3 : strlen((char *)c);
+				break;
+			case '[':
+				/* Named special: \[char46] */
+				width++;
+				while (*c && *c != ']') {
+					c++;
+				}
+				if (*c) {
+					c++;
+				}
+				break;
+			case 's':
+				/* Size change: \s0, \s+2, \s-2 */
+				c++;
+				if (*c == '+' || *c == '-') {
+					c++;
+				}
+				while (isdigit(*c)) {
+					c++;
+				}
+				break;
+			case '&':
+			case 'c':
+			case ':':
+			case '%':
+			case ')':
+			case '|':
+			case '^':
+				/* Zero-width */
+				c++;
+				break;
+			default:
+				/* \\, \e, \-, \~ and friends print one character */
+				width++;
+				c++;
+				break;
+			}
+			continue;
+		}
+
+		if (*c < 0x80) {
+			width++;
+			c++;
+		} else {
+			wchar_t wc;
+			mbstate_t state;
+			size_t len;
+			int w;
+
+			memset(&state, 0, sizeof(state));
+			len = mbrtowc(&wc, (const char *)c, MB_CUR_MAX, &state);
+			if (len == (size_t)-1 || len == (size_t)-2 || len == 0) {
+				/* Not valid in this locale, assume one column per byte */
+				width++;
+				c++;
+				continue;
+			}
+			w = wcwidth(wc);
+			width += (w < 0) ? 1 : w;
+			c += len;
+		}
+	}
+	if (visible_end && width != last_width) {
+		*visible_end = (const char *)c - string;
+	}
+	return width;
+}
+
+static int display_width(const char *string)
+{
+	return markup_width(string, NULL);
+}
+
+/* Print a string left-aligned in a field of 'width' display columns */
+static void print_padded(FILE *f, const char *string, int width)
+{
+	int pad = width - display_width(string);
+
+	fputs(string, f);
+	while (pad-- > 0) {
+		fputc(' ', f);
+	}
+}
+
 static int not_all_whitespace(char *string)
 {
 	unsigned int i;
@@ -174,6 +1109,79 @@ static int not_all_whitespace(char *string)
 	return 0;
 }
 
+static cstring_t append_chars_len(cstring_t buffer, const char *text, size_t len)
+{
+	char *chunk = strndup(text, len);
+
+	if (chunk) {
+		buffer = cstring_append_chars(buffer, chunk);
+		free(chunk);
+	}
+	return buffer;
+}
+
+/*
+ * Append some of the documentation's text. A backslash is written as \e,
+ * and a . or ' at the start of a line gets a \& in front of it, so that
+ * nroff doesn't read it as an escape or a request.
+ *
+ * groff is free to typeset a plain - as a hyphen, which is wrong for
+ * option names, negative numbers and ranges, and breaks copy and paste of
+ * things like -EINVAL. Use \- when the - starts a word that carries on
+ * with a letter or digit (-1, -EAGAIN, --flag) or sits between two digits
+ * (1-5), and leave the real hyphens in words like thread-safe alone.
+ */
+static cstring_t append_man_text(cstring_t buffer, const char *text)
+{
+	const char *c;
+	const char *start;
+	char last;
+	int minus;
+
+	if (!print_man) {
+		return cstring_append_chars(buffer, text);
+	}
+
+	last = cstring_last_char(buffer);
+	for (c = start = text; *c; c++) {
+		if (*c == '\\') {
+			buffer = append_chars_len(buffer, start, c - start);
+			buffer = cstring_append_chars(buffer, "\\e");
+			start = c + 1;
+			continue;
+		}
+		if ((*c == '.' || *c == '\'') && (c == text ? last == '\0' || last == '\n' : c[-1] == '\n')) {
+			buffer = append_chars_len(buffer, start, c - start);
+			buffer = cstring_append_chars(buffer, "\\&");
+			start = c;
+			continue;
+		}
+		if (*c != '-') {
+			continue;
+		}
+		if (c == text || strchr(" \t\n([=,|/\"'-", c[-1])) {
+			minus = isalnum((unsigned char)c[1]) || c[1] == '-';
+		} else {
+			minus = isdigit((unsigned char)c[-1]) && isdigit((unsigned char)c[1]);
+		}
+		if (minus) {
+			buffer = append_chars_len(buffer, start, c - start);
+			buffer = cstring_append_chars(buffer, "\\-");
+			start = c + 1;
+		}
+	}
+	return cstring_append_chars(buffer, start);
+}
+
+static char *man_text_dup(const char *text)
+{
+	cstring_t tmp = append_man_text(cstring_alloc(), text);
+	char *ret = cstring_to_chars(tmp);
+
+	cstring_free(tmp);
+	return ret;
+}
+
 static void get_param_info(xmlNode *cur_node, struct qb_list_head *list)
 {
 	xmlNode *this_tag;
@@ -187,29 +1195,67 @@ static void get_param_info(xmlNode *cur_node, struct qb_list_head *list)
 		for (sub_tag = this_tag->children; sub_tag; sub_tag = sub_tag->next) {
 			if (sub_tag->type == XML_ELEMENT_NODE && strcmp((char *)sub_tag->name, "parameternamelist") == 0 &&
 				sub_tag->children->next->children) {
-				paramname = (char*)sub_tag->children->next->children->content;
+				free(paramname);
+				paramname = man_text_dup((char*)sub_tag->children->next->children->content);
 			}
 			if (sub_tag->type == XML_ELEMENT_NODE && strcmp((char *)sub_tag->name, "parameterdescription") == 0 &&
 			    paramname && sub_tag->children->next->children) {
-				paramdesc = (char*)sub_tag->children->next->children->content;
+				paramdesc = man_text_dup((char*)sub_tag->children->next->children->content);
 
 				/* Add text to the param_map */
 				pi = find_param_by_name(list, paramname);
 				if (pi) {
+					free(pi->paramdesc);
 					pi->paramdesc = paramdesc;
 				}
 				else {
 					pi = malloc(sizeof(struct param_info));
 					if (pi) {
-						pi->paramname = paramname;
+						pi->paramname = strdup(paramname);
 						pi->paramdesc = paramdesc;
 						pi->paramtype = NULL; /* it's a retval */
+						pi->paramvalue = NULL;
 						qb_list_add_tail(&pi->list, list);
+					} else {
+						free(paramdesc);
 					}
 				}
 			}
 		}
 	}
+	free(paramname);
+}
+
+/*
+ * Append a piece of a code listing as it is, apart from what nroff would
+ * otherwise eat: backslashes, and a . or ' that could start a request.
+ * Every - in code is a minus, never a hyphen.
+ */
+static cstring_t append_code_text(cstring_t buffer, const char *text)
+{
+	char chunk[2] = {0, 0};
+
+	if (!print_man) {
+		return cstring_append_chars(buffer, text);
+	}
+	if (text[0] == '.') {
+		buffer = cstring_append_chars(buffer, "\\[char46]");
+		text++;
+	} else if (text[0] == '\'') {
+		buffer = cstring_append_chars(buffer, "\\[aq]");
+		text++;
+	}
+	for (; *text; text++) {
+		if (*text == '\\') {
+			buffer = cstring_append_chars(buffer, "\\e");
+		} else if (*text == '-') {
+			buffer = cstring_append_chars(buffer, "\\-");
+		} else {
+			chunk[0] = *text;
+			buffer = cstring_append_chars(buffer, chunk);
+		}
+	}
+	return buffer;
 }
 
 static cstring_t get_codeline(xmlNode *this_tag)
@@ -222,14 +1268,7 @@ static cstring_t get_codeline(xmlNode *this_tag)
 			buffer = cstring_append_chars(buffer, " ");
 		}
 		if (strcmp((char*)sub_tag->name, "text") == 0) {
-			// If the line starts with a dot then escape the first one to
-			// stop nroff thinking it's a macro
-			char *tmp = (char*)sub_tag->content;
-			if (tmp[0] == '.') {
-				buffer = cstring_append_chars(buffer, (char*)"\\[char46]");
-				tmp += 1;
-			}
-			buffer = cstring_append_chars(buffer, tmp);
+			buffer = append_code_text(buffer, (char*)sub_tag->content);
 		}
 		if (strcmp((char*)sub_tag->name, "ref") == 0) {
 			// Handled by the child recusion below
@@ -243,14 +1282,33 @@ static cstring_t get_codeline(xmlNode *this_tag)
 	return buffer;
 }
 
+/* @code and @verbatim blocks, man uses .EX/.EE for them with --example-macros */
+static const char *code_start(void)
+{
+	return (example_macros && dialect->id == DIALECT_MAN) ? ".EX" : dialect->code_start;
+}
+
+static const char *code_end(void)
+{
+	return (example_macros && dialect->id == DIALECT_MAN) ? ".EE" : dialect->code_end;
+}
+
+/* The fonts and code blocks are marked up for -a too, print_term() shows them in the terminal */
+static int text_markup(void)
+{
+	return print_man || print_ascii;
+}
+
 static cstring_t get_codetree(xmlNode *cur_node)
 {
 	xmlNode *this_tag;
 	cstring_t buffer = cstring_alloc();
 	cstring_t tmp;
 
-	if (print_man) {
-		buffer = cstring_append_chars(buffer, "\n.nf\n");
+	if (text_markup()) {
+		buffer = cstring_append_chars(buffer, "\n");
+		buffer = cstring_append_chars(buffer, code_start());
+		buffer = cstring_append_chars(buffer, "\n");
 	}
 
 	for (this_tag = cur_node->children; this_tag; this_tag = this_tag->next) {
@@ -265,53 +1323,197 @@ static cstring_t get_codetree(xmlNode *cur_node)
 		}
 	}
 
-	if (print_man) {
-		buffer = cstring_append_chars(buffer, ".fi\n");
+	if (text_markup()) {
+		buffer = cstring_append_chars(buffer, code_end());
+		buffer = cstring_append_chars(buffer, "\n");
 	}
 
 	return buffer;
 }
 
-
-static cstring_t get_text(xmlNode *cur_node, char **returntext, char **notetext)
+/*
+ * @verbatim blocks are a single text node with the whitespace exactly as it
+ * was in the source, so go through them line by line rather than letting
+ * get_text() drop the blank lines. Tabs are expanded so the layout doesn't
+ * depend on nroff's tab stops.
+ */
+static cstring_t get_verbatim(xmlNode *cur_node)
 {
-	xmlNode *this_tag;
-	xmlNode *sub_tag;
-	char *kind;
 	cstring_t buffer = cstring_alloc();
+	cstring_t line = cstring_alloc();
+	const char *text;
+	char *tmp;
+	char chunk[2] = {0, 0};
+	int col = 0;
+
+	if (!cur_node->children || !cur_node->children->content) {
+		cstring_free(line);
+		return buffer;
+	}
+	text = (char *)cur_node->children->content;
 
-	for (this_tag = cur_node->children; this_tag; this_tag = this_tag->next) {
-		if (this_tag->type == XML_TEXT_NODE && strcmp((char *)this_tag->name, "text") == 0) {
-			if (not_all_whitespace((char*)this_tag->content)) {
-				buffer = cstring_append_chars(buffer, (char*)this_tag->content);
-			}
-		}
-		if (this_tag->type == XML_ELEMENT_NODE && strcmp((char *)this_tag->name, "emphasis") == 0) {
-			if (print_man) {
-				buffer = cstring_append_chars(buffer, "\\fB");
-			}
-			buffer = cstring_append_chars(buffer, (char*)this_tag->children->content);
-			if (print_man) {
-				buffer = cstring_append_chars(buffer, "\\fR");
+	/* doxygen leaves the newline after \verbatim in */
+	if (*text == '\n') {
+		text++;
+	}
+
+	if (text_markup()) {
+		buffer = cstring_append_chars(buffer, "\n");
+		buffer = cstring_append_chars(buffer, code_start());
+		buffer = cstring_append_chars(buffer, "\n");
+	}
+
+	for (; *text; text++) {
+		if (*text == '\n') {
+			tmp = cstring_to_chars(line);
+			buffer = append_code_text(buffer, tmp);
+			buffer = cstring_append_chars(buffer, "\n");
+			free(tmp);
+			cstring_free(line);
+			line = cstring_alloc();
+			col = 0;
+		} else if (*text == '\t') {
+			do {
+				line = cstring_append_chars(line, " ");
+			} while (++col % 8);
+		} else {
+			chunk[0] = *text;
+			line = cstring_append_chars(line, chunk);
+			col++;
+		}
+	}
+	if (col) {
+		tmp = cstring_to_chars(line);
+		buffer = append_code_text(buffer, tmp);
+		buffer = cstring_append_chars(buffer, "\n");
+		free(tmp);
+	}
+	cstring_free(line);
+
+	if (text_markup()) {
+		buffer = cstring_append_chars(buffer, code_end());
+		buffer = cstring_append_chars(buffer, "\n");
+	}
+
+	return buffer;
+}
+
+
+/*
+ * A <ref> to a symbol in another project, that doxygen knew about from its
+ * TAGFILES, is cited as a man page if the --tagfile says it's on one, or
+ * with its URL if the --tagfile has a location.
+ */
+static cstring_t append_external_ref(cstring_t buffer, const char *name, struct external_symbol *es)
+{
+	char text[PATH_MAX * 3];
+	const char *dot = strrchr(es->file, '.');
+	int page_len;
+
+	if (dot && isdigit(dot[1])) {
+		page_len = dot - es->file;
+		if ((int)strlen(name) == page_len && strncmp(name, es->file, page_len) == 0) {
+			snprintf(text, sizeof(text), text_markup() ? "\\fB%s\\fR(%s)" : "%s(%s)", name, dot + 1);
+		} else {
+			snprintf(text, sizeof(text), text_markup() ? "%s%s\\fR (\\fB%.*s\\fR(%s))" : "%s%s (%.*s(%s))",
+				 text_markup() ? styles[STYLE_REF] : "", name, page_len, es->file, dot + 1);
+		}
+	} else if (es->location) {
+		snprintf(text, sizeof(text), text_markup() ? "%s%s\\fR <%s/%s%s%s>" : "%s%s <%s/%s%s%s>",
+			 text_markup() ? styles[STYLE_REF] : "", name, es->location, es->file,
+			 es->anchor ? "#" : "", es->anchor ? es->anchor : "");
+	} else {
+		snprintf(text, sizeof(text), text_markup() ? "%s%s\\fR" : "%s%s", text_markup() ? styles[STYLE_REF] : "", name);
+	}
+	return cstring_append_chars(buffer, text);
+}
+
+static cstring_t append_ref(cstring_t buffer, xmlNode *ref)
+{
+	struct external_symbol *es = NULL;
+	const char *name = (char *)ref->children->content;
+	char *external;
+
+	if (external_symbols) {
+		external = get_attr(ref, "external");
+		if (external || !function_map || !qb_map_get(function_map, name)) {
+			es = qb_map_get(external_symbols, name);
+		}
+		free(external);
+	}
+	if (es) {
+		return append_external_ref(buffer, name, es);
+	}
+
+	/* A symbol in another header, that an earlier run put in the --symbol-db */
+	if (symbol_db && (!function_map || !qb_map_get(function_map, name))) {
+		struct db_symbol *ds = qb_map_get(symbol_db, name);
+
+		if (ds && ds->page && strcmp(ds->header, headerfile) != 0) {
+			struct external_symbol page_ref = {.file = ds->page};
+
+			if (!qb_map_get(db_see_also, ds->page)) {
+				qb_map_put(db_see_also, strdup(ds->page), strdup(ds->page));
 			}
+			return append_external_ref(buffer, name, &page_ref);
 		}
+	}
 
-		if (this_tag->type == XML_ELEMENT_NODE && strcmp((char *)this_tag->name, "ref") == 0) {
-			if (print_man) {
-				buffer = cstring_append_chars(buffer, "\\fI");
+	if (text_markup()) {
+		buffer = cstring_append_chars(buffer, styles[STYLE_REF]);
+	}
+	buffer = cstring_append_chars(buffer, name);
+	if (text_markup()) {
+		buffer = cstring_append_chars(buffer, "\\fR");
+	}
+	return buffer;
+}
+
+static cstring_t get_text(xmlNode *cur_node, char **returntext, struct qb_list_head *notes)
+{
+	xmlNode *this_tag;
+	xmlNode *sub_tag;
+	char *kind;
+	cstring_t buffer = cstring_alloc();
+
+	for (this_tag = cur_node->children; this_tag; this_tag = this_tag->next) {
+		if (this_tag->type == XML_TEXT_NODE && strcmp((char *)this_tag->name, "text") == 0) {
+			if (not_all_whitespace((char*)this_tag->content)) {
+				buffer = append_man_text(buffer, (char*)this_tag->content);
 			}
-			buffer = cstring_append_chars(buffer, (char*)this_tag->children->content);
-			if (print_man) {
+		}
+		if (this_tag->type == XML_ELEMENT_NODE && strcmp((char *)this_tag->name, "emphasis") == 0) {
+			if (text_markup()) {
+				buffer = cstring_append_chars(buffer, styles[STYLE_EMPHASIS]);
+			}
+			buffer = append_man_text(buffer, (char*)this_tag->children->content);
+			if (text_markup()) {
 				buffer = cstring_append_chars(buffer, "\\fR");
 			}
 		}
+
+		if (this_tag->type == XML_ELEMENT_NODE && strcmp((char *)this_tag->name, "ref") == 0) {
+			buffer = append_ref(buffer, this_tag);
+		}
+		/*
+		 * \c and \p both come out as computeroutput. If it's the name of one
+		 * of the function's parameters then it's italic, as in the SYNOPSIS,
+		 * otherwise it's code and goes in bold.
+		 */
 		if (this_tag->type == XML_ELEMENT_NODE && strcmp((char *)this_tag->name, "computeroutput") == 0) {
-			if (print_man) {
-				buffer = cstring_append_chars(buffer, "\\fB");
-			}
-			buffer = cstring_append_chars(buffer, (char*)this_tag->children->content);
-			if (print_man) {
-				buffer = cstring_append_chars(buffer, "\\fP");
+			xmlChar *content = xmlNodeGetContent(this_tag);
+			struct param_info *pi;
+
+			if (content) {
+				pi = find_param_by_name(&params_list, (char *)content);
+				if (text_markup()) {
+					buffer = cstring_append_chars(buffer, styles[(pi && pi->paramtype) ? STYLE_PARAM : STYLE_CODE]);
+				}
+				buffer = append_code_text(buffer, (char *)content);
+				if (text_markup()) {
+					buffer = cstring_append_chars(buffer, "\\fP");
+				}
+				xmlFree(content);
 			}
 		}
 
@@ -319,7 +1521,7 @@ static cstring_t get_text(xmlNode *cur_node, char **returntext, char **notetext)
 			for (sub_tag = this_tag->children; sub_tag; sub_tag = sub_tag->next) {
 				if (sub_tag->type == XML_ELEMENT_NODE && strcmp((char *)sub_tag->name, "listitem") == 0
 				    && sub_tag->children->children->content) {
-					buffer = cstring_append_chars(buffer, (char*)sub_tag->children->children->content);
+					buffer = append_man_text(buffer, (char*)sub_tag->children->children->content);
 					buffer = cstring_append_chars(buffer, "\n");
 				}
 			}
@@ -332,6 +1534,13 @@ static cstring_t get_text(xmlNode *cur_node, char **returntext, char **notetext)
 			cstring_free(tmp);
 		}
 
+		if (this_tag->type == XML_ELEMENT_NODE && strcmp((char *)this_tag->name, "verbatim") == 0) {
+			cstring_t tmp = get_verbatim(this_tag);
+			buffer = cstring_append_cstring(buffer, tmp);
+			buffer = cstring_append_chars(buffer, "\n");
+			cstring_free(tmp);
+		}
+
 		/* Look for subsections - return value & params */
 		if (this_tag->type == XML_ELEMENT_NODE && strcmp((char *)this_tag->name, "simplesect") == 0) {
 			cstring_t tmp;
@@ -342,22 +1551,60 @@ static cstring_t get_text(xmlNode *cur_node, char **returntext, char **notetext)
 			if (returntext && strcmp(kind, "return") == 0) {
 				*returntext = cstring_to_chars(tmp);
 			}
-			if (notetext && strcmp(kind, "note") == 0) {
-				*notetext = cstring_to_chars(tmp);
+			if (notes && strcmp(kind, "note") == 0) {
+				struct note_info *ni = malloc(sizeof(struct note_info));
+
+				if (ni) {
+					ni->text = cstring_to_chars(tmp);
+					qb_list_add_tail(&ni->list, notes);
+				}
 			}
-			if (notetext && strcmp(kind, "par") == 0) {
+			/*
+			 * \par Title: the title is a bold run-in heading (with a
+			 * colon if it hasn't any punctuation) at the start of the
+			 * first paragraph, or on its own line if the paragraph
+			 * starts with a code listing.
+			 */
+			if (strcmp(kind, "par") == 0) {
+				cstring_t title;
+				char *title_text;
+				char *body;
 				int type;
 
-				tmp = get_child(this_tag, "title");
-				buffer = cstring_append_cstring(buffer, tmp);
-				buffer = cstring_append_chars(buffer, "\n");
 				cstring_free(tmp);
+				title = get_child(this_tag, "title");
+				title_text = cstring_to_chars(title);
+				cstring_free(title);
 
 				tmp = get_texttree(&type,this_tag, NULL, NULL);
+				body = cstring_to_chars(tmp);
+
+				if (title_text && not_all_whitespace(title_text)) {
+					int run_in = body && body[0] && body[0] != '\n';
+					size_t len = strlen(title_text);
+
+					while (len && isspace(title_text[len - 1])) {
+						title_text[--len] = '\0';
+					}
+					if (text_markup()) {
+						buffer = cstring_append_chars(buffer, styles[STYLE_TITLE]);
+					}
+					buffer = append_man_text(buffer, title_text);
+					if (run_in && !ispunct(title_text[len - 1])) {
+						buffer = cstring_append_chars(buffer, ":");
+					}
+					if (text_markup()) {
+						buffer = cstring_append_chars(buffer, "\\fR");
+					}
+					buffer = cstring_append_chars(buffer, run_in ? " " : "\n");
+				}
 				buffer = cstring_append_cstring(buffer, tmp);
 				buffer = cstring_append_chars(buffer, "\n");
+				free(title_text);
+				free(body);
 			}
 			cstring_free(tmp);
+			free(kind);
 		}
 
 		if (this_tag->type == XML_ELEMENT_NODE && strcmp((char *)this_tag->name, "parameterlist") == 0) {
@@ -368,6 +1615,7 @@ static cstring_t get_text(xmlNode *cur_node, char **returntext, char **notetext)
 			if (strcmp(kind, "retval") == 0) {
 				get_param_info(this_tag, &retval_list);
 			}
+			free(kind);
 		}
 	}
 	return buffer;
@@ -399,6 +1647,7 @@ static void read_structdesc(xmlNode *cur_node, void *arg)
 		if (strcmp((char*)this_tag->name, "briefdescription") == 0) {
 			cstring_t brief = get_texttree(NULL, this_tag, NULL, NULL);
 			si->brief_description = cstring_to_chars(brief);
+			cstring_free(brief);
 		}
 	}
 }
@@ -411,6 +1660,7 @@ static void read_headername(xmlNode *cur_node, void *arg)
 
 	for (this_tag = cur_node->children; this_tag; this_tag = this_tag->next) {
 		if (strcmp((char*)this_tag->name, "compoundname") == 0) {
+			free(*h_file);
 			*h_file = strdup((char*)this_tag->children->content);
 		}
 	}
@@ -425,18 +1675,21 @@ static void read_struct(xmlNode *cur_node, void *arg)
 	struct param_info *pi;
 	char fullname[1024];
 	char *type = NULL;
+	char *ref_type = NULL;
 	char *name = NULL;
 	const char *args="";
+	char *value = NULL;
+	cstring_t desc = NULL;
 
 	for (this_tag = cur_node->children; this_tag; this_tag = this_tag->next) {
 		if (strcmp((char*)this_tag->name, "type") == 0) {
-			type = (char*)this_tag->children->content;
-			/* If type is NULL then look for a ref - it's probably an external struct or typedef */
-			if (type == NULL) {
-				cstring_t tmp = get_child(this_tag, "ref");
-				type = cstring_to_chars(tmp);
-				cstring_free(tmp);
-			}
+			/* All of it, a ref to another type is often only part of it, eg enum <ref>x</ref> */
+			xmlChar *content = xmlNodeGetContent(this_tag);
+
+			free(ref_type);
+			ref_type = content ? strdup((char *)content) : NULL;
+			type = ref_type;
+			xmlFree(content);
 		}
 		if (strcmp((char*)this_tag->name, "name") == 0) {
 			name = (char*)this_tag->children->content;
@@ -444,6 +1697,19 @@ static void read_struct(xmlNode *cur_node, void *arg)
 		if (this_tag->children && strcmp((char*)this_tag->name, "argsstring") == 0) {
 			args = (char*)this_tag->children->content;
 		}
+		if (this_tag->children && strcmp((char*)this_tag->name, "initializer") == 0) {
+			value = (char*)this_tag->children->content;
+		}
+		/* Only --struct-pages and --enum-pages have room to describe each member */
+		if ((struct_pages || enum_pages) && (strcmp((char*)this_tag->name, "briefdescription") == 0 ||
+				     strcmp((char*)this_tag->name, "detaileddescription") == 0)) {
+			cstring_t text = get_texttree(NULL, this_tag, NULL, NULL);
+
+			desc = desc ? cstring_append_cstring(desc, text) : text;
+			if (desc != text) {
+				cstring_free(text);
+			}
+		}
 	}
 
 	if (name) {
@@ -452,10 +1718,22 @@ static void read_struct(xmlNode *cur_node, void *arg)
 			snprintf(fullname, sizeof(fullname), "%s%s", name, args);
 			pi->paramtype = type?strdup(type):strdup("");
 			pi->paramname = strdup(fullname);
-			pi->paramdesc = NULL;
+			pi->paramdesc = desc ? cstring_to_chars(desc) : NULL;
+			if (pi->paramdesc && !not_all_whitespace(pi->paramdesc)) {
+				free(pi->paramdesc);
+				pi->paramdesc = NULL;
+			}
+			if (value) {
+				value += strspn(value, "= \t");
+			}
+			pi->paramvalue = value && *value ? strdup(value) : NULL;
 			qb_list_add_tail(&pi->list, &si->params_list);
 		}
 	}
+	if (desc) {
+		cstring_free(desc);
+	}
+	free(ref_type);
 }
 
 static int read_structure_from_xml(const char *refid, const char *name)
@@ -471,18 +1749,21 @@ static int read_structure_from_xml(const char *refid, const char *name)
 
 	/* Don't call into libxml if the file does not exist - saves unwanted error messages */
 	if (stat(fname, &st) == -1) {
+		log_msg(LOG_VERBOSE, "No XML file for %s (%s)\n", refid, fname);
 		return -1;
 	}
+	log_msg(LOG_VERBOSE, "Reading %s from %s\n", refid, fname);
 
-	doc = xmlParseFile(fname);
+	doc = xmlReadFile(fname, xml_encoding, XML_PARSE_COMPACT);
 	if (doc == NULL) {
-		fprintf(stderr, "Error: unable to open xml file for %s\n", refid);
+		report_error(D2M_ERR_PARSE, fname, name, "unable to read structure XML");
 		return -1;
 	}
 
 	rootdoc = xmlDocGetRootElement(doc);
 	if (!rootdoc) {
-		fprintf(stderr, "Can't find \"document root\"\n");
+		report_error(D2M_ERR_PARSE, fname, name, "can't find document root");
+		xmlFreeDoc(doc);
 		return -1;
 	}
 
@@ -495,7 +1776,7 @@ static int read_structure_from_xml(const char *refid, const char *name)
 		traverse_node(rootdoc, "compounddef", read_structdesc, si);
 		traverse_node(rootdoc, "compounddef", read_structname, si);
 		ret = 0;
-		qb_map_put(structures_map, refid, si);
+		qb_map_put(structures_map, strdup(refid), si);
 	}
 	xmlFreeDoc(doc);
 
@@ -521,30 +1802,40 @@ static void print_param(FILE *manfile, struct param_info *pi, int field_width, i
 {
 	const char *asterisks = "  ";
 	char *type = pi->paramtype;
-	int typelength = strlen(type);
+	size_t typelength;
+	size_t strip = 0;
+
+	/* Look at the last character that will be printed, not any markup after it */
+	markup_width(type, &typelength);
 
 	/* Reformat pointer params so they look nicer */
 	if (typelength > 0 && pi->paramtype[typelength-1] == '*') {
 		asterisks=" *";
-		type = strdup(pi->paramtype);
-		type[typelength-1] = '\0';
+		strip = 1;
 
 		/* Cope with double pointers */
 		if (typelength > 1 && pi->paramtype[typelength-2] == '*') {
 			asterisks="**";
-			type[typelength-2] = '\0';
+			strip = 2;
 		}
 
 		/* Tidy function pointers */
 		if (typelength > 1 && pi->paramtype[typelength-2] == '(') {
 			asterisks="(*";
-			type[typelength-2] = '\0';
+			strip = 2;
 		}
 	}
+	if (strip) {
+		type = strdup(pi->paramtype);
+		/* Keep any trailing markup so fonts still get switched back */
+		memmove(type + typelength - strip, type + typelength,
+			strlen(type + typelength) + 1);
+	}
 
-	fprintf(manfile, "    %s%-*s%s%s\\fI%s\\fP%s\n",
-		bold?"\\fB":"", field_width, type,
-		asterisks, bold?"\\fP":"",
+	fprintf(manfile, "    %s", bold?styles[STYLE_TYPE]:"");
+	print_padded(manfile, type, field_width);
+	fprintf(manfile, "%s%s%s%s\\fP%s\n",
+		asterisks, bold?"\\fP":"", styles[STYLE_PARAM],
 		pi->paramname?pi->paramname:"", delimiter);
 
 	if (type != pi->paramtype) {
@@ -552,13 +1843,66 @@ static void print_param(FILE *manfile, struct param_info *pi, int field_width, i
 	}
 }
 
+/* Parameter types at least this wide don't get lined up with the others */
+static int param_type_limit(void)
+{
+	return max_param_type ? max_param_type : synopsis_width;
+}
+
+/* A parameter with a type too long to line up: the type, then the name under it */
+static void print_long_param(FILE *manfile, struct param_info *pi, const char *delimiter)
+{
+	fprintf(manfile, "    %s%s\\fP\n", styles[STYLE_TYPE], pi->paramtype);
+	fprintf(manfile, "        %s%s\\fP%s\n", styles[STYLE_PARAM], pi->paramname ? pi->paramname : "", delimiter);
+}
+
+/* Where the function's name starts in its definition, after the return type */
+static const char *def_name(const char *def)
+{
+	const char *name = def + strlen(def);
+
+	while (name > def && name[-1] != ' ' && name[-1] != '*') {
+		name--;
+	}
+	return name;
+}
+
+/* The return type and function name, split over two lines if they don't fit on one */
+static void print_synopsis_def(FILE *manfile, const char *def)
+{
+	const char *name = def_name(def);
+	int len;
+
+	if (name == def || *name == '\0') {
+		fprintf(manfile, "%s%s\\fP(\n", styles[STYLE_FUNCTION], def);
+		return;
+	}
+	if (display_width(def) + 1 <= synopsis_width) {
+		/* Only split the line up if the fonts are different */
+		if (strcmp(styles[STYLE_TYPE], styles[STYLE_FUNCTION]) == 0) {
+			fprintf(manfile, "%s%s\\fP(\n", styles[STYLE_FUNCTION], def);
+		} else {
+			fprintf(manfile, "%s%.*s\\fP%s%s\\fP(\n", styles[STYLE_TYPE], (int)(name - def), def,
+				styles[STYLE_FUNCTION], name);
+		}
+		return;
+	}
+
+	len = name - def;
+	while (len > 0 && def[len - 1] == ' ') {
+		len--;
+	}
+	fprintf(manfile, "%s%.*s\\fP\n", styles[STYLE_TYPE], len, def);
+	fprintf(manfile, "%s%s\\fP(\n", styles[STYLE_FUNCTION], name);
+}
+
 static void print_structure(FILE *manfile, struct struct_info *si)
 {
 	struct param_info *pi;
 	struct qb_list_head *iter;
-	unsigned int max_param_length=0;
+	int max_param_length=0;
 
-	fprintf(manfile, ".nf\n");
+	fprintf(manfile, "%s\n", dialect->nofill_start);
 	fprintf(manfile, "\\fB\n");
 
 	if (si->brief_description) {
@@ -570,8 +1914,8 @@ static void print_structure(FILE *manfile, struct struct_info *si)
 
 	qb_list_for_each(iter, &si->params_list) {
 		pi = qb_list_entry(iter, struct param_info, list);
-		if (strlen(pi->paramtype) > max_param_length) {
-			max_param_length = strlen(pi->paramtype);
+		if (display_width(pi->paramtype) > max_param_length) {
+			max_param_length = display_width(pi->paramtype);
 		}
 	}
 
@@ -589,11 +1933,11 @@ static void print_structure(FILE *manfile, struct struct_info *si)
 	}
 	fprintf(manfile, "};\n");
 
-	fprintf(manfile, "\\fP\n");
-	fprintf(manfile, ".fi\n");
+	fprintf(manfile, "\\fR\n");
+	fprintf(manfile, "%s\n", dialect->nofill_end);
 }
 
-cstring_t get_texttree(int *type, xmlNode *cur_node, char **returntext, char **notetext)
+cstring_t get_texttree(int *type, xmlNode *cur_node, char **returntext, struct qb_list_head *notes)
 {
 	xmlNode *this_tag;
 	cstring_t tmp;
@@ -602,7 +1946,7 @@ cstring_t get_texttree(int *type, xmlNode *cur_node, char **returntext, char **n
 	for (this_tag = cur_node->children; this_tag; this_tag = this_tag->next) {
 
 		if (this_tag->type == XML_ELEMENT_NODE && strcmp((char *)this_tag->name, "para") == 0) {
-			tmp = get_text(this_tag, returntext, notetext);
+			tmp = get_text(this_tag, returntext, notes);
 			buffer = cstring_append_cstring(buffer, tmp);
 			buffer = cstring_append_chars(buffer, "\n");
 
@@ -612,37 +1956,24 @@ cstring_t get_texttree(int *type, xmlNode *cur_node, char **returntext, char **n
 	return buffer;
 }
 
-/* The text output is VERY basic and just a check that it's working really */
-static void print_text(char *name, char *def, char *brief, char *args, char *detailed,
-		       struct qb_list_head *param_list, char *returntext, char *notetext)
+/* A tagged paragraph in a list, with the tag in 'font' (one of the dialect's macros) if it's set */
+static void print_list_tag(FILE *manfile, const char *font, const char *tag)
 {
-	printf(" ------------------ %s --------------------\n", name);
-	printf("NAME\n");
-	if (brief) {
-		printf("        %s - %s\n", name, brief);
+	fprintf(manfile, ".TP\n");
+	if (font) {
+		fprintf(manfile, ".%s %s\n", font, tag);
 	} else {
-		printf("        %s\n", name);
-	}
-
-	printf("SYNOPSIS\n");
-	printf("        #include <%s%s>\n", header_prefix, headerfile);
-	if (args) {
-		printf("        %s %s\n\n", name, args);
+		fprintf(manfile, "%s\n", tag);
 	}
+}
 
-	if (detailed) {
-		printf("DESCRIPTION\n");
-		printf("        %s\n", detailed);
-	}
+/* A tagged paragraph whose tag is a reference to a page */
+static void print_xref_tag(FILE *manfile, const char *name, int len, const char *section)
+{
+	char tag[PATH_MAX];
 
-	if (returntext) {
-		printf("RETURN VALUE\n");
-		printf("        %s\n", returntext);
-	}
-	if (notetext) {
-		printf("NOTE\n");
-		printf("        %s\n", notetext);
-	}
+	snprintf(tag, sizeof(tag), "%.*s (%s)", len, name, section);
+	print_list_tag(manfile, dialect->xref, tag);
 }
 
 /* Print a long string with para marks in it. */
@@ -657,7 +1988,9 @@ static void man_print_long_string(FILE *manfile, char *text)
 		*next_nl = '\0';
 
 		// Don't format @code blocks
-		if (strncmp(current, ".nf", 3) == 0) {
+		if (strncmp(current, ".nf", 3) == 0 ||
+		    strncmp(current, ".EX", 3) == 0 ||
+		    strncmp(current, ".Bd", 3) == 0) {
 			in_prog = 1;
 			fprintf(manfile, "\n");
 		}
@@ -666,11 +1999,13 @@ static void man_print_long_string(FILE *manfile, char *text)
 			fprintf(manfile, "%s\n", current);
 		} else {
 			if (strlen(current)) {
-				fprintf(manfile, ".PP\n%s\n", current);
+				fprintf(manfile, "%s\n%s\n", dialect->paragraph, current);
 			}
 		}
 
-		if (strncmp(current, ".fi", 3) == 0) {
+		if (strncmp(current, ".fi", 3) == 0 ||
+		    strncmp(current, ".EE", 3) == 0 ||
+		    strncmp(current, ".Ed", 3) == 0) {
 			in_prog = 0;
 			fprintf(manfile, "\n");
 		}
@@ -682,637 +2017,10326 @@ static void man_print_long_string(FILE *manfile, char *text)
 
 	/* The bit at the end */
 	if (strlen(current) && !in_prog) {
-		fprintf(manfile, ".PP\n%s\n", current);
+		fprintf(manfile, "%s\n%s\n", dialect->paragraph, current);
 	}
 }
 
-static void print_manpage(char *name, char *def, char *brief, char *args, char *detailed,
-			  struct qb_list_head *param_map, char *returntext, char *notetext)
+/* Each paragraph of a member's description, indented under its name */
+static void print_member_desc(FILE *manfile, const char *desc)
 {
-	char manfilename[PATH_MAX];
-	char gendate[64];
-	const char *dateptr = gendate;
-	FILE *manfile;
-	time_t t;
-	struct tm *tm;
-	qb_map_iter_t *map_iter;
-	struct qb_list_head *iter;
-	struct qb_list_head *tmp;
-	const char *p;
-	void *data;
-	unsigned int max_param_type_len;
-	unsigned int max_param_name_len;
-	unsigned int num_param_descs;
-	int param_count = 0;
-	int param_num = 0;
-	struct param_info *pi;
+	const char *line;
+	const char *next;
 
-	t = time(NULL);
-	tm = localtime(&t);
-	if (!tm) {
-		perror("unable to get localtime");
-		exit(1);
+	for (line = desc; *line; line = next) {
+		next = strchr(line, '\n');
+		next = next ? next + 1 : line + strlen(line);
+		if (next - line <= 1 && *line == '\n') {
+			continue;
+		}
+		if (line != desc) {
+			fprintf(manfile, "%s\n", dialect->item_paragraph);
+		}
+		fprintf(manfile, "%.*s\n", (int)(next - line - (next[-1] == '\n')), line);
 	}
-	strftime(gendate, sizeof(gendate), "%Y-%m-%d", tm);
+}
 
-	if (manpage_date) {
-		dateptr = manpage_date;
-	}
-	if (manpage_year == LONG_MIN) {
-		manpage_year = tm->tm_year+1900;
-	}
+static size_t count_notes(struct qb_list_head *notes)
+{
+	struct qb_list_head *iter;
+	size_t count = 0;
 
-	snprintf(manfilename, sizeof(manfilename), "%s/%s.%s", output_dir, name, man_section);
-	manfile = fopen(manfilename, "w+");
-	if (!manfile) {
-		perror("unable to open output file");
-		printf("%s", manfilename);
-		exit(1);
+	qb_list_for_each(iter, notes) {
+		count++;
 	}
+	return count;
+}
 
-	/* Work out the length of the parameters, so we can line them up   */
-	max_param_type_len = 0;
-	max_param_name_len = 0;
-	num_param_descs = 0;
-
-	qb_list_for_each(iter, param_map) {
-		pi = qb_list_entry(iter, struct param_info, list);
+/* One note is a paragraph like the rest of the page, more than one are a list */
+static void print_notes(FILE *manfile, struct qb_list_head *notes)
+{
+	struct qb_list_head *iter;
+	struct note_info *ni;
 
-		/* It's mainly macros that break this,
-		 * macros need more work
-		 */
-		if (!pi->paramtype) {
-			pi->paramtype = strdup("");
-		}
-		if ((strlen(pi->paramtype) < LINE_LENGTH) &&
-		    (strlen(pi->paramtype) > max_param_type_len)) {
-			max_param_type_len = strlen(pi->paramtype);
-		}
-		if (strlen(pi->paramname) > max_param_name_len) {
-			max_param_name_len = strlen(pi->paramname);
-		}
-		if (pi->paramdesc && pi->paramtype[0] != '\0') {
-			num_param_descs++;
-		}
-		param_count++;
+	if (count_notes(notes) == 1) {
+		ni = qb_list_entry(notes->next, struct note_info, list);
+		man_print_long_string(manfile, ni->text);
+		return;
 	}
+	qb_list_for_each(iter, notes) {
+		ni = qb_list_entry(iter, struct note_info, list);
+		fprintf(manfile, "%s\n", dialect->bullet_item);
+		print_member_desc(manfile, ni->text);
+	}
+}
 
-	/* Off we go */
-
-	fprintf(manfile, ".\\\"  Automatically generated man page, do not edit\n");
-	fprintf(manfile, ".TH %s %s %s \"%s\" \"%s\"\n", allcaps(name), man_section, dateptr, package_name, header);
+static void free_notes(struct qb_list_head *notes)
+{
+	struct note_info *ni;
+	struct qb_list_head *iter;
+	struct qb_list_head *tmp;
 
-	fprintf(manfile, ".SH NAME\n");
-	if (brief && not_all_whitespace(brief)) {
-		fprintf(manfile, "%s \\- %s\n", name, brief);
-	} else {
-		fprintf(manfile, "%s\n", name);
+	qb_list_for_each_safe(iter, tmp, notes) {
+		ni = qb_list_entry(iter, struct note_info, list);
+		qb_list_del(&ni->list);
+		free(ni->text);
+		free(ni);
 	}
+}
 
-	fprintf(manfile, ".SH SYNOPSIS\n");
-	fprintf(manfile, ".nf\n");
-	fprintf(manfile, ".B #include <%s%s>\n", header_prefix, headerfile);
-	if (def) {
-		fprintf(manfile, ".sp\n");
-		fprintf(manfile, "\\fB%s\\fP(\n", def);
-
-		qb_list_for_each(iter, param_map) {
-			pi = qb_list_entry(iter, struct param_info, list);
+/* Find the immediate child element of 'node' called 'name' */
+static xmlNode *find_child(xmlNode *node, const char *name)
+{
+	xmlNode *this_tag;
 
-			if (pi->paramtype[0] != '\0') { //CC
-				print_param(manfile, pi, max_param_type_len, 1, ++param_num < param_count?",":"");
-			}
+	for (this_tag = node->children; this_tag; this_tag = this_tag->next) {
+		if (this_tag->type == XML_ELEMENT_NODE && strcmp((char *)this_tag->name, name) == 0) {
+			return this_tag;
 		}
-
-		fprintf(manfile, ");\n");
-		fprintf(manfile, ".fi\n");
 	}
+	return NULL;
+}
 
-	if (print_params && num_param_descs) {
-		fprintf(manfile, ".SH PARAMS\n");
+/* Depth-first search for the first element called 'name' under 'node' */
+static xmlNode *find_node(xmlNode *node, const char *name)
+{
+	xmlNode *this_tag;
+	xmlNode *found;
 
-		qb_list_for_each(iter, &params_list) {
-			pi = qb_list_entry(iter, struct param_info, list);
-			fprintf(manfile, "\\fB%-*s \\fP\\fI%s\\fP\n", (int)max_param_name_len, pi->paramname,
-				pi->paramdesc);
-			fprintf(manfile, ".PP\n");
+	for (this_tag = node->children; this_tag; this_tag = this_tag->next) {
+		if (this_tag->type != XML_ELEMENT_NODE) {
+			continue;
+		}
+		if (strcmp((char *)this_tag->name, name) == 0) {
+			return this_tag;
+		}
+		found = find_node(this_tag, name);
+		if (found) {
+			return found;
 		}
 	}
+	return NULL;
+}
 
-	if (detailed) {
-		fprintf(manfile, ".SH DESCRIPTION\n");
-		man_print_long_string(manfile, detailed);
-	}
+/*
+ * Where doxygen says a symbol is declared, "<file>:<line>", so that
+ * messages point at the header rather than the XML. If there's no
+ * <location> it's the XML file.
+ */
+static const char *node_location(xmlNode *node, char *buf, size_t len)
+{
+	xmlNode *location = find_child(node, "location");
+	char *file = location ? get_attr(location, "file") : NULL;
+	char *line = location ? get_attr(location, "line") : NULL;
 
-	if (qb_map_count_get(used_structures_map)) {
-		int first_struct = 1;
+	if (!file) {
+		free(line);
+		return current_xml_file;
+	}
+	snprintf(buf, len, "%s%s%s", file, line ? ":" : "", line ? line : "");
+	free(file);
+	free(line);
+	return buf;
+}
 
-		map_iter = qb_map_iter_create(used_structures_map);
-		for (p = qb_map_iter_next(map_iter, &data); p; p = qb_map_iter_next(map_iter, &data)) {
-			struct struct_info *si;
-			const char *refid = p;
-			char *refname = data;
+/* The symbol whose page we're writing, for the SOURCE section and lint messages. NULL when done. */
+static void set_source_location(xmlNode *node)
+{
+	xmlNode *location = node ? find_child(node, "location") : NULL;
+	char *file = location ? get_attr(location, "file") : NULL;
+	char *line = location ? get_attr(location, "line") : NULL;
+
+	snprintf(source_file, sizeof(source_file), "%s", file ? file : "");
+	source_line = line ? atoi(line) : 0;
+	free(file);
+	free(line);
+}
 
-			/* If it's not been read in - go and look for it */
-			si = qb_map_get(structures_map, refid);
-			if (!si) {
-				if (!read_structure_from_xml(refid, refname)) {
-					si = qb_map_get(structures_map, refid);
-				}
-			}
+/* Append the text of a code listing, without any roff escaping */
+static void write_plain_code(FILE *f, xmlNode *node)
+{
+	xmlNode *this_tag;
 
-			/* Only print header if the struct files exist - sometimes they don't */
-			if (si && first_struct) {
-				fprintf(manfile, ".SH STRUCTURES\n");
-				first_struct = 0;
-			}
-			if (si) {
-				print_structure(manfile, si);
-				fprintf(manfile, ".PP\n");
-			}
+	for (this_tag = node->children; this_tag; this_tag = this_tag->next) {
+		if (strcmp((char *)this_tag->name, "sp") == 0) {
+			fputc(' ', f);
+		}
+		if (this_tag->type == XML_TEXT_NODE && this_tag->content) {
+			fputs((char *)this_tag->content, f);
+		}
+		if (this_tag->type == XML_ELEMENT_NODE) {
+			write_plain_code(f, this_tag);
 		}
-		qb_map_iter_free(map_iter);
-
-		fprintf(manfile, ".RE\n");
 	}
+}
 
-	if (returntext || !qb_list_empty(&retval_list)) {
-		fprintf(manfile, ".SH RETURN VALUE\n");
-		if (returntext) {
-			man_print_long_string(manfile, returntext);
+static void write_code_listings(FILE *f, xmlNode *node, int *count)
+{
+	xmlNode *this_tag;
+
+	for (this_tag = node->children; this_tag; this_tag = this_tag->next) {
+		if (this_tag->type != XML_ELEMENT_NODE) {
+			continue;
+		}
+		if (strcmp((char *)this_tag->name, "programlisting") == 0) {
+			/* Each codeline already ends with a newline */
+			if ((*count)++) {
+				fputc('\n', f);
+			}
+			write_plain_code(f, this_tag);
+		} else {
+			write_code_listings(f, this_tag, count);
 		}
-		fprintf(manfile, ".PP\n");
 	}
+}
 
-	qb_list_for_each(iter, &retval_list) {
-		pi = qb_list_entry(iter, struct param_info, list);
+/* Save the code listings in a page's description as <examples_dir>/<page>.c */
+static int write_example_file(const char *pagename, xmlNode *cur_node)
+{
+	char filename[PATH_MAX];
+	xmlNode *detailed;
+	FILE *example;
+	int count = 0;
 
-		fprintf(manfile, "\\fB%-*s \\fP%s\n", 10, pi->paramname,
-			pi->paramdesc);
-		fprintf(manfile, ".PP\n");
+	detailed = find_child(cur_node, "detaileddescription");
+	if (!detailed || !find_node(detailed, "programlisting")) {
+		return 0;
 	}
 
-	if (notetext) {
-		fprintf(manfile, ".SH NOTE\n");
-		man_print_long_string(manfile, notetext);
+	snprintf(filename, sizeof(filename), "%s/%s.c", examples_dir, pagename);
+	example = fopen(filename, "w");
+	if (!example) {
+		report_error(D2M_ERR_IO, filename, pagename, "unable to write example: %s", strerror(errno));
+		return -1;
 	}
+	write_code_listings(example, detailed, &count);
+	if (fclose(example)) {
+		report_error(D2M_ERR_IO, filename, pagename, "unable to write example: %s", strerror(errno));
+		return -1;
+	}
+	return 0;
+}
 
-	fprintf(manfile, ".SH SEE ALSO\n");
-	fprintf(manfile, ".PP\n");
-	fprintf(manfile, ".nh\n");
-	fprintf(manfile, ".ad l\n");
-
-	param_num = 0;
-	map_iter = qb_map_iter_create(function_map);
-	for (p = qb_map_iter_next(map_iter, &data); p; p = qb_map_iter_next(map_iter, &data)) {
+/* <header>_<what>, without the .h */
+static void header_pagename(char *pagename, size_t size, const char *what)
+{
+	size_t len = strlen(headerfile);
 
-		/* Exclude us! */
-		if (strcmp(data, name)) {
-			fprintf(manfile, "\\fI%s\\fR(%s)%s", (char *)data, man_section,
-				param_num < (num_functions - 1)?", ":"");
-		}
-		param_num++;
+	if (len > 2 && strcmp(headerfile + len - 2, ".h") == 0) {
+		len -= 2;
 	}
-	qb_map_iter_free(map_iter);
+	snprintf(pagename, size, "%.*s_%s", (int)len, headerfile, what);
+}
 
-	fprintf(manfile, "\n");
-	fprintf(manfile, ".ad\n");
-	fprintf(manfile, ".hy\n");
-	fprintf(manfile, ".SH \"COPYRIGHT\"\n");
-	fprintf(manfile, ".PP\n");
-	if (header_copyright[0] == 'C') {
-		fprintf(manfile, "%s", header_copyright); /* String already contains trailing NL */
-	} else {
-		fprintf(manfile, "Copyright (C) %4ld-%4ld %s, Inc. All rights reserved.\n", start_year, manpage_year, company);
-	}
-	fclose(manfile);
+/* The page listing the examples for a header, eg qbloop_examples */
+static const char *examples_pagename(void)
+{
+	static char pagename[PATH_MAX];
 
-	/* Free the params & retval info */
-	qb_list_for_each_safe(iter, tmp, &params_list) {
-		pi = qb_list_entry(iter, struct param_info, list);
-		qb_list_del(&pi->list);
-		free_paraminfo(pi);
-	}
+	header_pagename(pagename, sizeof(pagename), "examples");
+	return pagename;
+}
 
-	qb_list_for_each_safe(iter, tmp, &retval_list) {
-		pi = qb_list_entry(iter, struct param_info, list);
-		qb_list_del(&pi->list);
-		free_paraminfo(pi);
-	}
+static const char *constants_pagename(void)
+{
+	static char pagename[PATH_MAX];
 
-	/* Free used-structures map */
-	map_iter = qb_map_iter_create(used_structures_map);
-	for (p = qb_map_iter_next(map_iter, &data); p; p = qb_map_iter_next(map_iter, &data)) {
-		qb_map_rm(used_structures_map, p);
-		free(data);
-	}
+	header_pagename(pagename, sizeof(pagename), "constants");
+	return pagename;
 }
 
-/* Same as traverse_members, but to collect function names */
-static void collect_functions(xmlNode *cur_node, void *arg)
+/* The date for the top of the page, also fills in manpage_year if needed */
+static const char *get_manpage_date(void)
 {
-	xmlNode *this_tag;
-	char *kind;
-	char *name = NULL;
-
-	if (cur_node->name && strcmp((char *)cur_node->name, "memberdef") == 0) {
+	static char gendate[64];
+	time_t t;
+	struct tm *tm;
 
-		kind = get_attr(cur_node, "kind");
-		if (kind && strcmp(kind, "function") == 0) {
+	/* --no-dates, nothing that changes from one build to the next */
+	if (no_dates) {
+		return "";
+	}
+	if (header_date[0]) {
+		return header_date;
+	}
 
-			for (this_tag = cur_node->children; this_tag; this_tag = this_tag->next) {
-				if (this_tag->type == XML_ELEMENT_NODE && strcmp((char *)this_tag->name, "name") == 0) {
-					name = strdup((char *)this_tag->children->content);
-				}
-			}
+	if (gendate[0] == '\0') {
+		t = time(NULL);
+		tm = localtime(&t);
+		if (!tm) {
+			perror("unable to get localtime");
+			exit(1);
+		}
+		strftime(gendate, sizeof(gendate), "%Y-%m-%d", tm);
 
-			if (name) {
-				qb_map_put(function_map, name, name);
-				num_functions++;
-			}
+		if (manpage_year == LONG_MIN) {
+			manpage_year = tm->tm_year+1900;
 		}
 	}
+
+	if (manpage_date) {
+		return manpage_date;
+	}
+	return gendate;
 }
 
-/* Same as traverse_members, but to collect enums. The behave like structures for,
-   but, for some reason, are in the main XML file rather than their own */
-static void collect_enums(xmlNode *cur_node, void *arg)
+/* The source field of the .TH line, "<package> <version>" unless --th-source says otherwise */
+static const char *th_source_field(void)
 {
-	xmlNode *this_tag;
-	struct struct_info *si;
-	char *kind;
-	char *refid = NULL;
-	char *name = NULL;
+	static char source[1024];
 
-	if (cur_node->name && strcmp((char *)cur_node->name, "memberdef") == 0) {
+	if (th_source) {
+		return th_source;
+	}
+	if (!package_version) {
+		return package_name;
+	}
+	snprintf(source, sizeof(source), "%s %s", package_name, package_version);
+	return source;
+}
 
-		kind = get_attr(cur_node, "kind");
-		if (kind && strcmp(kind, "enum") == 0) {
-			refid = get_attr(cur_node, "id");
+/* The sections at the end of every page */
+static void print_manpage_footer(FILE *manfile)
+{
+	if (bugs_url || bugs_email) {
+		fprintf(manfile, "%s \"REPORTING BUGS\"\n", dialect->section);
+		fprintf(manfile, "%s\n", dialect->paragraph);
+		if (bugs_url && bugs_email) {
+			fprintf(manfile, "Please report bugs at \\fI%s\\fR or by email to <%s>.\n", bugs_url, bugs_email);
+		} else if (bugs_url) {
+			fprintf(manfile, "Please report bugs at \\fI%s\\fR.\n", bugs_url);
+		} else {
+			fprintf(manfile, "Please report bugs by email to <%s>.\n", bugs_email);
+		}
+	}
 
-			for (this_tag = cur_node->children; this_tag; this_tag = this_tag->next) {
-				if (this_tag->type == XML_ELEMENT_NODE && strcmp((char *)this_tag->name, "name") == 0) {
-					name = strdup((char *)this_tag->children->content);
-				}
-			}
+	fprintf(manfile, "%s \"COPYRIGHT\"\n", dialect->section);
+	fprintf(manfile, "%s\n", dialect->paragraph);
+	if (header_copyright[0]) {
+		fprintf(manfile, "%s", header_copyright); /* String already contains trailing NL */
+	} else if (no_dates) {
+		fprintf(manfile, "Copyright (C) %s, Inc. All rights reserved.\n", company);
+	} else {
+		fprintf(manfile, "Copyright (C) %4ld-%4ld %s, Inc. All rights reserved.\n", start_year, manpage_year, company);
+	}
 
-			if (name) {
-				si = malloc(sizeof(struct struct_info));
-				if (si) {
-					memset(si, 0, sizeof(*si));
-					si->kind = STRUCTINFO_ENUM;
-					qb_list_init(&si->params_list);
-					si->structname = strdup(name);
-					traverse_node(cur_node, "enumvalue", read_struct, si);
-					qb_map_put(structures_map, refid, si);
-				}
-			}
+	if (license_text || header_spdx[0]) {
+		fprintf(manfile, "%s \"LICENSE\"\n", dialect->section);
+	}
+	if (header_spdx[0]) {
+		fprintf(manfile, "%s\n", dialect->paragraph);
+		fprintf(manfile, "SPDX-License-Identifier: %s\n", header_spdx);
+	}
+	if (license_text) {
+		fprintf(manfile, "%s", license_text);
+	}
+
+	if (colophon) {
+		fprintf(manfile, "%s COLOPHON\n", dialect->section);
+		fprintf(manfile, "%s\n", dialect->paragraph);
+		fprintf(manfile, "This page is part of %s. It was generated by doxygen2man from the\n",
+			th_source_field());
+		if (source_revision) {
+			fprintf(manfile, "documentation comments in the source at revision %s.\n", source_revision);
+		} else {
+			fprintf(manfile, "documentation comments in the source.\n");
 		}
 	}
 }
 
-static void traverse_members(xmlNode *cur_node, void *arg)
+static void add_exported_symbol(const char *name, size_t len, const char *version)
 {
-	xmlNode *this_tag;
-
-	qb_list_init(&params_list);
-
-	/* if arg == NULL then we're generating a page for the whole header file */
-	if ((cur_node->name && (strcmp((char *)cur_node->name, "memberdef") == 0)) ||
-	    ((arg == NULL) && cur_node->name && strcmp((char *)cur_node->name, "compounddef")) == 0) {
-		char *kind = NULL;
-		char *def = NULL;
-		char *args = NULL;
-		char *name = NULL;
-		char *brief = NULL;
-		char *detailed = NULL;
-		char *returntext = NULL;
-		char *notetext = NULL;
-		int type;
+	struct exported_symbol *es = calloc(1, sizeof(struct exported_symbol));
 
-		kind=def=args=name=NULL;
+	if (!es) {
+		return;
+	}
+	es->name = strndup(name, len);
+	es->version = version ? strdup(version) : NULL;
+	if (!es->name) {
+		free(es->version);
+		free(es);
+		return;
+	}
+	qb_list_add_tail(&es->list, &exported_symbols_list);
+	if (!strpbrk(es->name, "*?[")) {
+		qb_map_put(exported_symbols_map, es->name, es);
+	}
+}
 
-		kind = get_attr(cur_node, "kind");
+/*
+ * Split a GNU ld version script into words and the punctuation that
+ * matters ({ } ; :), skipping comments. Returns NULL at the end.
+ */
+static const char *version_script_token(const char **pos, size_t *len)
+{
+	const char *c = *pos;
+	const char *start;
 
-		for (this_tag = cur_node->children; this_tag; this_tag = this_tag->next)
-		{
-			if (!this_tag->children || !this_tag->children->content)
-				continue;
+	for (;;) {
+		while (isspace(*c)) {
+			c++;
+		}
+		if (*c == '#') {
+			c += strcspn(c, "\n");
+		} else if (c[0] == '/' && c[1] == '*') {
+			const char *end = strstr(c + 2, "*/");
 
-			if (this_tag->type == XML_ELEMENT_NODE && strcmp((char *)this_tag->name, "definition") == 0)
-				def = strdup((char *)this_tag->children->content);
-			if (this_tag->type == XML_ELEMENT_NODE && strcmp((char *)this_tag->name, "argsstring") == 0)
-				args = strdup((char *)this_tag->children->content);
-			if (this_tag->type == XML_ELEMENT_NODE && strcmp((char *)this_tag->name, "name") == 0)
-				name = strdup((char *)this_tag->children->content);
+			c = end ? end + 2 : c + strlen(c);
+		} else {
+			break;
+		}
+	}
+	if (*c == '\0') {
+		*pos = c;
+		return NULL;
+	}
 
-			if (this_tag->type == XML_ELEMENT_NODE && strcmp((char *)this_tag->name, "briefdescription") == 0) {
-				cstring_t tmp = get_texttree(&type, this_tag, &returntext, &notetext);
-				if (!brief) {
-					brief = cstring_to_chars(tmp);
-				} else {
-					fprintf(stderr, "ERROR function %s has 2 briefdescription tags\n", name?name:"unknown");
-				}
-				cstring_free(tmp);
-			}
-			if (this_tag->type == XML_ELEMENT_NODE && strcmp((char *)this_tag->name, "detaileddescription") == 0) {
-				cstring_t tmp = get_texttree(&type, this_tag, &returntext, &notetext);
-				if (!detailed) {
-					detailed = cstring_to_chars(tmp);
-				} else {
-					fprintf(stderr, "ERROR function %s has 2 detaileddescription tags\n", name?name:"unknown");
-				}
-				cstring_free(tmp);
-			}
-			/* Get all the params */
-			if (this_tag->type == XML_ELEMENT_NODE && strcmp((char *)this_tag->name, "param") == 0) {
-				cstring_t param_type = get_child(this_tag, "type");
-				cstring_t param_name = get_child(this_tag, "declname");
-				struct param_info *pi = malloc(sizeof(struct param_info));
-				if (pi) {
-					pi->paramname = cstring_to_chars(param_name);
-					pi->paramtype = cstring_to_chars(param_type);
-					pi->paramdesc = NULL;
-					qb_list_add_tail(&pi->list, &params_list);
-				}
-			}
+	start = c;
+	if (strchr("{};:", *c)) {
+		c++;
+	} else if (*c == '"') {
+		c = strchr(c + 1, '"');
+		c = c ? c + 1 : start + strlen(start);
+	} else {
+		while (*c && !isspace(*c) && !strchr("{};:#", *c)) {
+			c++;
 		}
+	}
+	*len = c - start;
+	*pos = c;
+	return start;
+}
 
-		if (arg == headerfile) {
-			/* Print header page */
-			name = (char*)headerfile;
-			if (print_man) {
-				if (!quiet) {
-					printf("Printing header manpage for %s\n", name);
-				}
-				print_manpage(name, def, brief, args, detailed, &params_list, returntext, notetext);
+/*
+ * VERS_1.0 { global: sym1; sym2; local: *; };
+ * VERS_2.0 { global: sym3; } VERS_1.0;
+ * Only the global symbols are exported.
+ */
+static void parse_version_script(const char *text)
+{
+	const char *pos = text;
+	const char *tok;
+	const char *next;
+	char version[256] = "";
+	size_t len;
+	size_t next_len;
+	int depth = 0;
+	int global = 1;
+
+	for (tok = version_script_token(&pos, &len); tok; tok = next, len = next_len) {
+		next = version_script_token(&pos, &next_len);
+
+		if (*tok == '{') {
+			if (depth++ == 0) {
+				global = 1;
 			}
-			else {
-				print_text(name, def, brief, args, detailed, &params_list, returntext, notetext);
+		} else if (*tok == '}') {
+			if (depth > 0 && --depth == 0) {
+				version[0] = '\0';
+			}
+		} else if (*tok == ';' || *tok == ':' || *tok == '"') {
+			continue;
+		} else if (depth == 0) {
+			/* A version name, or the one it inherits from after the } */
+			if (next && *next == '{') {
+				snprintf(version, sizeof(version), "%.*s", (int)len, tok);
 			}
+		} else if (next && *next == ':' &&
+			   ((len == 6 && strncmp(tok, "global", 6) == 0) ||
+			    (len == 5 && strncmp(tok, "local", 5) == 0))) {
+			global = (len == 6);
+		} else if (len == 6 && strncmp(tok, "extern", 6) == 0) {
+			/* extern "C++" { ... } - just read the names inside */
+			continue;
+		} else if (global) {
+			add_exported_symbol(tok, len, version[0] ? version : NULL);
 		}
+	}
+}
 
-		if (kind && strcmp(kind, "function") == 0) {
+/*
+ * --exported-symbols is either a GNU ld version script or just a list of
+ * names, one per line, with # comments.
+ */
+static int read_exported_symbols(void)
+{
+	cstring_t text = cstring_alloc();
+	char line[4096];
+	char *contents;
+	char *c;
+	size_t len;
+	FILE *f;
+
+	f = fopen(exported_symbols_file, "r");
+	if (!f) {
+		report_error(D2M_ERR_IO, exported_symbols_file, NULL, "unable to read exported symbols: %s", strerror(errno));
+		cstring_free(text);
+		return -1;
+	}
+	while (fgets(line, sizeof(line), f)) {
+		text = cstring_append_chars(text, line);
+	}
+	fclose(f);
+	contents = cstring_to_chars(text);
+	cstring_free(text);
+	if (!contents) {
+		return -1;
+	}
 
-			/* Make sure function has a doxygen description */
-			if (!detailed) {
-				fprintf(stderr, "No detailed description for function '%s' - please fix this\n", name);
+	exported_symbols_map = qb_hashtable_create(10);
+	if (strchr(contents, '{')) {
+		parse_version_script(contents);
+	} else {
+		for (c = strtok(contents, "\n"); c; c = strtok(NULL, "\n")) {
+			c[strcspn(c, "#")] = '\0';
+			c += strspn(c, " \t\r");
+			len = strcspn(c, " \t\r;");
+			if (len) {
+				add_exported_symbol(c, len, NULL);
 			}
+		}
+	}
+	free(contents);
+	log_msg(LOG_VERBOSE, "%d exported symbols read from %s\n",
+		qb_list_length(&exported_symbols_list), exported_symbols_file);
+	return 0;
+}
 
-			if (!name) {
-				fprintf(stderr, "Internal error - no name found for function\n");
-			} else {
-				if (print_man) {
-					if (!quiet) {
-						printf("Printing manpage for %s\n", name);
-					}
-					print_manpage(name, def, brief, args, detailed, &params_list, returntext, notetext);
-				}
-				else {
-					print_text(name, def, brief, args, detailed, &params_list, returntext, notetext);
-				}
-			}
+static void map_free_symbol_info(uint32_t event, char *key, void *old_value, void *value, void *user_data)
+{
+	struct symbol_info *sym = old_value;
 
-		}
+	free(key);
+	free(sym->version);
+	free(sym->status);
+	free(sym);
+}
 
-		free(kind);
-		free(def);
-		free(args);
-		free(name);
-		free(brief);
-		free(detailed);
+static void map_free_deprecated(uint32_t event, char *key, void *old_value, void *value, void *user_data)
+{
+	struct deprecated_info *di = old_value;
+
+	free(key);
+	if (di) {
+		free(di->header);
+		free(di->page);
+		free(di->text);
+		free(di->replacement);
+		free(di->version);
+		free(di);
 	}
 }
 
-
-static void traverse_node(xmlNode *parentnode, const char *leafname, void (do_members(xmlNode*, void*)), void *arg)
+/*
+ * --symbol-info keeps versions and stability out of the headers. Each
+ * line is
+ *   <symbol> <version> [<status>]
+ * where <version> can be - if there's only a status, eg
+ *   qb_log_thread_start QB_0.10 stable
+ *   qb_ipcs_request_rate_limit - deprecated, use qb_ipcs_rate_limit()
+ */
+static int read_symbol_info(void)
 {
-	xmlNode *cur_node;
+	struct symbol_info *sym;
+	char line[4096];
+	char *name;
+	char *version;
+	char *status;
+	char *end;
+	int lineno = 0;
+	FILE *f;
+
+	f = fopen(symbol_info_file, "r");
+	if (!f) {
+		report_error(D2M_ERR_IO, symbol_info_file, NULL, "unable to read symbol info: %s", strerror(errno));
+		return -1;
+	}
 
-	for (cur_node = parentnode->children; cur_node; cur_node = cur_node->next) {
-		if (cur_node->type == XML_ELEMENT_NODE && cur_node->name
-		    && strcmp((char*)cur_node->name, leafname)==0) {
-			do_members(cur_node, arg);
+	symbol_info_map = qb_skiplist_create();
+	qb_map_notify_add(symbol_info_map, NULL, map_free_symbol_info, QB_MAP_NOTIFY_FREE, NULL);
+	while (fgets(line, sizeof(line), f)) {
+		lineno++;
+		line[strcspn(line, "#\r\n")] = '\0';
+		name = strtok(line, " \t");
+		if (!name) {
 			continue;
 		}
-		if (cur_node->type == XML_ELEMENT_NODE) {
-			traverse_node(cur_node, leafname, do_members, arg);
+		version = strtok(NULL, " \t");
+		status = strtok(NULL, "");
+		if (!version) {
+			report_error(D2M_ERR_DOC, symbol_info_file, name, "line %d has no version (use - for none)", lineno);
+			continue;
+		}
+		if (status) {
+			status += strspn(status, " \t");
+			for (end = status + strlen(status); end > status && isspace(end[-1]); end--) {
+				end[-1] = '\0';
+			}
+		}
+
+		sym = calloc(1, sizeof(struct symbol_info));
+		if (!sym) {
+			break;
 		}
+		sym->version = strcmp(version, "-") ? strdup(version) : NULL;
+		sym->status = (status && *status) ? strdup(status) : NULL;
+		qb_map_put(symbol_info_map, strdup(name), sym);
 	}
+	fclose(f);
+	return 0;
 }
 
-
-static void usage(char *name)
+/* The text of a tagfile element, NULL if it's empty */
+static char *tagfile_text(xmlNode *node, const char *tag)
 {
-	printf("Usage:\n");
-	printf("      %s [OPTIONS] <XML file>\n", name);
-	printf("\n");
-	printf(" This is a tool to generate API manpages from a doxygen-annotated header file.\n");
-	printf(" First run doxygen on the file and then run this program against the main XML file\n");
-	printf(" it created and the directory containing the ancilliary files. It will then\n");
-	printf(" output a lot of *.3 man page files which you can then ship with your library.\n");
-	printf("\n");
-	printf(" You will need to invoke this program once for each .h file in your library,\n");
-	printf(" using the name of the generated .xml file. This file will usually be called\n");
-	printf(" something like <include-file>_8h.xml, eg qbipcs_8h.xml\n");
-	printf("\n");
-	printf(" If you want HTML output then simpy use nroff on the generated files as you\n");
-	printf(" would do with any other man page.\n");
-	printf("\n");
-	printf("       -a            Print ASCII dump of man pages to stdout\n");
-	printf("       -m            Write man page files to <output dir>\n");
-	printf("       -P            Print PARAMS section\n");
-	printf("       -g            Print general man page for the whole header file\n");
-	printf("       -c            Use the Copyright date from the header file (if one can be found)\n");
-	printf("       -O <dir>      Directory for the orignal header file. Often needed by -c above\n");
-	printf("       -s <s>        Write man pages into section <s> <default 3)\n");
-	printf("       -p <package>  Use <package> name. default <Package>\n");
-	printf("       -H <header>   Set header (default \"Programmer's Manual\"\n");
-	printf("       -I <include>  Set include filename (default taken from xml)\n");
-	printf("       -i <prefix>   Prefix for include files. eg qb/ (default \"\")\n");
-	printf("       -C <company>  Company name in copyright (defaults to Red Hat)\n");
-	printf("       -D <date>     Date to print at top of man pages (format not checked, default: today)\n");
-	printf("       -S <year>     Start year to print at end of copyright line (default: 2010)\n");
-	printf("       -Y <year>     Year to print at end of copyright line (default: today's year)\n");
-	printf("       -o <dir>      Write all man pages to <dir> (default .)\n");
-	printf("       -d <dir>      Directory for XML files (./xml/)\n");
-	printf("       -h            Print this usage text\n");
+	xmlNode *child;
+
+	for (child = node->children; child; child = child->next) {
+		if (child->type == XML_ELEMENT_NODE && strcmp((char *)child->name, tag) == 0 &&
+		    child->children && child->children->content && child->children->content[0]) {
+			return strdup((char *)child->children->content);
+		}
+	}
+	return NULL;
 }
 
-static long get_year(char *optionarg, char optionchar)
+static void add_external_symbol(const char *name, char *file, char *anchor, const char *location)
 {
-	long year = strtol(optionarg, NULL, 10);
-	/*
-	 * Don't make too many assumptions about the year. I was on call at the
-	 * 2000 rollover. #experience
-	 */
-	if (year == LONG_MIN || year == LONG_MAX ||
-	    year < 1900) {
-		fprintf(stderr, "Value passed to -%c is not a valid year number\n", optionchar);
-		return 0;
+	struct external_symbol *es;
+
+	if (!name || !file || qb_map_get(external_symbols, name) ||
+	    !(es = calloc(1, sizeof(struct external_symbol)))) {
+		free(file);
+		free(anchor);
+		return;
 	}
-	return year;
+	es->file = file;
+	es->anchor = anchor;
+	es->location = location ? strdup(location) : NULL;
+	qb_map_put(external_symbols, strdup(name), es);
 }
 
-int main(int argc, char *argv[])
+/*
+ * --tagfile <file>[=<location>], as in doxygen's TAGFILES. The members and
+ * compounds in it are the symbols that refs to another project can go to.
+ * The first tagfile that has a symbol wins.
+ */
+static int read_tagfile(const char *arg)
 {
 	xmlNode *rootdoc;
+	xmlNode *compound;
+	xmlNode *member;
 	xmlDocPtr doc;
-	int opt;
-	char xml_filename[PATH_MAX];
+	char *filename;
+	char *location;
+	char *name;
 
-	while ( (opt = getopt_long(argc, argv, "H:amqgcPD:Y:s:S:d:o:p:f:I:i:C:O:h?", NULL, NULL)) != EOF)
-	{
-		switch(opt)
-		{
-			case 'a':
-				print_ascii = 1;
-				print_man = 0;
-				break;
-			case 'm':
-				print_man = 1;
-				print_ascii = 0;
-				break;
-			case 'P':
-				print_params = 1;
-				break;
-			case 'g':
-				print_general = 1;
-				break;
-			case 'q':
-				quiet = 1;
-				break;
-			case 'c':
-				use_header_copyright = 1;
+	filename = strdup(arg);
+	if (!filename) {
+		return -1;
+	}
+	location = strchr(filename, '=');
+	if (location) {
+		*location++ = '\0';
+		if (*location && location[strlen(location) - 1] == '/') {
+			location[strlen(location) - 1] = '\0';
+		}
+	}
+
+	doc = xmlReadFile(filename, NULL, XML_PARSE_COMPACT | XML_PARSE_NONET);
+	if (!doc) {
+		report_error(D2M_ERR_IO, filename, NULL, "unable to read tagfile");
+		free(filename);
+		return -1;
+	}
+	rootdoc = xmlDocGetRootElement(doc);
+	if (!rootdoc || strcmp((char *)rootdoc->name, "tagfile") != 0) {
+		report_error(D2M_ERR_PARSE, filename, NULL, "not a doxygen tagfile");
+		xmlFreeDoc(doc);
+		free(filename);
+		return -1;
+	}
+
+	if (!external_symbols) {
+		external_symbols = qb_skiplist_create();
+		qb_map_notify_add(external_symbols, NULL, map_free_external_symbol, QB_MAP_NOTIFY_FREE, NULL);
+	}
+	for (compound = rootdoc->children; compound; compound = compound->next) {
+		if (compound->type != XML_ELEMENT_NODE || strcmp((char *)compound->name, "compound") != 0) {
+			continue;
+		}
+		name = tagfile_text(compound, "name");
+		add_external_symbol(name, tagfile_text(compound, "filename"), NULL, location);
+		free(name);
+
+		for (member = compound->children; member; member = member->next) {
+			if (member->type != XML_ELEMENT_NODE || strcmp((char *)member->name, "member") != 0) {
+				continue;
+			}
+			name = tagfile_text(member, "name");
+			add_external_symbol(name, tagfile_text(member, "anchorfile"),
+					    tagfile_text(member, "anchor"), location);
+			free(name);
+		}
+	}
+	log_msg(LOG_VERBOSE, "Read %s\n", filename);
+
+	xmlFreeDoc(doc);
+	free(filename);
+	return 0;
+}
+
+/*
+ * A string (or null) from a line of JSON that json_string() wrote, *p is
+ * moved past it. Returns -1 if it isn't one.
+ */
+static int read_json_string(const char **p, char **str)
+{
+	const char *c = *p;
+	char chunk[2] = {0, 0};
+	char hex[5] = {0};
+	cstring_t buffer;
+
+	*str = NULL;
+	if (strncmp(c, "null", 4) == 0) {
+		*p = c + 4;
+		return 0;
+	}
+	if (*c != '"') {
+		return -1;
+	}
+
+	buffer = cstring_alloc();
+	for (c++; *c && *c != '"'; c++) {
+		if (c[0] == '\\' && c[1] == 'u' && strspn(c + 2, "0123456789abcdefABCDEF") >= 4) {
+			memcpy(hex, c + 2, 4);
+			chunk[0] = (char)strtol(hex, NULL, 16);
+			c += 5;
+		} else if (c[0] == '\\' && c[1]) {
+			chunk[0] = *++c;
+		} else {
+			chunk[0] = *c;
+		}
+		if (chunk[0]) {
+			buffer = cstring_append_chars(buffer, chunk);
+		}
+	}
+	if (*c != '"') {
+		cstring_free(buffer);
+		return -1;
+	}
+	*str = cstring_to_chars(buffer);
+	cstring_free(buffer);
+	*p = c + 1;
+	return 0;
+}
+
+/*
+ * --symbol-db <file>, the symbols documented by earlier runs, so that refs to
+ * the ones in other headers can name their pages and go in SEE ALSO. It's
+ * JSON, one symbol per line, as write_symbol_db() writes it:
+ *   "qb_log_init":{"kind":"function","header":"qblog.h","page":"qb_log_init.3"},
+ * The file doesn't have to be there, the first run creates it.
+ */
+static qb_map_t *new_symbol_db_map(void)
+{
+	qb_map_t *map = qb_skiplist_create();
+
+	qb_map_notify_add(map, NULL, map_free_db_symbol, QB_MAP_NOTIFY_FREE, NULL);
+	return map;
+}
+
+/* Add the symbols in a --symbol-db file to 'map', 'report' the lines that aren't symbols */
+static void parse_symbol_db(FILE *f, qb_map_t *map, int report)
+{
+	struct db_symbol *ds;
+	char *line = NULL;
+	size_t linelen = 0;
+	const char *c;
+	char *name;
+	char *key;
+	char *value;
+	int lineno = 0;
+	int bad;
+
+	while (getline(&line, &linelen, f) != -1) {
+		lineno++;
+		c = line + strspn(line, " \t");
+		if (*c != '"') {
+			/* The { and } lines */
+			continue;
+		}
+		ds = calloc(1, sizeof(struct db_symbol));
+		if (!ds) {
+			break;
+		}
+
+		bad = read_json_string(&c, &name) || !name || *c++ != ':' || *c++ != '{';
+		while (!bad && *c == '"') {
+			value = NULL;
+			bad = read_json_string(&c, &key) || *c++ != ':' || read_json_string(&c, &value);
+			if (!bad && strcmp(key, "kind") == 0 && !ds->kind) {
+				ds->kind = value;
+			} else if (!bad && strcmp(key, "header") == 0 && !ds->header) {
+				ds->header = value;
+			} else if (!bad && strcmp(key, "page") == 0 && !ds->page) {
+				ds->page = value;
+			} else {
+				free(value);
+			}
+			free(key);
+			if (*c == ',') {
+				c++;
+			}
+		}
+		if (bad || *c != '}' || !ds->kind || !ds->header) {
+			if (report) {
+				report_error(D2M_ERR_DOC, symbol_db_file, name, "line %d isn't a symbol, ignored", lineno);
+			}
+			map_free_db_symbol(0, name, ds, NULL, NULL);
+			continue;
+		}
+		if (qb_map_get(map, name)) {
+			map_free_db_symbol(0, name, ds, NULL, NULL);
+			continue;
+		}
+		qb_map_put(map, name, ds);
+	}
+	free(line);
+}
+
+static int read_symbol_db(void)
+{
+	FILE *f;
+
+	symbol_db = new_symbol_db_map();
+	db_see_also = new_used_structures_map();
+
+	f = fopen(symbol_db_file, "r");
+	if (!f) {
+		if (errno == ENOENT) {
+			log_msg(LOG_VERBOSE, "%s isn't there yet, it will be created\n", symbol_db_file);
+			return 0;
+		}
+		report_error(D2M_ERR_IO, symbol_db_file, NULL, "unable to read symbol database: %s", strerror(errno));
+		return -1;
+	}
+	parse_symbol_db(f, symbol_db, 1);
+	fclose(f);
+	log_msg(LOG_VERBOSE, "Read %zu symbols from %s\n", qb_map_count_get(symbol_db), symbol_db_file);
+	return 0;
+}
+
+/* Whether --exported-symbols has this name, or a wildcard that matches it */
+static struct exported_symbol *find_exported_symbol(const char *name)
+{
+	struct exported_symbol *es;
+	struct qb_list_head *iter;
+
+	es = qb_map_get(exported_symbols_map, name);
+	if (es) {
+		return es;
+	}
+	qb_list_for_each(iter, &exported_symbols_list) {
+		es = qb_list_entry(iter, struct exported_symbol, list);
+		if (strpbrk(es->name, "*?[") && fnmatch(es->name, name, 0) == 0) {
+			return es;
+		}
+	}
+	return NULL;
+}
+
+/*
+ * --source-revision auto, what 'git describe' says about the tree the
+ * headers (-O) are in. A tag if there is one, or the commit, and -dirty
+ * if there are changes that aren't committed.
+ */
+static int get_git_revision(void)
+{
+	char *argv[] = {(char *)"git", (char *)"-C", (char *)header_src_dir, (char *)"describe",
+			(char *)"--tags", (char *)"--always", (char *)"--dirty", NULL};
+	ssize_t len = 0;
+	ssize_t n;
+	int status;
+	int fds[2];
+	pid_t pid;
+
+	if (pipe(fds) == -1) {
+		report_error(D2M_ERR_IO, header_src_dir, NULL, "unable to run git: %s", strerror(errno));
+		return -1;
+	}
+	fflush(stdout);
+	pid = fork();
+	if (pid == 0) {
+		close(fds[0]);
+		dup2(fds[1], STDOUT_FILENO);
+		close(fds[1]);
+		execvp(argv[0], argv);
+		fprintf(stderr, "Unable to run %s: %s\n", argv[0], strerror(errno));
+		_exit(127);
+	}
+	close(fds[1]);
+	while (pid != -1 && (n = read(fds[0], git_revision + len, sizeof(git_revision) - 1 - len)) > 0) {
+		len += n;
+	}
+	close(fds[0]);
+	git_revision[len] = '\0';
+	git_revision[strcspn(git_revision, "\r\n")] = '\0';
+
+	if (pid == -1 || waitpid(pid, &status, 0) == -1 ||
+	    !WIFEXITED(status) || WEXITSTATUS(status) != 0 || !git_revision[0]) {
+		report_error(D2M_ERR_IO, header_src_dir, NULL, "unable to get the revision from git, use --source-revision <rev>");
+		return -1;
+	}
+	log_msg(LOG_VERBOSE, "Source revision is %s\n", git_revision);
+	return 0;
+}
+
+/* --spell-words, the project's own words, one per line */
+static int read_spell_words(void)
+{
+	char line[256];
+	FILE *f;
+
+	f = fopen(spell_words_file, "r");
+	if (!f) {
+		report_error(D2M_ERR_IO, spell_words_file, NULL, "unable to read spelling words: %s", strerror(errno));
+		return -1;
+	}
+
+	spell_words = qb_hashtable_create(100);
+	qb_map_notify_add(spell_words, NULL, map_free_entry, QB_MAP_NOTIFY_FREE, NULL);
+	while (fgets(line, sizeof(line), f)) {
+		line[strcspn(line, "#\r\n")] = '\0';
+		line[strcspn(line, " \t")] = '\0';
+		if (line[0] && !qb_map_get(spell_words, line)) {
+			qb_map_put(spell_words, strdup(line), strdup(line));
+		}
+	}
+	fclose(f);
+	return 0;
+}
+
+/*
+ * Read --license-file into license_text, already formatted for the
+ * LICENSE section: blank lines separate paragraphs.
+ */
+static int read_license_file(void)
+{
+	char line[4096];
+	cstring_t text;
+	char *c;
+	int new_para = 1;
+	FILE *f;
+
+	f = fopen(license_file, "r");
+	if (!f) {
+		report_error(D2M_ERR_IO, license_file, NULL, "unable to read license file: %s", strerror(errno));
+		return -1;
+	}
+
+	text = cstring_alloc();
+	while (fgets(line, sizeof(line), f)) {
+		line[strcspn(line, "\n")] = '\0';
+		c = line + strspn(line, " \t\r");
+		if (*c == '\0') {
+			new_para = 1;
+			continue;
+		}
+		if (new_para) {
+			text = cstring_append_chars(text, dialect->paragraph);
+			text = cstring_append_chars(text, "\n");
+			new_para = 0;
+		}
+		/* Don't let the text be taken for requests or escapes */
+		if (*c == '.' || *c == '\'') {
+			text = cstring_append_chars(text, "\\&");
+		}
+		for (; *c; c++) {
+			char ch[2] = {*c, '\0'};
+
+			text = cstring_append_chars(text, *c == '\\' ? "\\e" : ch);
+		}
+		text = cstring_append_chars(text, "\n");
+	}
+	fclose(f);
+
+	license_text = cstring_to_chars(text);
+	cstring_free(text);
+	return 0;
+}
+
+/*
+ * FNV-1a hashes, used to tell whether a page needs regenerating.
+ * Strings are hashed with their terminating NUL so that
+ * adjacent fields can't run into each other.
+ */
+#define HASH_INIT  0xcbf29ce484222325ULL
+#define HASH_PRIME 0x100000001b3ULL
+#define HASH_COMMENT ".\\\" doxygen2man-hash: "
+/* Goes into every page hash, bump it when a change to the code changes the pages */
+#define PAGE_FORMAT 1
+#define GENERATED_COMMENT ".\\\"  Automatically generated man page, do not edit\n"
+
+static uint64_t hash_bytes(uint64_t hash, const void *data, size_t len)
+{
+	const unsigned char *c = data;
+	size_t i;
+
+	for (i = 0; i < len; i++) {
+		hash ^= c[i];
+		hash *= HASH_PRIME;
+	}
+	return hash;
+}
+
+static uint64_t hash_string(uint64_t hash, const char *string)
+{
+	if (!string) {
+		string = "(null)";
+	}
+	return hash_bytes(hash, string, strlen(string) + 1);
+}
+
+static uint64_t hash_long(uint64_t hash, long value)
+{
+	return hash_bytes(hash, &value, sizeof(value));
+}
+
+static int hash_file(uint64_t *hash, const char *filename)
+{
+	char buffer[65536];
+	size_t len;
+	FILE *f;
+
+	f = fopen(filename, "r");
+	if (!f) {
+		return -1;
+	}
+	while ((len = fread(buffer, 1, sizeof(buffer), f)) > 0) {
+		*hash = hash_bytes(*hash, buffer, len);
+	}
+	fclose(f);
+	return 0;
+}
+
+/*
+ * Everything given on the command line that changes the contents of a page.
+ * Any new options that affect the output need to be added here too, or
+ * pages won't be regenerated when they change.
+ */
+static uint64_t hash_options(void)
+{
+	struct qb_list_head *iter;
+	uint64_t hash = HASH_INIT;
+	int i;
+
+	/* A new doxygen2man might write the same input differently */
+	hash = hash_string(hash, DOXYGEN2MAN_VERSION);
+	hash = hash_long(hash, PAGE_FORMAT);
+	hash = hash_long(hash, print_params);
+	hash = hash_long(hash, synopsis_width);
+	hash = hash_long(hash, max_param_type);
+	hash = hash_long(hash, wrap_width);
+	hash = hash_long(hash, dialect->id);
+	for (i = 0; i < STYLE_NUM_CLASSES; i++) {
+		hash = hash_string(hash, styles[i]);
+	}
+	hash = hash_long(hash, example_macros);
+	hash = hash_long(hash, header_includes);
+	hash = hash_long(hash, references);
+	hash = hash_long(hash, source_location);
+	hash = hash_long(hash, print_general);
+	hash = hash_long(hash, use_header_copyright);
+	hash = hash_long(hash, start_year);
+	get_manpage_date(); /* fills in manpage_year */
+	hash = hash_long(hash, manpage_year);
+	hash = hash_long(hash, no_dates);
+	hash = hash_long(hash, date_from_header);
+	hash = hash_string(hash, man_section);
+	hash = hash_string(hash, package_name);
+	hash = hash_string(hash, header);
+	hash = hash_string(hash, th_source);
+	hash = hash_string(hash, package_version);
+	hash = hash_string(hash, th_manual);
+	hash = hash_string(hash, generated_comment);
+	hash = hash_string(hash, source_revision);
+	hash = hash_long(hash, colophon);
+	hash = hash_long(hash, no_generated_comment);
+	hash = hash_long(hash, title_asis);
+	hash = hash_string(hash, company);
+	hash = hash_string(hash, header_prefix);
+	hash = hash_string(hash, user_headerfile);
+	/* Not today's date, or every page would be regenerated every day */
+	hash = hash_string(hash, manpage_date);
+	hash = hash_string(hash, bugs_url);
+	hash = hash_string(hash, bugs_email);
+	hash = hash_string(hash, license_text);
+	hash = hash_string(hash, examples_dir);
+	hash = hash_string(hash, xml_encoding);
+	hash = hash_string(hash, page_hook);
+	hash = hash_string(hash, include_symbols);
+	hash = hash_string(hash, exclude_symbols);
+	hash = hash_string(hash, symbol_prefix);
+	hash = hash_string(hash, combine_prefix);
+	hash = hash_long(hash, exclude_static);
+	hash = hash_long(hash, exclude_inline);
+	hash = hash_long(hash, struct_pages);
+	hash = hash_long(hash, enum_pages);
+	hash = hash_long(hash, constants_page);
+	hash = hash_long(hash, constants_all);
+	qb_list_for_each(iter, &exported_symbols_list) {
+		struct exported_symbol *es = qb_list_entry(iter, struct exported_symbol, list);
+
+		hash = hash_string(hash, es->name);
+		hash = hash_string(hash, es->version);
+	}
+	if (symbol_info_map) {
+		qb_map_iter_t *map_iter = qb_map_iter_create(symbol_info_map);
+		struct symbol_info *sym;
+		const char *p;
+		void *data;
+
+		for (p = qb_map_iter_next(map_iter, &data); p; p = qb_map_iter_next(map_iter, &data)) {
+			sym = data;
+			hash = hash_string(hash, p);
+			hash = hash_string(hash, sym->version);
+			hash = hash_string(hash, sym->status);
+		}
+		qb_map_iter_free(map_iter);
+	}
+	if (symbol_db) {
+		qb_map_iter_t *map_iter = qb_map_iter_create(symbol_db);
+		struct db_symbol *ds;
+		const char *p;
+		void *data;
+
+		for (p = qb_map_iter_next(map_iter, &data); p; p = qb_map_iter_next(map_iter, &data)) {
+			ds = data;
+			hash = hash_string(hash, p);
+			hash = hash_string(hash, ds->header);
+			hash = hash_string(hash, ds->page);
+		}
+		qb_map_iter_free(map_iter);
+	}
+	if (external_symbols) {
+		qb_map_iter_t *map_iter = qb_map_iter_create(external_symbols);
+		struct external_symbol *ext;
+		const char *p;
+		void *data;
+
+		for (p = qb_map_iter_next(map_iter, &data); p; p = qb_map_iter_next(map_iter, &data)) {
+			ext = data;
+			hash = hash_string(hash, p);
+			hash = hash_string(hash, ext->file);
+			hash = hash_string(hash, ext->anchor);
+			hash = hash_string(hash, ext->location);
+		}
+		qb_map_iter_free(map_iter);
+	}
+	hash = hash_long(hash, sort_order);
+	hash = hash_long(hash, see_also_group);
+	qb_list_for_each(iter, &extra_see_also_list) {
+		struct extra_see_also *esa = qb_list_entry(iter, struct extra_see_also, list);
+
+		hash = hash_string(hash, esa->page);
+		hash = hash_string(hash, esa->name);
+		hash = hash_string(hash, esa->section);
+	}
+	if (member_groups || header_functions) {
+		char fname[PATH_MAX];
+
+		snprintf(fname, sizeof(fname), "%s/index.xml", xml_dir);
+		hash_file(&hash, fname);
+	}
+	return hash;
+}
+
+/* The hash of everything that goes into the page we're about to write */
+static uint64_t get_page_hash(void)
+{
+	uint64_t hash = hash_long(options_hash, xml_file_hash);
+	qb_map_iter_t *map_iter;
+	const char *refid;
+	void *data;
+
+	hash = hash_string(hash, headerfile);
+	hash = hash_string(hash, header_copyright);
+	hash = hash_string(hash, header_spdx);
+	hash = hash_string(hash, header_date);
+
+	/* Structures are read from their own XML files */
+	map_iter = qb_map_iter_create(used_structures_map);
+	for (refid = qb_map_iter_next(map_iter, &data); refid; refid = qb_map_iter_next(map_iter, &data)) {
+		char fname[PATH_MAX];
+
+		snprintf(fname, sizeof(fname), "%s/%s.xml", xml_dir, refid);
+		hash = hash_string(hash, refid);
+		hash_file(&hash, fname);
+	}
+	qb_map_iter_free(map_iter);
+	return hash;
+}
+
+/* The date on the page that page_is_current() last looked at, if it had the same hash */
+static char current_page_date[64];
+
+/* The date from a .TH line, returns 0 if it's some other line */
+static int read_page_date(const char *line)
+{
+	const char *p;
+	size_t len;
+	int i;
+
+	if (strncmp(line, ".TH ", 4) == 0) {
+		/* .TH <name> <section> <date> ... */
+		p = line + 4;
+		for (i = 0; i < 2; i++) {
+			p += strcspn(p, " \n");
+			p += strspn(p, " ");
+		}
+		if (*p == '"') {
+			p++;
+			len = strcspn(p, "\"\n");
+		} else {
+			len = strcspn(p, " \n");
+		}
+	} else {
+		return 0;
+	}
+	if (len < sizeof(current_page_date)) {
+		memcpy(current_page_date, p, len);
+		current_page_date[len] = '\0';
+	}
+	return 1;
+}
+
+/*
+ * Returns 1 if an existing page was generated from exactly the same input.
+ * If it was, its date is kept for page_date() even with --force.
+ */
+static int page_is_current(const char *manfilename, uint64_t page_hash)
+{
+	char line[256];
+	FILE *manfile;
+	int lineno = 0;
+	int same = 0;
+
+	current_page_date[0] = '\0';
+	manfile = fopen(manfilename, "r");
+	if (!manfile) {
+		return 0;
+	}
+	/* The hash is near the top of the file, and the .TH line just after it */
+	while (lineno++ < 6 && fgets(line, sizeof(line), manfile)) {
+		if (strncmp(line, HASH_COMMENT, strlen(HASH_COMMENT)) == 0) {
+			same = (strtoull(line + strlen(HASH_COMMENT), NULL, 16) == page_hash);
+			if (!same) {
 				break;
-			case 'I':
-				headerfile = optarg;
+			}
+		} else if (same && read_page_date(line)) {
+			break;
+		} else if (!same && lineno >= 3) {
+			break;
+		}
+	}
+	fclose(manfile);
+	return same && !force;
+}
+
+/*
+ * The date for the top of the page. Without -D a page that is written again
+ * from the same input, with --force say, keeps the date it had.
+ */
+static const char *page_date(void)
+{
+	const char *date = get_manpage_date();
+
+	if (!manpage_date && !no_dates && !header_date[0] && current_page_date[0]) {
+		return current_page_date;
+	}
+	return date;
+}
+
+/*
+ * Whether an existing file was written by doxygen2man: it starts with one
+ * of our comments or has a page hash near the top. -1 if there's no file.
+ */
+static int is_generated_file(const char *filename)
+{
+	char line[256];
+	FILE *f;
+	int lineno = 0;
+	int ours = 0;
+	size_t len = strlen(filename);
+
+	f = fopen(filename, "r");
+	if (!f) {
+		return -1;
+	}
+
+	/* A --text-pages page has nowhere to put a comment, it starts with NAME(3) and ends the line with it */
+	if (len > 4 && strcmp(filename + len - 4, ".txt") == 0) {
+		if (fgets(line, sizeof(line), f)) {
+			size_t title_len = strcspn(line, " \n");
+			size_t line_len = strcspn(line, "\n");
+
+			ours = title_len > 3 && line[title_len - 1] == ')' && memchr(line, '(', title_len) &&
+				line_len >= title_len && strncmp(line + line_len - title_len, line, title_len) == 0;
+		}
+		fclose(f);
+		return ours;
+	}
+
+	while (!ours && lineno++ < 3 && fgets(line, sizeof(line), f)) {
+		if ((lineno == 1 && strcmp(line, GENERATED_COMMENT) == 0) ||
+		    strncmp(line, HASH_COMMENT, strlen(HASH_COMMENT)) == 0) {
+			ours = 1;
+		}
+	}
+	fclose(f);
+	return ours;
+}
+
+static void print_generated_comment(FILE *manfile)
+{
+	if (no_generated_comment) {
+		return;
+	}
+	if (generated_comment) {
+		fprintf(manfile, ".\\\"  %s\n", generated_comment);
+	} else {
+		fprintf(manfile, GENERATED_COMMENT);
+	}
+}
+
+static void print_th(FILE *manfile, const char *name, const char *section, const char *date)
+{
+	/* After the hash, which has to be near the top */
+	if (source_revision) {
+		fprintf(manfile, ".\\\" Source revision: %s\n", source_revision);
+	}
+	/* An empty date (--no-dates) still needs its place on the line */
+	fprintf(manfile, ".TH %s %s %s \"%s\" \"%s\"\n", title_asis ? name : allcaps(name), section,
+		date[0] ? date : "\"\"", th_source_field(), th_manual ? th_manual : header);
+}
+
+/* The NAME section, 'others' are the rest of the functions on a --combine-by-prefix page */
+static void print_name_section(FILE *manfile, const char *name, struct qb_list_head *others, char *brief)
+{
+	struct combined_function *cf;
+	struct qb_list_head *iter;
+	int have_brief = brief && not_all_whitespace(brief);
+
+	fprintf(manfile, "%s NAME\n", dialect->section);
+	fprintf(manfile, "%s", name);
+	if (others) {
+		qb_list_for_each(iter, others) {
+			cf = qb_list_entry(iter, struct combined_function, list);
+			fprintf(manfile, ", %s", cf->name);
+		}
+	}
+	if (have_brief) {
+		fprintf(manfile, " \\- %s\n", brief);
+	} else {
+		fprintf(manfile, "\n");
+	}
+}
+
+/* An #include line, of one of the project's headers */
+static void print_include(FILE *manfile, const char *name, int local)
+{
+	fprintf(manfile, ".B #include %c%s%c\n", local ? '"' : '<', name, local ? '"' : '>');
+}
+
+/* The start of a SYNOPSIS, man leaves it in no-fill mode for the declarations */
+static void print_synopsis_include(FILE *manfile)
+{
+	fprintf(manfile, "%s SYNOPSIS\n", dialect->section);
+	fprintf(manfile, ".nf\n");
+	fprintf(manfile, ".B #include <%s%s>\n", header_prefix, headerfile);
+}
+
+/* --struct-pages and --enum-pages go in the "type" subsection of our section, eg 3type */
+static const char *type_section(void)
+{
+	static char section[16];
+
+	snprintf(section, sizeof(section), "%ctype", man_section[0]);
+	return section;
+}
+
+/* A page file name is <name>.<section>, and sections don't have dots in them */
+static const char *page_section(const char *page, size_t *namelen)
+{
+	const char *dot = strrchr(page, '.');
+
+	if (!dot) {
+		*namelen = strlen(page);
+		return "";
+	}
+	*namelen = dot - page;
+	return dot + 1;
+}
+
+/*
+ * The directory a page is written to. Everything is in the output directory
+ * unless --man-dirs puts a page from another section, like the
+ * --deprecated-page, in a man<section> directory of its own.
+ */
+static const char *page_dir(const char *page)
+{
+	static char dir[PATH_MAX * 2];
+	const char *section;
+	size_t len;
+
+	section = page_section(page, &len);
+	if (!man_base_dir || !section[0] || section[0] == man_section[0]) {
+		return output_dir;
+	}
+	snprintf(dir, sizeof(dir), "%s/man%c", man_base_dir, section[0]);
+	return dir;
+}
+
+/*
+ * A directory as the lists of pages (--manifest and the like) have it, ending
+ * in a '/'. With --output-archive the pages are put together in archive_dir,
+ * which is gone by the end of the run, so they get their path in the archive.
+ */
+static const char *listed_dir(const char *dir)
+{
+	static char listed[PATH_MAX * 2 + 2];
+	size_t len = strlen(archive_dir);
+
+	if (archive_dir[0] && strncmp(dir, archive_dir, len) == 0 &&
+	    (dir[len] == '\0' || dir[len] == '/')) {
+		dir += len;
+		while (*dir == '/') {
+			dir++;
+		}
+		if (!*dir) {
+			return "";
+		}
+	}
+	snprintf(listed, sizeof(listed), "%s%s", dir, dir[strlen(dir) - 1] == '/' ? "" : "/");
+	return listed;
+}
+
+/* --include-symbols and --exclude-symbols */
+static int symbol_wanted(const char *name)
+{
+	if (include_symbols && regexec(&include_symbols_re, name, 0, NULL, 0) != 0) {
+		return 0;
+	}
+	if (exclude_symbols && regexec(&exclude_symbols_re, name, 0, NULL, 0) == 0) {
+		return 0;
+	}
+	return 1;
+}
+
+/*
+ * static and inline functions in a header are often helpers for macros or
+ * other inlines rather than part of the API, --exclude-static and
+ * --exclude-inline leave them out. Some are the API, like qb_list_*().
+ */
+static int function_wanted(xmlNode *cur_node)
+{
+	char *is_static = get_attr(cur_node, "static");
+	char *is_inline = get_attr(cur_node, "inline");
+	int wanted = 1;
+
+	if (exclude_static && is_static && strcmp(is_static, "yes") == 0) {
+		wanted = 0;
+	}
+	if (exclude_inline && is_inline && strcmp(is_inline, "yes") == 0) {
+		wanted = 0;
+	}
+	free(is_static);
+	free(is_inline);
+	return wanted;
+}
+
+/*
+ * Symbols outside the --symbol-prefix namespace(s) aren't documented, but
+ * functions still get a mention in SEE ALSO.
+ */
+static int symbol_documented(const char *name)
+{
+	const char *prefix;
+	size_t len;
+
+	if (!symbol_wanted(name)) {
+		return 0;
+	}
+	if (!symbol_prefix) {
+		return 1;
+	}
+
+	/* A comma-separated list, eg "qb_,QB_" */
+	for (prefix = symbol_prefix; *prefix; prefix += len + (prefix[len] == ',')) {
+		len = strcspn(prefix, ",");
+		if (len && strncmp(name, prefix, len) == 0) {
+			return 1;
+		}
+	}
+	return 0;
+}
+
+/*
+ * Get 'filename' out of the way before writing a new page there. Returns 0
+ * to carry on, 1 if the page should be left alone, or -1 on error.
+ */
+static int replace_output_file(const char *filename, const char *symbol)
+{
+	char backupname[PATH_MAX + 1];
+	struct stat st;
+	int ours;
+
+	if (lstat(filename, &st) == -1) {
+		return 0;
+	}
+	/* A link from --alias-links is ours, even if the page it points at has gone */
+	ours = S_ISLNK(st.st_mode) ? 1 : is_generated_file(filename);
+	if (ours == -1) {
+		return 0;
+	}
+	if (no_clobber) {
+		log_msg(LOG_VERBOSE, "Not overwriting %s\n", filename);
+		return 1;
+	}
+	if (!ours && !force) {
+		report_error(D2M_ERR_IO, filename, symbol, "file was not generated by doxygen2man, use --force to overwrite it");
+		return -1;
+	}
+	if (backup) {
+		snprintf(backupname, sizeof(backupname), "%s~", filename);
+		if (rename(filename, backupname) == -1) {
+			report_error(D2M_ERR_IO, filename, symbol, "unable to make backup: %s", strerror(errno));
+			return -1;
+		}
+		return 0;
+	}
+
+	/* Writing through a link would change the page at the other end too */
+	if ((S_ISLNK(st.st_mode) || st.st_nlink > 1) && unlink(filename) == -1) {
+		report_error(D2M_ERR_IO, filename, symbol, "unable to remove link: %s", strerror(errno));
+		return -1;
+	}
+	return 0;
+}
+
+/*
+ * --write-threads. Pages are generated into memory and a pool of threads
+ * opens, writes and closes the files, so that a slow open() or close() (on
+ * NFS, say) doesn't hold up generating the next page. The threads only
+ * touch the queue and their own page, errors are reported when the pool is
+ * finished with, from here.
+ */
+struct pending_write {
+	FILE *f;
+	char *buf;
+	size_t len;
+	char *filename;
+	char *symbol;
+	int error;
+	struct qb_list_head list;
+};
+
+static QB_LIST_DECLARE(writes_open);
+static QB_LIST_DECLARE(writes_queued);
+static QB_LIST_DECLARE(writes_done);
+static pthread_mutex_t writes_lock = PTHREAD_MUTEX_INITIALIZER;
+static pthread_cond_t writes_cond = PTHREAD_COND_INITIALIZER;
+static pthread_t *write_pool;
+static int write_pool_size;
+static int writes_finishing;
+
+static void free_pending_write(struct pending_write *pw)
+{
+	free(pw->buf);
+	free(pw->filename);
+	free(pw->symbol);
+	free(pw);
+}
+
+static int open_pending_write(const char *filename, const char *symbol, FILE **f)
+{
+	struct pending_write *pw;
+
+	pw = calloc(1, sizeof(*pw));
+	if (!pw) {
+		report_error(D2M_ERR_IO, filename, symbol, "unable to open output file: %s", strerror(ENOMEM));
+		return -1;
+	}
+	pw->f = open_memstream(&pw->buf, &pw->len);
+	if (!pw->f) {
+		report_error(D2M_ERR_IO, filename, symbol, "unable to open output file: %s", strerror(errno));
+		free(pw);
+		return -1;
+	}
+	pw->filename = strdup(filename);
+	pw->symbol = strdup(symbol);
+	qb_list_add_tail(&pw->list, &writes_open);
+	*f = pw->f;
+	return 0;
+}
+
+/* Returns 0 or an errno */
+static int write_whole_file(const struct pending_write *pw)
+{
+	size_t done = 0;
+	ssize_t len;
+	int fd;
+
+	fd = open(pw->filename, O_WRONLY | O_CREAT | O_TRUNC, 0666);
+	if (fd == -1) {
+		return errno;
+	}
+	while (done < pw->len) {
+		len = write(fd, pw->buf + done, pw->len - done);
+		if (len == -1) {
+			if (errno == EINTR) {
+				continue;
+			}
+			len = errno;
+			close(fd);
+			return len;
+		}
+		done += len;
+	}
+	if (close(fd) == -1) {
+		return errno;
+	}
+	return 0;
+}
+
+static void *write_thread(void *arg)
+{
+	struct pending_write *pw;
+
+	(void)arg;
+	pthread_mutex_lock(&writes_lock);
+	while (1) {
+		while (qb_list_empty(&writes_queued) && !writes_finishing) {
+			pthread_cond_wait(&writes_cond, &writes_lock);
+		}
+		if (qb_list_empty(&writes_queued)) {
+			break;
+		}
+		pw = qb_list_first_entry(&writes_queued, struct pending_write, list);
+		qb_list_del(&pw->list);
+		pthread_mutex_unlock(&writes_lock);
+
+		pw->error = write_whole_file(pw);
+
+		pthread_mutex_lock(&writes_lock);
+		qb_list_add_tail(&pw->list, &writes_done);
+	}
+	pthread_mutex_unlock(&writes_lock);
+	return NULL;
+}
+
+/* The pool starts with the first page, so that each -j worker has its own */
+static int queue_pending_write(struct pending_write *pw)
+{
+	int err;
+
+	if (!write_pool) {
+		write_pool = calloc(write_threads, sizeof(pthread_t));
+		for (write_pool_size = 0; write_pool && write_pool_size < write_threads; write_pool_size++) {
+			err = pthread_create(&write_pool[write_pool_size], NULL, write_thread, NULL);
+			if (err) {
+				log_msg(LOG_VERBOSE, "Only started %d of %d write threads: %s\n",
+					write_pool_size, write_threads, strerror(err));
+				break;
+			}
+		}
+	}
+
+	/* Do it ourself if there are no threads to do it */
+	if (!write_pool_size) {
+		err = write_whole_file(pw);
+		if (err) {
+			report_error(D2M_ERR_IO, pw->filename, pw->symbol, "unable to write output file: %s", strerror(err));
+		}
+		free_pending_write(pw);
+		return err ? -1 : 0;
+	}
+
+	pthread_mutex_lock(&writes_lock);
+	qb_list_add_tail(&pw->list, &writes_queued);
+	pthread_cond_signal(&writes_cond);
+	pthread_mutex_unlock(&writes_lock);
+	return 0;
+}
+
+/* Wait for the pool to write everything queued, and report what it couldn't */
+static int finish_writes(void)
+{
+	struct pending_write *pw;
+	struct qb_list_head *iter;
+	struct qb_list_head *tmp;
+	int ret = 0;
+	int i;
+
+	if (!write_pool) {
+		return 0;
+	}
+	pthread_mutex_lock(&writes_lock);
+	writes_finishing = 1;
+	pthread_cond_broadcast(&writes_cond);
+	pthread_mutex_unlock(&writes_lock);
+	for (i = 0; i < write_pool_size; i++) {
+		pthread_join(write_pool[i], NULL);
+	}
+	free(write_pool);
+	write_pool = NULL;
+	write_pool_size = 0;
+	writes_finishing = 0;
+
+	qb_list_for_each_safe(iter, tmp, &writes_done) {
+		pw = qb_list_entry(iter, struct pending_write, list);
+		qb_list_del(&pw->list);
+		if (pw->error) {
+			report_error(D2M_ERR_IO, pw->filename, pw->symbol, "unable to write output file: %s",
+				     strerror(pw->error));
+			ret = -1;
+		}
+		free_pending_write(pw);
+	}
+	return ret;
+}
+
+/*
+ * Open a page for writing, unless there's something there we shouldn't
+ * touch. Returns 0 with *f set, 1 if the page should be left alone,
+ * or -1 on error.
+ */
+static int open_output_file(const char *filename, const char *symbol, const char *mode, FILE **f)
+{
+	int ret;
+
+	*f = NULL;
+	ret = replace_output_file(filename, symbol);
+	if (ret) {
+		return ret;
+	}
+
+	if (write_threads) {
+		return open_pending_write(filename, symbol, f);
+	}
+
+	*f = fopen(filename, mode);
+	if (!*f) {
+		report_error(D2M_ERR_IO, filename, symbol, "unable to open output file: %s", strerror(errno));
+		return -1;
+	}
+	return 0;
+}
+
+/*
+ * The other end of open_output_file(). With --write-threads the page is
+ * only in memory so far, it goes on the queue for the pool to write.
+ */
+static int close_output_file(FILE *f, const char *filename, const char *symbol)
+{
+	struct pending_write *pw = NULL;
+	struct qb_list_head *iter;
+
+	qb_list_for_each(iter, &writes_open) {
+		if (qb_list_entry(iter, struct pending_write, list)->f == f) {
+			pw = qb_list_entry(iter, struct pending_write, list);
+			break;
+		}
+	}
+	if (!pw) {
+		if (fclose(f)) {
+			report_error(D2M_ERR_IO, filename, symbol, "unable to write output file: %s", strerror(errno));
+			return -1;
+		}
+		return 0;
+	}
+
+	qb_list_del(&pw->list);
+	if (fclose(f)) {
+		report_error(D2M_ERR_IO, filename, symbol, "unable to write output file: %s", strerror(errno));
+		free_pending_write(pw);
+		return -1;
+	}
+	return queue_pending_write(pw);
+}
+
+/*
+ * Which @ingroup each member is in comes from index.xml, the file XML
+ * doesn't say. Keyed by member refid.
+ */
+static void read_member_groups(void)
+{
+	char fname[PATH_MAX];
+	xmlNode *rootdoc;
+	xmlNode *compound;
+	xmlNode *member;
+	xmlNode *name_node;
+	xmlDocPtr doc;
+	char *kind;
+	char *refid;
+
+	member_groups = qb_hashtable_create(10);
+	qb_map_notify_add(member_groups, NULL, map_free_entry, QB_MAP_NOTIFY_FREE, NULL);
+
+	snprintf(fname, sizeof(fname), "%s/index.xml", xml_dir);
+	doc = xmlReadFile(fname, xml_encoding, XML_PARSE_COMPACT | XML_PARSE_NOERROR | XML_PARSE_NOWARNING);
+	if (!doc) {
+		log_msg(LOG_VERBOSE, "No group information, can't read %s\n", fname);
+		return;
+	}
+	rootdoc = xmlDocGetRootElement(doc);
+
+	for (compound = rootdoc ? rootdoc->children : NULL; compound; compound = compound->next) {
+		if (compound->type != XML_ELEMENT_NODE || strcmp((char *)compound->name, "compound") != 0) {
+			continue;
+		}
+		kind = get_attr(compound, "kind");
+		name_node = find_child(compound, "name");
+		if (kind && strcmp(kind, "group") == 0 &&
+		    name_node && name_node->children && name_node->children->content) {
+			for (member = compound->children; member; member = member->next) {
+				if (member->type != XML_ELEMENT_NODE || strcmp((char *)member->name, "member") != 0) {
+					continue;
+				}
+				refid = get_attr(member, "refid");
+				if (refid && !qb_map_get(member_groups, refid)) {
+					qb_map_put(member_groups, refid, strdup((char *)name_node->children->content));
+				} else {
+					free(refid);
+				}
+			}
+		}
+		free(kind);
+	}
+	xmlFreeDoc(doc);
+}
+
+/*
+ * --references, the functions declared in the project's headers, from
+ * index.xml. Those are the ones that have pages, or will have when
+ * their header is processed, so they're the ones worth listing. Keyed by
+ * member refid.
+ */
+static void read_header_functions(void)
+{
+	char fname[PATH_MAX];
+	xmlNode *rootdoc;
+	xmlNode *compound;
+	xmlNode *member;
+	xmlNode *name_node;
+	xmlDocPtr doc;
+	size_t len;
+	char *kind;
+	char *refid;
+
+	header_functions = qb_hashtable_create(10);
+	qb_map_notify_add(header_functions, NULL, map_free_entry, QB_MAP_NOTIFY_FREE, NULL);
+
+	snprintf(fname, sizeof(fname), "%s/index.xml", xml_dir);
+	doc = xmlReadFile(fname, xml_encoding, XML_PARSE_COMPACT | XML_PARSE_NOERROR | XML_PARSE_NOWARNING);
+	if (!doc) {
+		log_msg(LOG_VERBOSE, "Can't read %s, USES and USED BY only list functions in the same header\n", fname);
+		return;
+	}
+	rootdoc = xmlDocGetRootElement(doc);
+
+	for (compound = rootdoc ? rootdoc->children : NULL; compound; compound = compound->next) {
+		if (compound->type != XML_ELEMENT_NODE || strcmp((char *)compound->name, "compound") != 0) {
+			continue;
+		}
+		kind = get_attr(compound, "kind");
+		name_node = find_child(compound, "name");
+		len = name_node && name_node->children && name_node->children->content ?
+			strlen((char *)name_node->children->content) : 0;
+		if (kind && strcmp(kind, "file") == 0 &&
+		    len > 2 && strcmp((char *)name_node->children->content + len - 2, ".h") == 0) {
+			for (member = compound->children; member; member = member->next) {
+				if (member->type != XML_ELEMENT_NODE || strcmp((char *)member->name, "member") != 0) {
+					continue;
+				}
+				free(kind);
+				kind = get_attr(member, "kind");
+				name_node = find_child(member, "name");
+				refid = get_attr(member, "refid");
+				if (refid && kind && strcmp(kind, "function") == 0 &&
+				    name_node && name_node->children && name_node->children->content &&
+				    !qb_map_get(header_functions, refid)) {
+					qb_map_put(header_functions, refid, strdup((char *)name_node->children->content));
+				} else {
+					free(refid);
+				}
+			}
+		}
+		free(kind);
+	}
+	xmlFreeDoc(doc);
+	log_msg(LOG_VERBOSE, "%zu functions declared in headers in %s\n", qb_map_count_get(header_functions), fname);
+}
+
+/* The @ingroup of a function, or the member group (@name) it's in, or NULL */
+static char *get_function_group(xmlNode *cur_node)
+{
+	xmlNode *header_node;
+	char *refid;
+	char *group = NULL;
+
+	if (member_groups) {
+		refid = get_attr(cur_node, "id");
+		if (refid && qb_map_get(member_groups, refid)) {
+			group = strdup(qb_map_get(member_groups, refid));
+		}
+		free(refid);
+		if (group) {
+			return group;
+		}
+	}
+
+	if (cur_node->parent && cur_node->parent->name &&
+	    strcmp((char *)cur_node->parent->name, "sectiondef") == 0) {
+		header_node = find_child(cur_node->parent, "header");
+		if (header_node && header_node->children && header_node->children->content) {
+			return strdup((char *)header_node->children->content);
+		}
+	}
+	return NULL;
+}
+
+static int compare_functions(const void *a, const void *b)
+{
+	const struct function_info *fa = *(struct function_info * const *)a;
+	const struct function_info *fb = *(struct function_info * const *)b;
+	int ret;
+
+	switch (sort_order) {
+	case SORT_ALPHA:
+		return strcmp(fa->name, fb->name);
+	case SORT_GROUP:
+		/* Ungrouped functions go at the end */
+		if (fa->group && fb->group) {
+			ret = strcmp(fa->group, fb->group);
+			if (ret) {
+				return ret;
+			}
+		} else if (fa->group || fb->group) {
+			return fa->group ? -1 : 1;
+		}
+		break;
+	case SORT_SOURCE:
+		break;
+	}
+	return (fa->seq > fb->seq) - (fa->seq < fb->seq);
+}
+
+/* The functions in function_map in --sort order, free() the array when done */
+static struct function_info **sorted_functions(size_t *count)
+{
+	struct function_info **functions;
+	qb_map_iter_t *map_iter;
+	const char *p;
+	void *data;
+	size_t n = 0;
+
+	*count = 0;
+	functions = calloc(qb_map_count_get(function_map) + 1, sizeof(*functions));
+	if (!functions) {
+		return NULL;
+	}
+	map_iter = qb_map_iter_create(function_map);
+	for (p = qb_map_iter_next(map_iter, &data); p; p = qb_map_iter_next(map_iter, &data)) {
+		functions[n++] = data;
+	}
+	qb_map_iter_free(map_iter);
+
+	qsort(functions, n, sizeof(*functions), compare_functions);
+	*count = n;
+	return functions;
+}
+
+/*
+ * Build the SEE ALSO text for all of the functions in the header just once.
+ * Each page then writes it out, less its own entry, rather than
+ * going through the whole list of functions again.
+ */
+static void build_see_also_functions(void)
+{
+	struct function_info **functions;
+	struct function_info *fi;
+	struct function_info *prev = NULL;
+	cstring_t text = cstring_alloc();
+	char entry[PATH_MAX];
+	size_t num;
+	size_t i;
+
+	see_also_len = 0;
+	see_also_names = 0;
+	functions = sorted_functions(&num);
+	see_also_order = functions;
+	see_also_count = num;
+	for (i = 0; i < num; i++) {
+		fi = functions[i];
+
+		/* A combined page is listed under the name of its first function */
+		if (fi->combined_with) {
+			continue;
+		}
+		see_also_names++;
+
+		if (prev) {
+			text = cstring_append_chars(text, ", ");
+			see_also_len += 2;
+			/* Leaving out a page that isn't last takes the comma with it */
+			prev->see_also_end = see_also_len;
+		}
+		snprintf(entry, sizeof(entry), "\\fI%s\\fR(%s)", fi->name, man_section);
+		text = cstring_append_chars(text, entry);
+		fi->see_also_start = see_also_len;
+		see_also_len += strlen(entry);
+		fi->see_also_end = see_also_len;
+		prev = fi;
+	}
+
+	/* ... and the last one takes the comma before it */
+	if (prev && prev->see_also_start > 0) {
+		prev->see_also_start -= 2;
+	}
+
+	see_also_functions = cstring_to_chars(text);
+	cstring_free(text);
+}
+
+/* --see-also-group, just the other functions in the same group. Returns how many */
+static int print_see_also_group(FILE *manfile, struct function_info *fi)
+{
+	struct function_info *other;
+	int count = 0;
+	size_t i;
+
+	for (i = 0; i < see_also_count; i++) {
+		other = see_also_order[i];
+		if (other == fi || other->combined_with ||
+		    !other->group || strcmp(other->group, fi->group) != 0) {
+			continue;
+		}
+		if (manfile) {
+			fprintf(manfile, "%s\\fI%s\\fR(%s)", count ? ", " : "", other->name, man_section);
+		}
+		count++;
+	}
+	return count;
+}
+
+/* A function that's alone in its group still gets the full list */
+static int see_also_by_group(struct function_info *fi)
+{
+	return see_also_group && fi->group && print_see_also_group(NULL, fi);
+}
+
+/* Returns how many entries it printed */
+static int print_see_also_functions(FILE *manfile, const char *name)
+{
+	struct function_info *fi = qb_map_get(function_map, name);
+
+	if (!see_also_functions || see_also_names == 0) {
+		return 0;
+	}
+
+	/* The header page isn't a function, so gets all of them */
+	if (!fi) {
+		fwrite(see_also_functions, 1, see_also_len, manfile);
+		return see_also_names;
+	}
+
+	if (see_also_by_group(fi)) {
+		return print_see_also_group(manfile, fi);
+	}
+	fwrite(see_also_functions, 1, fi->see_also_start, manfile);
+	fwrite(see_also_functions + fi->see_also_end, 1, see_also_len - fi->see_also_end, manfile);
+	return see_also_names - 1;
+}
+
+/*
+ * Whether 'name' in our own section is already in the SEE ALSO of
+ * 'pagename' (or is the page itself), so a --see-also for it can be
+ * left out
+ */
+static int see_also_listed(const char *pagename, const char *name)
+{
+	struct function_info *fi;
+	struct function_info *other;
+	struct example_info *ei;
+	struct qb_list_head *iter;
+
+	if (strcmp(name, pagename) == 0) {
+		return 1;
+	}
+	if (db_see_also) {
+		char page[PATH_MAX];
+
+		snprintf(page, sizeof(page), "%s.%s", name, man_section);
+		if (qb_map_get(db_see_also, page)) {
+			return 1;
+		}
+	}
+	if (examples_dir && !qb_list_empty(&examples_list)) {
+		if (strcmp(pagename, examples_pagename()) == 0) {
+			qb_list_for_each(iter, &examples_list) {
+				ei = qb_list_entry(iter, struct example_info, list);
+				if (strcmp(ei->pagename, name) == 0) {
+					return 1;
+				}
+			}
+			return 0;
+		}
+		if (strcmp(pagename, headerfile) == 0 && strcmp(name, examples_pagename()) == 0) {
+			return 1;
+		}
+	}
+	if (constants_page && !qb_list_empty(&defines_list) &&
+	    strcmp(pagename, headerfile) == 0 && strcmp(name, constants_pagename()) == 0) {
+		return 1;
+	}
+
+	other = qb_map_get(function_map, name);
+	if (!other) {
+		return 0;
+	}
+	fi = qb_map_get(function_map, pagename);
+	if (fi && see_also_by_group(fi)) {
+		return other->group && strcmp(other->group, fi->group) == 0;
+	}
+	return 1;
+}
+
+/* --symbol-db, the pages in other headers that the text referred to, after 'count' others */
+static int print_db_see_also(FILE *manfile, int count)
+{
+	qb_map_iter_t *map_iter;
+	const char *page;
+	const char *dot;
+	void *data;
+	int printed = 0;
+
+	map_iter = qb_map_iter_create(db_see_also);
+	for (page = qb_map_iter_next(map_iter, &data); page; page = qb_map_iter_next(map_iter, &data)) {
+		dot = strrchr(page, '.');
+		if (!dot) {
+			continue;
+		}
+		fprintf(manfile, "%s\\fI%.*s\\fR(%s)", count + printed ? ", " : "",
+			(int)(dot - page), page, dot + 1);
+		printed++;
+	}
+	qb_map_iter_free(map_iter);
+	return printed;
+}
+
+/*
+ * The --see-also entries for this page, after 'count' others. Pages in
+ * our own section (other headers' pages, mostly) come before the ones
+ * in other sections, and nothing is listed twice.
+ */
+static void print_extra_see_also(FILE *manfile, const char *pagename, int count)
+{
+	struct qb_list_head *iter;
+	struct qb_list_head *iter2;
+	struct extra_see_also *esa;
+	struct extra_see_also *earlier;
+	int same_section;
+	int pass;
+
+	for (pass = 0; pass < 2; pass++) {
+		qb_list_for_each(iter, &extra_see_also_list) {
+			esa = qb_list_entry(iter, struct extra_see_also, list);
+			if (esa->page && strcmp(esa->page, pagename) != 0) {
+				continue;
+			}
+			same_section = strcmp(esa->section, man_section) == 0;
+			if (same_section != (pass == 0)) {
+				continue;
+			}
+			if (same_section && see_also_listed(pagename, esa->name)) {
+				continue;
+			}
+
+			/* Only the first of any duplicates */
+			qb_list_for_each(iter2, &extra_see_also_list) {
+				if (iter2 == iter) {
+					break;
+				}
+				earlier = qb_list_entry(iter2, struct extra_see_also, list);
+				if ((!earlier->page || strcmp(earlier->page, pagename) == 0) &&
+				    strcmp(earlier->name, esa->name) == 0 &&
+				    strcmp(earlier->section, esa->section) == 0) {
+					break;
+				}
+			}
+			if (iter2 != iter) {
+				continue;
+			}
+
+			fprintf(manfile, "%s\\fI%s\\fR(%s)", count++ ? ", " : "", esa->name, esa->section);
+		}
+	}
+}
+
+/* Something wrong with a generated page, only an error with --strict */
+static void lint_problem(const char *location, const char *symbol, const char *fmt, ...)
+	__attribute__((format(printf, 3, 4)));
+
+static void lint_problem(const char *location, const char *symbol, const char *fmt, ...)
+{
+	char msg[1024];
+	va_list ap;
+
+	va_start(ap, fmt);
+	vsnprintf(msg, sizeof(msg), fmt, ap);
+	va_end(ap);
+	/* A problem with a page, say where in the header it came from */
+	if (source_file[0] && strncmp(location, source_file, strlen(source_file)) != 0) {
+		if (source_line) {
+			report_error(lint_strict ? D2M_ERR_LINT : D2M_ERR_DOC, location, symbol, "%s (from %s:%d)",
+				     msg, source_file, source_line);
+		} else {
+			report_error(lint_strict ? D2M_ERR_LINT : D2M_ERR_DOC, location, symbol, "%s (from %s)",
+				     msg, source_file);
+		}
+		return;
+	}
+	report_error(lint_strict ? D2M_ERR_LINT : D2M_ERR_DOC, location, symbol, "%s", msg);
+}
+
+static enum lint_level lint_level(enum lint_check check)
+{
+	if (lint_levels[check] != LINT_DEFAULT) {
+		return lint_levels[check];
+	}
+	/* Missing descriptions have always been warned about, and --spell-check asks for spelling */
+	if (check == LINT_UNDOCUMENTED || (check == LINT_SPELLING && spell_command)) {
+		return LINT_WARN;
+	}
+	if (!lint_docs) {
+		return LINT_OFF;
+	}
+	return lint_strict ? LINT_ERROR : LINT_WARN;
+}
+
+static int lint_wanted(enum lint_check check)
+{
+	return lint_level(check) != LINT_OFF;
+}
+
+/* Something wrong with a doxygen comment, how bad depends on the --lint-level of 'check' */
+static void doc_problem(enum lint_check check, xmlNode *cur_node, const char *symbol, const char *fmt, ...)
+	__attribute__((format(printf, 4, 5)));
+
+static void doc_problem(enum lint_check check, xmlNode *cur_node, const char *symbol, const char *fmt, ...)
+{
+	char location[PATH_MAX + 16];
+	char msg[1024];
+	va_list ap;
+
+	if (!lint_wanted(check)) {
+		return;
+	}
+	va_start(ap, fmt);
+	vsnprintf(msg, sizeof(msg), fmt, ap);
+	va_end(ap);
+	report_error(lint_level(check) == LINT_ERROR ? D2M_ERR_LINT : D2M_ERR_DOC,
+		     node_location(cur_node, location, sizeof(location)), symbol, "%s", msg);
+}
+
+/*
+ * --lint-docs, the \param names in the comment against the function's own
+ * parameters. get_param_info() leaves a documented name that isn't one of
+ * them in the list without a type.
+ */
+static void lint_params(xmlNode *cur_node, const char *name, struct qb_list_head *list)
+{
+	struct qb_list_head *iter;
+	struct param_info *pi;
+
+	qb_list_for_each(iter, list) {
+		pi = qb_list_entry(iter, struct param_info, list);
+
+		if (!pi->paramtype) {
+			doc_problem(LINT_PARAMS, cur_node, name, "\\param %s is not one of the parameters", pi->paramname);
+		} else if (pi->paramname[0] && !pi->paramdesc) {
+			/* (void) and ... don't have names */
+			doc_problem(LINT_PARAMS, cur_node, name, "parameter %s is not documented", pi->paramname);
+		}
+	}
+}
+
+/*
+ * --lint-docs, a function that returns something should say what (with
+ * \return or \retval), and a void one shouldn't
+ */
+static void lint_return(xmlNode *cur_node, const char *name, const char *returntext, struct qb_list_head *retvals)
+{
+	xmlNode *this_tag;
+	xmlChar *content = NULL;
+	const char *type;
+	size_t len;
+	int is_void = 0;
+
+	for (this_tag = cur_node->children; this_tag; this_tag = this_tag->next) {
+		if (this_tag->type == XML_ELEMENT_NODE && strcmp((char *)this_tag->name, "type") == 0) {
+			content = xmlNodeGetContent(this_tag);
+			break;
+		}
+	}
+	/* The last word, so that "static void" is void too, but "void *" isn't */
+	type = content ? (char *)content : "";
+	len = strlen(type);
+	while (len > 0 && isspace(type[len - 1])) {
+		len--;
+	}
+	if (len >= 4 && strncmp(type + len - 4, "void", 4) == 0 &&
+	    (len == 4 || isspace(type[len - 5]))) {
+		is_void = 1;
+	}
+	xmlFree(content);
+
+	if (!is_void && !returntext && qb_list_empty(retvals)) {
+		doc_problem(LINT_RETURN, cur_node, name, "return value is not documented");
+	}
+	if (is_void && (returntext || !qb_list_empty(retvals))) {
+		doc_problem(LINT_RETURN, cur_node, name, "void function documents a return value");
+	}
+}
+
+/* --lint-docs, a @deprecated should say what to use instead */
+static void lint_deprecated(xmlNode *cur_node, const char *name)
+{
+	xmlNode *xref;
+	xmlNode *desc;
+	xmlChar *content = NULL;
+	char *replacement;
+	char *version;
+
+	xref = find_xrefsect(cur_node, "deprecated");
+	if (!xref) {
+		return;
+	}
+	desc = find_child(xref, "xrefdescription");
+	if (desc) {
+		content = xmlNodeGetContent(desc);
+	}
+	parse_deprecation(content ? (char *)content : "", &replacement, &version);
+	if (!replacement) {
+		doc_problem(LINT_DEPRECATED, cur_node, name, "deprecated without saying what to use instead");
+	}
+	free(replacement);
+	free(version);
+	if (content) {
+		xmlFree(content);
+	}
+}
+
+/* The words in a description worth checking, leaving out code, names and numbers */
+static cstring_t append_spell_text(cstring_t buffer, xmlNode *node)
+{
+	static const char *skip_tags[] = {"programlisting", "computeroutput", "verbatim", "ref", "ulink",
+					  "parametername", "xreftitle", NULL};
+	char word[256];
+	xmlNode *this_node;
+	const char *c;
+	size_t len;
+	size_t i;
+
+	for (this_node = node->children; this_node; this_node = this_node->next) {
+		if (this_node->type == XML_TEXT_NODE && this_node->content) {
+			for (c = (char *)this_node->content; *c; c += len) {
+				c += strspn(c, " \t\r\n");
+				len = strcspn(c, " \t\r\n");
+				/* Identifiers, numbers, paths, options and the like aren't words */
+				for (i = 0; i < len && !strchr("_0123456789()/@:<>#=*&", c[i]); i++) {
+				}
+				if (len && i == len && c[0] != '-' && len < sizeof(word)) {
+					snprintf(word, sizeof(word), "%.*s ", (int)len, c);
+					buffer = cstring_append_chars(buffer, word);
+				}
+			}
+			continue;
+		}
+		if (this_node->type != XML_ELEMENT_NODE) {
+			continue;
+		}
+		for (i = 0; skip_tags[i] && strcmp((char *)this_node->name, skip_tags[i]) != 0; i++) {
+		}
+		if (!skip_tags[i]) {
+			buffer = append_spell_text(buffer, this_node);
+		}
+	}
+	return buffer;
+}
+
+/* --spell-check, save the brief and detailed descriptions of a symbol for check_spelling() */
+static void add_spell_text(xmlNode *cur_node, const char *symbol)
+{
+	struct spell_text *st;
+	xmlNode *this_tag;
+	cstring_t text = cstring_alloc();
+	char *words;
+
+	for (this_tag = cur_node->children; this_tag; this_tag = this_tag->next) {
+		if (this_tag->type == XML_ELEMENT_NODE &&
+		    (strcmp((char *)this_tag->name, "briefdescription") == 0 ||
+		     strcmp((char *)this_tag->name, "detaileddescription") == 0)) {
+			text = append_spell_text(text, this_tag);
+		}
+	}
+
+	words = cstring_to_chars(text);
+	cstring_free(text);
+	if (!symbol || !words || !not_all_whitespace(words)) {
+		free(words);
+		return;
+	}
+
+	st = calloc(1, sizeof(struct spell_text));
+	if (!st) {
+		free(words);
+		return;
+	}
+	st->node = cur_node;
+	st->symbol = strdup(symbol);
+	st->text = words;
+	qb_list_add_tail(&st->list, &spell_list);
+}
+
+/* The checks on a function's doxygen comment */
+static void lint_function(xmlNode *cur_node, const char *name, char *brief, const char *detailed,
+			  const char *returntext, struct qb_list_head *params, struct qb_list_head *retvals)
+{
+	/* Make sure function has a doxygen description */
+	if (!detailed) {
+		doc_problem(LINT_UNDOCUMENTED, cur_node, name, "no detailed description - please fix this");
+	}
+	if (!brief || !not_all_whitespace(brief)) {
+		doc_problem(LINT_BRIEF, cur_node, name, "no brief description");
+	}
+	if (lint_wanted(LINT_PARAMS)) {
+		lint_params(cur_node, name, params);
+	}
+	if (lint_wanted(LINT_RETURN)) {
+		lint_return(cur_node, name, returntext, retvals);
+	}
+	if (lint_wanted(LINT_DEPRECATED)) {
+		lint_deprecated(cur_node, name);
+	}
+	if (spell_command && lint_wanted(LINT_SPELLING)) {
+		add_spell_text(cur_node, name);
+	}
+}
+
+/* 'word' on its own in 'text', not part of a longer one */
+static int has_word(const char *text, const char *word)
+{
+	size_t len = strlen(word);
+	const char *c;
+
+	for (c = strstr(text, word); c; c = strstr(c + 1, word)) {
+		if ((c == text || !isalpha((unsigned char)c[-1])) && !isalpha((unsigned char)c[len])) {
+			return 1;
+		}
+	}
+	return 0;
+}
+
+static void free_spell_list(void)
+{
+	struct qb_list_head *iter;
+	struct qb_list_head *tmp;
+	struct spell_text *st;
+
+	qb_list_for_each_safe(iter, tmp, &spell_list) {
+		st = qb_list_entry(iter, struct spell_text, list);
+		qb_list_del(&st->list);
+		free(st->symbol);
+		free(st->text);
+		free(st);
+	}
+}
+
+/*
+ * --spell-check, all of the descriptions in the header go through the
+ * command in one go. It prints the words it doesn't know, one per line
+ * (like 'aspell list' or 'hunspell -l'), and each one is reported for
+ * every symbol whose description has it.
+ */
+static void check_spelling(void)
+{
+	struct qb_list_head *iter;
+	struct spell_text *st;
+	qb_map_t *misspelt;
+	qb_map_iter_t *map_iter;
+	const char *word;
+	char *line = NULL;
+	size_t linelen = 0;
+	void *data;
+	FILE *in;
+	FILE *out;
+	int pipefd[2];
+	int status;
+	pid_t pid;
+
+	if (qb_list_empty(&spell_list)) {
+		return;
+	}
+
+	in = tmpfile();
+	if (!in || pipe(pipefd) == -1) {
+		report_error(D2M_ERR_DOC, headerfile, NULL, "unable to run spell check: %s", strerror(errno));
+		if (in) {
+			fclose(in);
+		}
+		free_spell_list();
+		return;
+	}
+	qb_list_for_each(iter, &spell_list) {
+		st = qb_list_entry(iter, struct spell_text, list);
+		fprintf(in, "%s\n", st->text);
+	}
+	fflush(in);
+	rewind(in);
+
+	fflush(stdout);
+	pid = fork();
+	if (pid == 0) {
+		dup2(fileno(in), STDIN_FILENO);
+		dup2(pipefd[1], STDOUT_FILENO);
+		close(pipefd[0]);
+		close(pipefd[1]);
+		execl("/bin/sh", "sh", "-c", spell_command, NULL);
+		_exit(127);
+	}
+	close(pipefd[1]);
+	fclose(in);
+
+	/* Sorted, so that each symbol's words come out in the same order every time */
+	misspelt = qb_skiplist_create();
+	qb_map_notify_add(misspelt, NULL, map_free_entry, QB_MAP_NOTIFY_FREE, NULL);
+	out = fdopen(pipefd[0], "r");
+	while (out && getline(&line, &linelen, out) != -1) {
+		line[strcspn(line, " \t\r\n")] = '\0';
+		if (line[0] && !qb_map_get(misspelt, line) &&
+		    !(spell_words && qb_map_get(spell_words, line))) {
+			qb_map_put(misspelt, strdup(line), strdup(line));
+		}
+	}
+	free(line);
+	if (out) {
+		fclose(out);
+	} else {
+		close(pipefd[0]);
+	}
+
+	if (pid == -1 || waitpid(pid, &status, 0) == -1 ||
+	    !WIFEXITED(status) || WEXITSTATUS(status) != 0) {
+		report_error(D2M_ERR_DOC, headerfile, NULL, "spell check command failed, descriptions not checked");
+	} else {
+		qb_list_for_each(iter, &spell_list) {
+			st = qb_list_entry(iter, struct spell_text, list);
+
+			map_iter = qb_map_iter_create(misspelt);
+			for (word = qb_map_iter_next(map_iter, &data); word; word = qb_map_iter_next(map_iter, &data)) {
+				if (has_word(st->text, word)) {
+					doc_problem(LINT_SPELLING, st->node, st->symbol, "possible misspelling '%s'", word);
+				}
+			}
+			qb_map_iter_free(map_iter);
+		}
+	}
+	qb_map_destroy(misspelt);
+	free_spell_list();
+}
+
+/*
+ * Check a generated man page for the things that most often go wrong
+ * with the output: fonts left switched on at the end of a paragraph,
+ * an empty NAME section and (if check_refs is set) SEE ALSO references
+ * to pages we didn't write. This is used by --selftest, and by --lint-output
+ * when mandoc isn't available.
+ */
+static int check_page(const char *dir, const char *pagename, const char *symbol, int check_refs)
+{
+	char location[PATH_MAX + 16];
+	char filename[PATH_MAX];
+	char line[4096];
+	FILE *page;
+	int lineno = 0;
+	int errors = 0;
+	int in_name = 0;
+	int name_found = 0;
+	int in_see_also = 0;
+	char cur_font = 'R';
+	char prev_font = 'R';
+
+	if (dir) {
+		snprintf(filename, sizeof(filename), "%s/%s", dir, pagename);
+	} else {
+		snprintf(filename, sizeof(filename), "%s", pagename);
+	}
+	page = fopen(filename, "r");
+	if (!page) {
+		report_error(D2M_ERR_IO, filename, symbol, "cannot open page: %s", strerror(errno));
+		return 1;
+	}
+
+	while (fgets(line, sizeof(line), page)) {
+		char *c;
+
+		lineno++;
+		line[strcspn(line, "\n")] = '\0';
+
+		/* The man macros reset the font at the start of every paragraph */
+		if (strncmp(line, ".SH", 3) == 0 || strncmp(line, ".SS", 3) == 0 ||
+		    strncmp(line, ".PP", 3) == 0 || strncmp(line, ".TH", 3) == 0) {
+			if (cur_font != 'R') {
+				snprintf(location, sizeof(location), "%s:%d", filename, lineno);
+				lint_problem(location, symbol, "font \\f%c still active at paragraph break", cur_font);
+				errors++;
+			}
+			cur_font = prev_font = 'R';
+		}
+
+		if (strncmp(line, ".SH", 3) == 0) {
+			if (in_name && !name_found) {
+				snprintf(location, sizeof(location), "%s:%d", filename, lineno);
+				lint_problem(location, symbol, "empty NAME section");
+				errors++;
+			}
+			in_name = (strstr(line, "NAME") != NULL);
+			in_see_also = (strstr(line, "SEE ALSO") != NULL);
+			continue;
+		}
+		if (in_name && line[0] != '.' && not_all_whitespace(line)) {
+			name_found = 1;
+		}
+
+		for (c = line; *c; c++) {
+			if (*c != '\\') {
+				continue;
+			}
+			c++;
+			if (*c == 'f' && c[1]) {
+				char new_font = c[1] == 'P' ? prev_font : c[1];
+
+				prev_font = cur_font;
+				cur_font = new_font;
+				c++;
+			} else if (*c == '\0') {
+				break;
+			}
+		}
+
+		/* Every SEE ALSO entry should be a page we generated */
+		if (in_see_also && check_refs) {
+			char *ref = line;
+			char *end;
+
+			while ((ref = strstr(ref, "\\fI"))) {
+				ref += 3;
+				end = strstr(ref, "\\fR(");
+				if (end) {
+					char refpage[PATH_MAX];
+					struct stat st;
+					int sec_len = strcspn(end + 4, ")");
+
+					snprintf(refpage, sizeof(refpage), "%s/%.*s.%.*s", dir,
+						 (int)(end - ref), ref, sec_len, end + 4);
+					if (stat(refpage, &st) == -1) {
+						snprintf(location, sizeof(location), "%s:%d", filename, lineno);
+						lint_problem(location, symbol, "SEE ALSO reference %.*s cannot be resolved",
+							     (int)(end - ref), ref);
+						errors++;
+					}
+				}
+			}
+		}
+	}
+	if (cur_font != 'R') {
+		lint_problem(filename, symbol, "font \\f%c still active at end of page", cur_font);
+		errors++;
+	}
+	if (in_name && !name_found) {
+		lint_problem(filename, symbol, "empty NAME section");
+		errors++;
+	}
+	if (lineno == 0) {
+		lint_problem(filename, symbol, "page is empty");
+		errors++;
+	}
+
+	fclose(page);
+	return errors;
+}
+
+/* Look for a program in $PATH */
+static int find_program(const char *name)
+{
+	char path[PATH_MAX];
+	const char *dir;
+	const char *end;
+
+	for (dir = getenv("PATH"); dir && *dir; dir = *end ? end + 1 : end) {
+		end = strchrnul(dir, ':');
+		snprintf(path, sizeof(path), "%.*s/%s", (int)(end - dir), dir, name);
+		if (access(path, X_OK) == 0) {
+			return 1;
+		}
+	}
+	return 0;
+}
+
+/* Run mandoc -Tlint on a page and pass on anything it says */
+static int mandoc_lint(const char *filename, const char *symbol)
+{
+	char line[1024];
+	FILE *output;
+	int fds[2];
+	int status;
+	int problems = 0;
+	pid_t pid;
+
+	if (pipe(fds)) {
+		report_error(D2M_ERR_IO, filename, symbol, "unable to run mandoc: %s", strerror(errno));
+		return -1;
+	}
+	fflush(stdout);
+	pid = fork();
+	if (pid == -1) {
+		report_error(D2M_ERR_IO, filename, symbol, "unable to run mandoc: %s", strerror(errno));
+		close(fds[0]);
+		close(fds[1]);
+		return -1;
+	}
+	if (pid == 0) {
+		dup2(fds[1], STDOUT_FILENO);
+		dup2(fds[1], STDERR_FILENO);
+		close(fds[0]);
+		close(fds[1]);
+		execlp("mandoc", "mandoc", "-Tlint", "-W", "warning", filename, NULL);
+		_exit(127);
+	}
+	close(fds[1]);
+
+	output = fdopen(fds[0], "r");
+	while (output && fgets(line, sizeof(line), output)) {
+		line[strcspn(line, "\n")] = '\0';
+		lint_problem(filename, symbol, "%s", line);
+		problems++;
+	}
+	if (output) {
+		fclose(output);
+	} else {
+		close(fds[0]);
+	}
+	waitpid(pid, &status, 0);
+	return problems;
+}
+
+/* --lint-output, check a page we've just written */
+static void lint_page(const char *filename, const char *symbol)
+{
+	static int have_mandoc = -1;
+
+	if (have_mandoc == -1) {
+		have_mandoc = find_program("mandoc");
+		if (!have_mandoc) {
+			log_msg(LOG_VERBOSE, "mandoc not found, using the built-in checks\n");
+		}
+	}
+
+	if (have_mandoc) {
+		mandoc_lint(filename, symbol);
+	} else {
+		check_page(NULL, filename, symbol, 0);
+	}
+}
+
+/* A space that isn't escaped with a backslash, so the line can be broken there */
+static int breakable_space(const char *line, const char *p)
+{
+	int backslashes = 0;
+
+	while (p - backslashes > line && p[-backslashes - 1] == '\\') {
+		backslashes++;
+	}
+	return *p == ' ' && (backslashes % 2) == 0;
+}
+
+/*
+ * Write one line of filled text, broken at spaces so that the pieces fit in
+ * wrap_width where they can. A word longer than that is left on a line of its
+ * own. troff joins the lines up again so the formatted page doesn't change.
+ */
+static void wrap_line(FILE *out, const char *line)
+{
+	const char *start = line;
+	const char *brk;
+	const char *p;
+
+	while ((int)strlen(start) > wrap_width) {
+		brk = NULL;
+		for (p = start + 1; *p; p++) {
+			if (!breakable_space(start, p) || p[-1] == ' ') {
+				continue;
+			}
+			if (brk && p - start > wrap_width) {
+				break;
+			}
+			brk = p;
+			if (p - start >= wrap_width) {
+				break;
+			}
+		}
+		if (!brk) {
+			break;
+		}
+		for (p = brk; *p == ' '; p++)
+			;
+		if (*p == '\0') {
+			break;
+		}
+		fprintf(out, "%.*s\n", (int)(brk - start), start);
+		start = p;
+		/* Don't let the rest of the line turn into a request */
+		if (*start == '.' || *start == '\'') {
+			fprintf(out, "\\&");
+		}
+	}
+	fprintf(out, "%s\n", start);
+}
+
+/*
+ * --wrap-width, break up the long lines of text in a page we've just written.
+ * doxygen gives us each paragraph as one line, which is hard to read in a diff
+ * of the pages. Requests, no-fill blocks (code listings and tables, up to the
+ * next section if nothing ends them sooner) and lines starting with a space are
+ * copied as they are, those mean something to troff.
+ */
+static int wrap_page(const char *manfilename, const char *name)
+{
+	char wrapfilename[PATH_MAX + 8];
+	char *line = NULL;
+	size_t linelen = 0;
+	ssize_t len;
+	int nofill = 0;
+	FILE *in;
+	FILE *out;
+
+	snprintf(wrapfilename, sizeof(wrapfilename), "%s.wrap", manfilename);
+	in = fopen(manfilename, "r");
+	if (!in) {
+		report_error(D2M_ERR_IO, manfilename, name, "unable to read page to wrap it: %s", strerror(errno));
+		return -1;
+	}
+	out = fopen(wrapfilename, "w");
+	if (!out) {
+		report_error(D2M_ERR_IO, wrapfilename, name, "unable to create output file: %s", strerror(errno));
+		fclose(in);
+		return -1;
+	}
+
+	while ((len = getline(&line, &linelen, in)) != -1) {
+		if (len && line[len - 1] == '\n') {
+			line[--len] = '\0';
+		}
+		if (line[0] == '.' || line[0] == '\'') {
+			if (strncmp(line, ".nf", 3) == 0 || strncmp(line, ".EX", 3) == 0 ||
+			    strncmp(line, ".TS", 3) == 0 || strncmp(line, ".Bd", 3) == 0) {
+				nofill = 1;
+			} else if (strncmp(line, ".fi", 3) == 0 || strncmp(line, ".EE", 3) == 0 ||
+				   strncmp(line, ".TE", 3) == 0 || strncmp(line, ".SH", 3) == 0 ||
+				   strncmp(line, ".SS", 3) == 0 || strncmp(line, ".Ed", 3) == 0 ||
+				   strncmp(line, ".Sh", 3) == 0 || strncmp(line, ".Ss", 3) == 0) {
+				nofill = 0;
+			}
+			fprintf(out, "%s\n", line);
+		} else if (nofill || line[0] == ' ' || len <= wrap_width) {
+			fprintf(out, "%s\n", line);
+		} else {
+			wrap_line(out, line);
+		}
+	}
+	free(line);
+	fclose(in);
+
+	if (fclose(out)) {
+		report_error(D2M_ERR_IO, wrapfilename, name, "unable to write output file: %s", strerror(errno));
+		unlink(wrapfilename);
+		return -1;
+	}
+	if (rename(wrapfilename, manfilename) == -1) {
+		report_error(D2M_ERR_IO, manfilename, name, "unable to replace page: %s", strerror(errno));
+		unlink(wrapfilename);
+		return -1;
+	}
+	return 0;
+}
+
+/*
+ * -a, the pages as man would show them in a terminal: the fonts in bold and
+ * underlined (only if stdout is a terminal), the text filled to its width and
+ * the lot sent through $PAGER. It's a preview, so only the main sections are
+ * there.
+ */
+#define TERM_INDENT 7
+
+enum term_font {
+	TERM_ROMAN,
+	TERM_BOLD,
+	TERM_UNDERLINE,
+	TERM_BOLD_UNDERLINE,
+};
+
+static const char *term_font_codes[] = {"\033[0m", "\033[0;1m", "\033[0;4m", "\033[0;1;4m"};
+static enum term_font term_font = TERM_ROMAN;
+static enum term_font term_prev_font = TERM_ROMAN;
+static FILE *term_out;
+
+static void term_set_font(enum term_font font)
+{
+	term_prev_font = term_font;
+	term_font = font;
+	if (term_fonts) {
+		fputs(term_font_codes[font], term_out);
+	}
+}
+
+/* The font a \f escape at 'c' changes to, 'c' is moved past it */
+static enum term_font term_font_escape(const char **c)
+{
+	const char *s = *c + 2;
+	const char *font;
+	size_t len;
+
+	if (*s == '(') {
+		font = s + 1;
+		len = strnlen(font, 2);
+		*c = font + len;
+	} else if (*s == '[') {
+		font = s + 1;
+		len = strcspn(font, "]");
+		*c = font + len + (font[len] == ']');
+	} else {
+		font = s;
+		len = *s ? 1 : 0;
+		*c = s + len;
+	}
+
+	if (len == 0 || (len == 1 && *font == 'P')) {
+		return term_prev_font;
+	}
+	if (len == 1 && *font == 'B') {
+		return TERM_BOLD;
+	}
+	if (len == 1 && *font == 'I') {
+		return TERM_UNDERLINE;
+	}
+	if (len == 2 && strncmp(font, "BI", 2) == 0) {
+		return TERM_BOLD_UNDERLINE;
+	}
+	return TERM_ROMAN;
+}
+
+/* Text with the font escapes in it turned into the terminal's */
+static void term_put(const char *text)
+{
+	const char *c = text;
+
+	while (*c) {
+		if (c[0] == '\\' && c[1] == 'f') {
+			term_set_font(term_font_escape(&c));
+		} else if (c[0] == '\\' && c[1] == ' ') {
+			fputc(' ', term_out);
+			c += 2;
+		} else {
+			fputc(*c++, term_out);
+		}
+	}
+}
+
+/* The font is switched off at the end of each line, and back on after the indent */
+static void term_newline(void)
+{
+	if (term_fonts && term_font != TERM_ROMAN) {
+		fputs(term_font_codes[TERM_ROMAN], term_out);
+	}
+	fputc('\n', term_out);
+}
+
+static void term_indent(int indent)
+{
+	fprintf(term_out, "%*s", indent, "");
+	if (term_fonts && term_font != TERM_ROMAN) {
+		fputs(term_font_codes[term_font], term_out);
+	}
+}
+
+/*
+ * One paragraph, filled to term_width starting at 'indent', then at 'hang'
+ * on the following lines. An escaped space (\ ) holds two words together.
+ */
+static void term_fill(const char *text, int indent, int hang)
+{
+	const char *c = text;
+	const char *start;
+	char *word;
+	int margin = indent;
+	int col = indent;
+	int width;
+
+	term_font = term_prev_font = TERM_ROMAN;
+	term_indent(indent);
+	while (*c) {
+		while (*c && isspace((unsigned char)*c)) {
+			c++;
+		}
+		if (!*c) {
+			break;
+		}
+		for (start = c; *c && !(isspace((unsigned char)*c) && (*c != ' ' || breakable_space(text, c))); c++)
+			;
+		word = strndup(start, c - start);
+		if (!word) {
+			break;
+		}
+		width = display_width(word);
+		if (col > margin && col + 1 + width > term_width) {
+			term_newline();
+			margin = col = hang;
+			term_indent(hang);
+		} else if (col > margin) {
+			fputc(' ', term_out);
+			col++;
+		}
+		term_put(word);
+		col += width;
+		free(word);
+	}
+	term_newline();
+	term_font = TERM_ROMAN;
+}
+
+/*
+ * A description, as man_print_long_string() would lay it out: each line is a
+ * paragraph, apart from the code blocks which are left as they are
+ */
+static void term_text(const char *text, int indent)
+{
+	const char *line;
+	const char *next;
+	char *tmp;
+	int nofill = 0;
+	int printed = 0;
+
+	for (line = text; *line; line = next) {
+		next = line + strcspn(line, "\n");
+		tmp = strndup(line, next - line);
+		if (*next) {
+			next++;
+		}
+		if (!tmp) {
+			break;
+		}
+
+		if (tmp[0] == '.') {
+			/* The only requests in there are for code blocks */
+			if (strncmp(tmp, ".nf", 3) == 0 || strncmp(tmp, ".EX", 3) == 0 ||
+			    strncmp(tmp, ".Bd", 3) == 0) {
+				if (printed) {
+					fputc('\n', term_out);
+				}
+				nofill = 1;
+				printed = 0;
+			} else if (strncmp(tmp, ".fi", 3) == 0 || strncmp(tmp, ".EE", 3) == 0 ||
+				   strncmp(tmp, ".Ed", 3) == 0) {
+				nofill = 0;
+				printed = 1;
+			}
+		} else if (nofill) {
+			term_font = term_prev_font = TERM_ROMAN;
+			term_indent(indent);
+			term_put(tmp);
+			term_newline();
+			printed = 1;
+		} else if (not_all_whitespace(tmp)) {
+			if (printed) {
+				fputc('\n', term_out);
+			}
+			term_fill(tmp, indent, indent);
+			printed = 1;
+		}
+		free(tmp);
+	}
+}
+
+static void term_section(const char *title)
+{
+	fputc('\n', term_out);
+	term_set_font(TERM_BOLD);
+	fputs(title, term_out);
+	term_set_font(TERM_ROMAN);
+	fputc('\n', term_out);
+}
+
+/* Append 'text' with its spaces escaped, so it isn't broken over two lines */
+static cstring_t append_unbroken(cstring_t buffer, const char *text)
+{
+	const char *c;
+	char chunk[2] = {0, 0};
+
+	for (c = text; *c; c++) {
+		if (*c == ' ') {
+			buffer = cstring_append_chars(buffer, "\\ ");
+		} else {
+			chunk[0] = *c;
+			buffer = cstring_append_chars(buffer, chunk);
+		}
+	}
+	return buffer;
+}
+
+/* The declaration, with the parameters lined up after the ( if there's room */
+static void term_synopsis(const char *def, struct qb_list_head *param_list)
+{
+	const char *fn = def_name(def);
+	struct qb_list_head *iter;
+	struct param_info *pi;
+	cstring_t line = cstring_alloc();
+	char *text;
+	char *type_end;
+	size_t visible;
+	int len = fn - def;
+	int hang;
+
+	while (len > 0 && def[len - 1] == ' ') {
+		len--;
+	}
+	if (len) {
+		char *type = strndup(def, len);
+
+		if (type) {
+			line = cstring_append_chars(line, styles[STYLE_TYPE]);
+			line = append_unbroken(line, type);
+			line = cstring_append_chars(line, "\\fR");
+			free(type);
+		}
+		if (len < fn - def) {
+			line = cstring_append_chars(line, " ");
+		}
+	}
+	line = cstring_append_chars(line, styles[STYLE_FUNCTION]);
+	line = cstring_append_chars(line, fn);
+	line = cstring_append_chars(line, "\\fR(");
+	text = cstring_to_chars(line);
+	hang = TERM_INDENT + (text ? display_width(text) : 0);
+	free(text);
+	if (hang > term_width / 2) {
+		hang = TERM_INDENT + 4;
+	}
+
+	qb_list_for_each(iter, param_list) {
+		pi = qb_list_entry(iter, struct param_info, list);
+		line = cstring_append_chars(line, styles[STYLE_TYPE]);
+		line = append_unbroken(line, pi->paramtype);
+		line = cstring_append_chars(line, "\\fR");
+		if (pi->paramname) {
+			markup_width(pi->paramtype, &visible);
+			type_end = visible ? pi->paramtype + visible - 1 : NULL;
+			if (!type_end || *type_end != '*') {
+				line = cstring_append_chars(line, "\\ ");
+			}
+			line = cstring_append_chars(line, styles[STYLE_PARAM]);
+			line = cstring_append_chars(line, pi->paramname);
+			line = cstring_append_chars(line, "\\fR");
+		}
+		if (iter->next != param_list) {
+			line = cstring_append_chars(line, ", ");
+		}
+	}
+	if (qb_list_empty(param_list)) {
+		line = cstring_append_chars(line, styles[STYLE_TYPE]);
+		line = cstring_append_chars(line, "void\\fR");
+	}
+	line = cstring_append_chars(line, ");");
+
+	text = cstring_to_chars(line);
+	if (text) {
+		term_fill(text, TERM_INDENT, hang);
+	}
+	free(text);
+	cstring_free(line);
+}
+
+/* A list of names and what they are, like PARAMS and the return values */
+static void term_tagged(const char *tag, int tag_width, const char *desc)
+{
+	cstring_t line = cstring_alloc();
+	char *text;
+	int pad = tag_width - display_width(tag);
+
+	line = cstring_append_chars(line, "\\fB");
+	line = append_unbroken(line, tag);
+	line = cstring_append_chars(line, "\\fR");
+	while (pad-- > 0) {
+		line = cstring_append_chars(line, "\\ ");
+	}
+	line = cstring_append_chars(line, " ");
+	line = cstring_append_chars(line, desc ? desc : "");
+	text = cstring_to_chars(line);
+	if (text) {
+		term_fill(text, TERM_INDENT, TERM_INDENT + tag_width + 1);
+	}
+	free(text);
+	cstring_free(line);
+}
+
+/* To stdout, or to <name>.txt in the output directory with --text-pages */
+static int print_term(char *name, char *def, char *brief, char *args, char *detailed,
+		      struct qb_list_head *param_list, char *returntext, struct qb_list_head *notes)
+{
+	struct qb_list_head *iter;
+	struct param_info *pi;
+	struct note_info *ni;
+	const char *manual = th_manual ? th_manual : header;
+	char textfilename[PATH_MAX];
+	char title[PATH_MAX + 16];
+	char include[PATH_MAX];
+	int max_name_len = 0;
+	int num_param_descs = 0;
+	int gap;
+	int ret;
+
+	term_out = stdout;
+	if (text_pages) {
+		snprintf(textfilename, sizeof(textfilename), "%s/%s.txt", output_dir, name);
+		log_msg(LOG_INFO, "Writing text page for %s\n", name);
+		ret = open_output_file(textfilename, name, "w", &term_out);
+		if (ret) {
+			return ret == 1 ? 0 : -1;
+		}
+	}
+
+	snprintf(title, sizeof(title), "%s(%s)", title_asis ? name : allcaps(name), man_section);
+	gap = term_width - 2 * (int)strlen(title) - display_width(manual);
+	if (gap < 2) {
+		fprintf(term_out, "%s\n", title);
+	} else {
+		fprintf(term_out, "%s%*s%s%*s%s\n", title, gap / 2, "", manual, gap - gap / 2, "", title);
+	}
+
+	term_section("NAME");
+	snprintf(include, sizeof(include), "%s - %s", name, brief ? brief : "");
+	term_fill(brief && not_all_whitespace(brief) ? include : name, TERM_INDENT, TERM_INDENT);
+
+	term_section("SYNOPSIS");
+	snprintf(include, sizeof(include), "\\fB#include\\ <%s%s>\\fR", header_prefix, headerfile);
+	term_fill(include, TERM_INDENT, TERM_INDENT);
+	if (def && args) {
+		fputc('\n', term_out);
+		term_synopsis(def, param_list);
+	}
+
+	qb_list_for_each(iter, param_list) {
+		pi = qb_list_entry(iter, struct param_info, list);
+		if (pi->paramname && pi->paramdesc) {
+			if (display_width(pi->paramname) > max_name_len) {
+				max_name_len = display_width(pi->paramname);
+			}
+			num_param_descs++;
+		}
+	}
+	if (print_params && num_param_descs) {
+		term_section("PARAMS");
+		qb_list_for_each(iter, param_list) {
+			pi = qb_list_entry(iter, struct param_info, list);
+			if (pi->paramname && pi->paramdesc) {
+				term_tagged(pi->paramname, max_name_len, pi->paramdesc);
+			}
+		}
+	}
+
+	if (detailed && not_all_whitespace(detailed)) {
+		term_section("DESCRIPTION");
+		term_text(detailed, TERM_INDENT);
+	}
+
+	if (returntext || !qb_list_empty(&retval_list)) {
+		term_section("RETURN VALUE");
+		if (returntext) {
+			term_text(returntext, TERM_INDENT);
+		}
+		qb_list_for_each(iter, &retval_list) {
+			pi = qb_list_entry(iter, struct param_info, list);
+			term_tagged(pi->paramname ? pi->paramname : "", 10, pi->paramdesc);
+		}
+	}
+
+	if (!qb_list_empty(notes)) {
+		term_section(notes->next->next != notes ? "NOTES" : "NOTE");
+		qb_list_for_each(iter, notes) {
+			ni = qb_list_entry(iter, struct note_info, list);
+			term_text(ni->text, TERM_INDENT);
+		}
+	}
+
+	if (!text_pages) {
+		fputc('\n', term_out);
+		return 0;
+	}
+	if (close_output_file(term_out, textfilename, name)) {
+		return -1;
+	}
+	return 0;
+}
+
+/*
+ * Send stdout through $PAGER (less if it isn't set). less is told to show the
+ * fonts, and to get out of the way if it all fits on the screen.
+ */
+static pid_t start_pager(void)
+{
+	const char *pager = getenv("PAGER");
+	int fds[2];
+	pid_t pid;
+
+	if (!pager || !*pager) {
+		pager = "less";
+	}
+	if (strcmp(pager, "cat") == 0 || pipe(fds) == -1) {
+		return 0;
+	}
+
+	fflush(stdout);
+	pid = fork();
+	if (pid == -1) {
+		close(fds[0]);
+		close(fds[1]);
+		return 0;
+	}
+	if (pid == 0) {
+		dup2(fds[0], STDIN_FILENO);
+		close(fds[0]);
+		close(fds[1]);
+		setenv("LESS", "FRX", 0);
+		execl("/bin/sh", "sh", "-c", pager, (char *)NULL);
+		perror("unable to run pager");
+		_exit(127);
+	}
+
+	dup2(fds[1], STDOUT_FILENO);
+	close(fds[0]);
+	close(fds[1]);
+	return pid;
+}
+
+static void finish_pager(pid_t pid)
+{
+	fflush(stdout);
+	close(STDOUT_FILENO);
+	waitpid(pid, NULL, 0);
+}
+
+/*
+ * --page-hook, pass a page we've just written through a shell command. What
+ * it prints replaces the page, and if it prints nothing the page is dropped.
+ * The command gets the symbol and the XML file it came from in the
+ * environment. Returns 0 to keep the page, 1 if it was dropped or -1 on error.
+ */
+static int run_page_hook(const char *manfilename, const char *name, const char *kind)
+{
+	char hookfilename[PATH_MAX + 8];
+	const char *pagename;
+	const char *section;
+	size_t len;
+	struct page_info *pi;
+	struct stat st;
+	int status;
+	int in_fd;
+	int out_fd;
+	pid_t pid;
+
+	pagename = strrchr(manfilename, '/') ? strrchr(manfilename, '/') + 1 : manfilename;
+	section = page_section(pagename, &len);
+	snprintf(hookfilename, sizeof(hookfilename), "%s.hook", manfilename);
+	pi = qb_map_get(all_pages, pagename);
+
+	in_fd = open(manfilename, O_RDONLY);
+	out_fd = open(hookfilename, O_WRONLY | O_CREAT | O_TRUNC, 0644);
+	if (in_fd == -1 || out_fd == -1) {
+		report_error(D2M_ERR_IO, manfilename, name, "unable to run page hook: %s", strerror(errno));
+		if (in_fd != -1) {
+			close(in_fd);
+		}
+		if (out_fd != -1) {
+			close(out_fd);
+			unlink(hookfilename);
+		}
+		return -1;
+	}
+
+	fflush(stdout);
+	pid = fork();
+	if (pid == 0) {
+		dup2(in_fd, STDIN_FILENO);
+		dup2(out_fd, STDOUT_FILENO);
+		close(in_fd);
+		close(out_fd);
+		setenv("D2M_PAGE", pagename, 1);
+		setenv("D2M_SYMBOL", name, 1);
+		setenv("D2M_KIND", kind, 1);
+		setenv("D2M_HEADER", pi ? pi->header : headerfile, 1);
+		setenv("D2M_SECTION", section, 1);
+		setenv("D2M_BRIEF", pi && pi->brief ? pi->brief : "", 1);
+		setenv("D2M_XML", current_xml_file ? current_xml_file : "", 1);
+		execl("/bin/sh", "sh", "-c", page_hook, NULL);
+		_exit(127);
+	}
+	close(in_fd);
+	close(out_fd);
+
+	/* Don't leave the page behind, it would look up to date next time */
+	if (pid == -1 || waitpid(pid, &status, 0) == -1 ||
+	    !WIFEXITED(status) || WEXITSTATUS(status) != 0) {
+		report_error(D2M_ERR_IO, manfilename, name, "page hook failed, page not written");
+		unlink(hookfilename);
+		unlink(manfilename);
+		return -1;
+	}
+
+	if (stat(hookfilename, &st) == 0 && st.st_size == 0) {
+		log_msg(LOG_VERBOSE, "%s dropped by the page hook\n", manfilename);
+		unlink(hookfilename);
+		unlink(manfilename);
+		qb_map_rm(all_pages, pagename);
+		return 1;
+	}
+	if (rename(hookfilename, manfilename) == -1) {
+		report_error(D2M_ERR_IO, manfilename, name, "unable to replace page: %s", strerror(errno));
+		unlink(hookfilename);
+		return -1;
+	}
+	return 0;
+}
+
+static void free_combined_function(struct combined_function *cf)
+{
+	struct qb_list_head *iter;
+	struct qb_list_head *tmp;
+	struct param_info *pi;
+
+	qb_list_for_each_safe(iter, tmp, &cf->params) {
+		pi = qb_list_entry(iter, struct param_info, list);
+		qb_list_del(&pi->list);
+		free_paraminfo(pi);
+	}
+	qb_list_for_each_safe(iter, tmp, &cf->retvals) {
+		pi = qb_list_entry(iter, struct param_info, list);
+		qb_list_del(&pi->list);
+		free_paraminfo(pi);
+	}
+	free(cf->name);
+	free(cf->def);
+	free(cf->brief);
+	free(cf->detailed);
+	free(cf->returntext);
+	free_notes(&cf->notes);
+	free(cf);
+}
+
+/* Free the things collected for the page we've just printed */
+static void free_page_info(void)
+{
+	struct qb_list_head *iter;
+	struct qb_list_head *tmp;
+	struct combined_function *cf;
+	struct param_info *pi;
+
+	/* Free the params & retval info */
+	qb_list_for_each_safe(iter, tmp, &params_list) {
+		pi = qb_list_entry(iter, struct param_info, list);
+		qb_list_del(&pi->list);
+		free_paraminfo(pi);
+	}
+
+	qb_list_for_each_safe(iter, tmp, &retval_list) {
+		pi = qb_list_entry(iter, struct param_info, list);
+		qb_list_del(&pi->list);
+		free_paraminfo(pi);
+	}
+
+	qb_list_for_each_safe(iter, tmp, &combined_list) {
+		cf = qb_list_entry(iter, struct combined_function, list);
+		qb_list_del(&cf->list);
+		free_combined_function(cf);
+	}
+
+	/*
+	 * Empty used-structures map, map_free_entry() frees the contents.
+	 * A skiplist can't have entries removed while it's being iterated
+	 * over, so start a new one.
+	 */
+	qb_map_destroy(used_structures_map);
+	used_structures_map = new_used_structures_map();
+	if (db_see_also) {
+		qb_map_destroy(db_see_also);
+		db_see_also = new_used_structures_map();
+	}
+}
+
+/*
+ * Work out the widths of the parameter types and names, so they can be
+ * lined up. Returns the number of parameters.
+ */
+static int measure_params(struct qb_list_head *param_map, int *max_param_type_len,
+			  int *max_param_name_len, unsigned int *num_param_descs)
+{
+	struct qb_list_head *iter;
+	struct param_info *pi;
+	int param_count = 0;
+
+	qb_list_for_each(iter, param_map) {
+		pi = qb_list_entry(iter, struct param_info, list);
+
+		/* It's mainly macros that break this,
+		 * macros need more work
+		 */
+		if (!pi->paramtype) {
+			pi->paramtype = strdup("");
+		}
+		if ((display_width(pi->paramtype) < param_type_limit()) &&
+		    (display_width(pi->paramtype) > *max_param_type_len)) {
+			*max_param_type_len = display_width(pi->paramtype);
+		}
+		if (display_width(pi->paramname) > *max_param_name_len) {
+			*max_param_name_len = display_width(pi->paramname);
+		}
+		if (pi->paramdesc && pi->paramtype[0] != '\0') {
+			(*num_param_descs)++;
+		}
+		param_count++;
+	}
+	return param_count;
+}
+
+/*
+ * --header-includes, called from traverse_node() for the header. Only
+ * the headers that doxygen has a refid for are kept, those are the
+ * project's own, the system headers aren't interesting.
+ */
+static void collect_includes(xmlNode *cur_node, void *arg)
+{
+	struct include_info *ii;
+	xmlNode *this_tag;
+	char *refid;
+	char *local;
+
+	for (this_tag = cur_node->children; this_tag; this_tag = this_tag->next) {
+		if (this_tag->type != XML_ELEMENT_NODE || !this_tag->children ||
+		    (strcmp((char *)this_tag->name, "includes") != 0 &&
+		     strcmp((char *)this_tag->name, "includedby") != 0)) {
+			continue;
+		}
+		refid = get_attr(this_tag, "refid");
+		if (!refid) {
+			continue;
+		}
+		free(refid);
+
+		ii = malloc(sizeof(struct include_info));
+		if (!ii) {
+			continue;
+		}
+		local = get_attr(this_tag, "local");
+		ii->name = strdup((char *)this_tag->children->content);
+		ii->local = local && strcmp(local, "yes") == 0;
+		free(local);
+		if (strcmp((char *)this_tag->name, "includes") == 0) {
+			qb_list_add_tail(&ii->list, &includes_list);
+		} else {
+			qb_list_add_tail(&ii->list, &includedby_list);
+		}
+	}
+}
+
+static void print_header_includes(FILE *manfile)
+{
+	struct qb_list_head *iter;
+	struct include_info *ii;
+	const char *slash;
+	int count = 0;
+
+	if (qb_list_empty(&includes_list) && qb_list_empty(&includedby_list)) {
+		return;
+	}
+
+	fprintf(manfile, "%s INCLUDES\n", dialect->section);
+	if (!qb_list_empty(&includes_list)) {
+		fprintf(manfile, "%s\n", dialect->paragraph);
+		fprintf(manfile, "\\fB%s\\fR includes these other %s headers itself, so they don't need to be\n",
+			headerfile, package_name);
+		fprintf(manfile, "included as well:\n");
+		fprintf(manfile, "%s\n", dialect->paragraph);
+		fprintf(manfile, "%s\n", dialect->nofill_start);
+		qb_list_for_each(iter, &includes_list) {
+			ii = qb_list_entry(iter, struct include_info, list);
+			print_include(manfile, ii->name, ii->local);
+		}
+		fprintf(manfile, "%s\n", dialect->nofill_end);
+	}
+	if (!qb_list_empty(&includedby_list)) {
+		fprintf(manfile, "%s\n", dialect->paragraph);
+		fprintf(manfile, "It is included by:\n");
+		fprintf(manfile, "%s\n", dialect->paragraph);
+		fprintf(manfile, ".nh\n");
+		fprintf(manfile, ".ad l\n");
+		qb_list_for_each(iter, &includedby_list) {
+			ii = qb_list_entry(iter, struct include_info, list);
+			/* The header pages are named after the file, without any directory */
+			slash = strrchr(ii->name, '/');
+			fprintf(manfile, "%s\\fI%s\\fR(%s)", count++ ? ", " : "",
+				slash ? slash + 1 : ii->name, man_section);
+		}
+		fprintf(manfile, "\n");
+		fprintf(manfile, ".ad\n");
+		fprintf(manfile, ".hy\n");
+	}
+}
+
+static void free_includes(void)
+{
+	struct include_info *ii;
+	struct qb_list_head *iter;
+	struct qb_list_head *tmp;
+
+	qb_list_for_each_safe(iter, tmp, &includes_list) {
+		ii = qb_list_entry(iter, struct include_info, list);
+		qb_list_del(&ii->list);
+		free(ii->name);
+		free(ii);
+	}
+	qb_list_for_each_safe(iter, tmp, &includedby_list) {
+		ii = qb_list_entry(iter, struct include_info, list);
+		qb_list_del(&ii->list);
+		free(ii->name);
+		free(ii);
+	}
+}
+
+/*
+ * --references, the documented functions that doxygen says 'name' calls
+ * (<references>) and is called by (<referencedby>). Without index.xml
+ * only the functions in the same header are known to be documented.
+ */
+static void collect_references(xmlNode *cur_node, const char *name)
+{
+	struct reference_info *ri;
+	struct function_info *fi;
+	struct qb_list_head *list;
+	struct qb_list_head *iter;
+	xmlNode *this_tag;
+	const char *ref;
+	char *refid;
+	int found;
+
+	for (this_tag = cur_node->children; this_tag; this_tag = this_tag->next) {
+		if (this_tag->type != XML_ELEMENT_NODE || !this_tag->children || !this_tag->children->content) {
+			continue;
+		}
+		if (strcmp((char *)this_tag->name, "references") == 0) {
+			list = &uses_list;
+		} else if (strcmp((char *)this_tag->name, "referencedby") == 0) {
+			list = &used_by_list;
+		} else {
+			continue;
+		}
+
+		refid = get_attr(this_tag, "refid");
+		ref = (char *)this_tag->children->content;
+		fi = qb_map_get(function_map, ref);
+		if (!fi && (!refid || qb_map_count_get(header_functions) == 0 ||
+			    !qb_map_get(header_functions, refid))) {
+			free(refid);
+			continue;
+		}
+		free(refid);
+		/* A --combine-by-prefix function is on the page of the first one */
+		if (fi && fi->combined_with) {
+			ref = fi->combined_with->name;
+		}
+		if (strcmp(ref, name) == 0 || !symbol_documented(ref) ||
+		    (exported_symbols_file && !find_exported_symbol(ref))) {
+			continue;
+		}
+
+		found = 0;
+		qb_list_for_each(iter, list) {
+			ri = qb_list_entry(iter, struct reference_info, list);
+			if (strcmp(ri->name, ref) == 0) {
+				found = 1;
+				break;
+			}
+		}
+		if (found) {
+			continue;
+		}
+		ri = malloc(sizeof(struct reference_info));
+		if (!ri) {
+			continue;
+		}
+		ri->name = strdup(ref);
+		qb_list_add_tail(&ri->list, list);
+	}
+}
+
+static void print_references(FILE *manfile, const char *title, struct qb_list_head *list)
+{
+	struct qb_list_head *iter;
+	struct reference_info *ri;
+	int count = 0;
+
+	if (qb_list_empty(list)) {
+		return;
+	}
+	fprintf(manfile, "%s %s\n", dialect->section, title);
+	fprintf(manfile, "%s\n", dialect->paragraph);
+	fprintf(manfile, ".nh\n");
+	fprintf(manfile, ".ad l\n");
+	qb_list_for_each(iter, list) {
+		ri = qb_list_entry(iter, struct reference_info, list);
+		fprintf(manfile, "%s\\fI%s\\fR(%s)", count++ ? ", " : "", ri->name, man_section);
+	}
+	fprintf(manfile, "\n");
+	fprintf(manfile, ".ad\n");
+	fprintf(manfile, ".hy\n");
+}
+
+static void free_references(void)
+{
+	struct reference_info *ri;
+	struct qb_list_head *iter;
+	struct qb_list_head *tmp;
+
+	qb_list_for_each_safe(iter, tmp, &uses_list) {
+		ri = qb_list_entry(iter, struct reference_info, list);
+		qb_list_del(&ri->list);
+		free(ri->name);
+		free(ri);
+	}
+	qb_list_for_each_safe(iter, tmp, &used_by_list) {
+		ri = qb_list_entry(iter, struct reference_info, list);
+		qb_list_del(&ri->list);
+		free(ri->name);
+		free(ri);
+	}
+}
+
+/* A parameter's name goes straight after a type ending in * or ( */
+static const char *type_space(const char *type)
+{
+	size_t len = strlen(type);
+
+	return (len && type[len - 1] != '*' && type[len - 1] != '(') ? " " : "";
+}
+
+/*
+ * --dialect groff, .SY indents the parameters to just after the bracket
+ * so they don't need lining up
+ */
+static void print_synopsis_sy(FILE *manfile, const char *def, struct qb_list_head *param_map)
+{
+	struct qb_list_head *iter;
+	struct param_info *pi;
+	int param_count = 0;
+	int param_num = 0;
+
+	qb_list_for_each(iter, param_map) {
+		pi = qb_list_entry(iter, struct param_info, list);
+		if (pi->paramtype[0] != '\0') {
+			param_count++;
+		}
+	}
+
+	fprintf(manfile, ".SY \"%s(%s\"\n", def, param_count ? "" : ");");
+	qb_list_for_each(iter, param_map) {
+		pi = qb_list_entry(iter, struct param_info, list);
+		if (pi->paramtype[0] == '\0') {
+			continue;
+		}
+		fprintf(manfile, "%s%s%s\\fP%s%s\\fP%s\n", styles[STYLE_TYPE], pi->paramtype, type_space(pi->paramtype),
+			styles[STYLE_PARAM], pi->paramname ? pi->paramname : "", ++param_num < param_count ? "," : ");");
+	}
+	fprintf(manfile, ".YS\n");
+}
+
+/* One function declaration in the SYNOPSIS */
+static void print_synopsis_function(FILE *manfile, const char *def, struct qb_list_head *param_map)
+{
+	struct qb_list_head *iter;
+	struct param_info *pi;
+	int max_param_type_len = 0;
+	int max_param_name_len = 0;
+	unsigned int num_param_descs = 0;
+	int type_column;
+	int param_count;
+	int param_num = 0;
+
+	if (dialect->id == DIALECT_GROFF) {
+		print_synopsis_sy(manfile, def, param_map);
+		return;
+	}
+
+	param_count = measure_params(param_map, &max_param_type_len, &max_param_name_len, &num_param_descs);
+	fprintf(manfile, ".sp\n");
+	print_synopsis_def(manfile, def);
+
+	/* Line the names up, but not so far across that they run off the page */
+	type_column = max_param_type_len;
+	if (4 + type_column + 2 + max_param_name_len + 1 > synopsis_width) {
+		type_column = synopsis_width - 7 - max_param_name_len;
+		if (type_column < 0) {
+			type_column = 0;
+		}
+	}
+
+	qb_list_for_each(iter, param_map) {
+		pi = qb_list_entry(iter, struct param_info, list);
+
+		if (pi->paramtype[0] != '\0' && display_width(pi->paramtype) >= param_type_limit()) {
+			print_long_param(manfile, pi, ++param_num < param_count?",":"");
+		} else if (pi->paramtype[0] != '\0') { //CC
+			print_param(manfile, pi, type_column, 1, ++param_num < param_count?",":"");
+		}
+	}
+
+	fprintf(manfile, ");\n");
+}
+
+/* Whether a parameter called 'name' has been described for a function before 'cf' on the page */
+static int param_described_before(const char *name, struct combined_function *cf)
+{
+	struct combined_function *earlier;
+	struct qb_list_head *iter;
+	struct param_info *pi;
+
+	pi = find_param_by_name(&params_list, name);
+	if (pi && pi->paramdesc) {
+		return 1;
+	}
+	qb_list_for_each(iter, &combined_list) {
+		earlier = qb_list_entry(iter, struct combined_function, list);
+		if (earlier == cf) {
+			break;
+		}
+		pi = find_param_by_name(&earlier->params, name);
+		if (pi && pi->paramdesc) {
+			return 1;
+		}
+	}
+	return 0;
+}
+
+static void print_return_value(FILE *manfile, char *returntext, struct qb_list_head *retvals)
+{
+	struct qb_list_head *iter;
+	struct param_info *pi;
+
+	if (returntext) {
+		man_print_long_string(manfile, returntext);
+	}
+	fprintf(manfile, "%s\n", dialect->paragraph);
+
+	qb_list_for_each(iter, retvals) {
+		pi = qb_list_entry(iter, struct param_info, list);
+
+		fprintf(manfile, "\\fB");
+		print_padded(manfile, pi->paramname, 10);
+		fprintf(manfile, " \\fP%s\n", pi->paramdesc);
+		fprintf(manfile, "%s\n", dialect->paragraph);
+	}
+}
+
+/*
+ * The symbol versions from --symbol-info, or from an --exported-symbols
+ * version script, of the function 'name' and any others on its page
+ */
+static void print_versions(FILE *manfile, const char *name)
+{
+	struct combined_function *cf;
+	struct qb_list_head *iter;
+	struct symbol_info *sym;
+	struct exported_symbol *es;
+	const char *version;
+	const char *fn;
+	int section = 0;
+
+	/* VERSIONS, then STABILITY */
+	while (section < 2) {
+		int first = 1;
+
+		for (fn = name, iter = combined_list.next; fn; ) {
+			sym = symbol_info_map ? qb_map_get(symbol_info_map, fn) : NULL;
+			es = exported_symbols_file ? find_exported_symbol(fn) : NULL;
+			version = (sym && sym->version) ? sym->version : (es ? es->version : NULL);
+
+			if (section == 0 && version) {
+				if (first) {
+					fprintf(manfile, "%s VERSIONS\n", dialect->section);
+				}
+				fprintf(manfile, "%s\n", dialect->paragraph);
+				fprintf(manfile, "\\fB%s\\fP() is available since symbol version \\fB%s\\fP.\n",
+					fn, version);
+				first = 0;
+			}
+			if (section == 1 && sym && sym->status) {
+				if (first) {
+					fprintf(manfile, "%s STABILITY\n", dialect->section);
+				}
+				fprintf(manfile, "%s\n", dialect->paragraph);
+				fprintf(manfile, "\\fB%s\\fP() is %s.\n", fn, sym->status);
+				first = 0;
+			}
+
+			if (iter == &combined_list) {
+				break;
+			}
+			cf = qb_list_entry(iter, struct combined_function, list);
+			fn = cf->name;
+			iter = iter->next;
+		}
+		section++;
+	}
+}
+
+/* Whether the <type> in 'node' names 'name', as a whole word */
+static int type_mentions(xmlNode *node, const char *name)
+{
+	xmlChar *content;
+	const char *text;
+	const char *p;
+	size_t len = strlen(name);
+	int found = 0;
+
+	content = xmlNodeGetContent(node);
+	text = (const char *)content;
+	for (p = text; p && (p = strstr(p, name)) != NULL; p += len) {
+		if ((p == text || !(isalnum((unsigned char)p[-1]) || p[-1] == '_')) &&
+		    !(isalnum((unsigned char)p[len]) || p[len] == '_')) {
+			found = 1;
+			break;
+		}
+	}
+	xmlFree(content);
+	return found;
+}
+
+/* Whether a function returns 'name' or takes it as a parameter */
+static int function_uses_type(struct function_info *fi, const char *name)
+{
+	xmlNode *child;
+	xmlNode *type;
+
+	for (child = fi->node->children; child; child = child->next) {
+		if (child->type != XML_ELEMENT_NODE) {
+			continue;
+		}
+		if (strcmp((char *)child->name, "type") == 0) {
+			type = child;
+		} else if (strcmp((char *)child->name, "param") == 0) {
+			type = find_child(child, "type");
+		} else {
+			continue;
+		}
+		if (type && type_mentions(type, name)) {
+			return 1;
+		}
+	}
+	return 0;
+}
+
+/* The same, for any of the functions on fi's page */
+static int page_uses_type(struct function_info *fi, const char *name)
+{
+	size_t i;
+
+	if (function_uses_type(fi, name)) {
+		return 1;
+	}
+	for (i = 0; i < see_also_count; i++) {
+		if (see_also_order[i]->combined_with == fi && function_uses_type(see_also_order[i], name)) {
+			return 1;
+		}
+	}
+	return 0;
+}
+
+/*
+ * --struct-pages and --enum-pages, the SEE ALSO entries for the structures
+ * and enums a function uses, or all of them on the header page. Returns how many.
+ */
+static int print_struct_see_also(FILE *manfile, const char *name, int count)
+{
+	struct function_info *fi = qb_map_get(function_map, name);
+	qb_map_iter_t *map_iter;
+	struct page_info *pi;
+	char structname[PATH_MAX];
+	const char *section;
+	const char *page;
+	void *data;
+	size_t len;
+	int printed = 0;
+
+	map_iter = qb_map_iter_create(all_pages);
+	for (page = qb_map_iter_next(map_iter, &data); page; page = qb_map_iter_next(map_iter, &data)) {
+		pi = data;
+		if ((strcmp(pi->kind, "struct") != 0 && strcmp(pi->kind, "enum") != 0) ||
+		    strcmp(pi->header, headerfile) != 0) {
+			continue;
+		}
+		section = page_section(page, &len);
+		snprintf(structname, sizeof(structname), "%.*s", (int)len, page);
+		if (name != headerfile && (!fi || !page_uses_type(fi, structname))) {
+			continue;
+		}
+		fprintf(manfile, "%s\\fI%s\\fR(%s)", count + printed ? ", " : "", structname, section);
+		printed++;
+	}
+	qb_map_iter_free(map_iter);
+	return printed;
+}
+
+static int print_manpage(char *name, char *def, char *brief, char *args, char *detailed,
+			  struct qb_list_head *param_map, char *returntext, struct qb_list_head *notes)
+{
+	char manfilename[PATH_MAX];
+	const char *dateptr;
+	FILE *manfile;
+	uint64_t page_hash;
+	qb_map_iter_t *map_iter;
+	struct qb_list_head *iter;
+	struct qb_list_head *param_iter;
+	struct combined_function *cf;
+	const char *p;
+	void *data;
+	int max_param_type_len;
+	int max_param_name_len;
+	unsigned int num_param_descs;
+	int combined = !qb_list_empty(&combined_list);
+	int any_text;
+	size_t num_notes;
+	struct param_info *pi;
+	int see_also_entries;
+	int ret;
+
+	snprintf(manfilename, sizeof(manfilename), "%s/%s.%s", output_dir, name, man_section);
+	page_hash = get_page_hash();
+	if (page_is_current(manfilename, page_hash)) {
+		log_msg(LOG_VERBOSE, "%s is up to date\n", manfilename);
+		free_page_info();
+		return 0;
+	}
+	dateptr = page_date();
+
+	ret = open_output_file(manfilename, name, "w+", &manfile);
+	if (ret) {
+		free_page_info();
+		return ret == 1 ? 0 : -1;
+	}
+	if (name == headerfile) {
+		log_msg(LOG_INFO, "Printing header manpage for %s\n", headerfile);
+	} else {
+		log_msg(LOG_INFO, "Printing manpage for %s\n", name);
+	}
+
+	/* Work out the length of the parameters, so we can line them up   */
+	max_param_type_len = 0;
+	max_param_name_len = 0;
+	num_param_descs = 0;
+	measure_params(param_map, &max_param_type_len, &max_param_name_len, &num_param_descs);
+	qb_list_for_each(iter, &combined_list) {
+		cf = qb_list_entry(iter, struct combined_function, list);
+		measure_params(&cf->params, &max_param_type_len, &max_param_name_len, &num_param_descs);
+	}
+
+	/* Off we go */
+
+	print_generated_comment(manfile);
+	fprintf(manfile, HASH_COMMENT "%016" PRIx64 "\n", page_hash);
+	print_th(manfile, name, man_section, dateptr);
+
+	print_name_section(manfile, name, &combined_list, brief);
+
+	print_synopsis_include(manfile);
+	if (def) {
+		/* .SY fills, the declarations in man are laid out by hand */
+		if (dialect->id == DIALECT_GROFF) {
+			fprintf(manfile, ".fi\n");
+		}
+		print_synopsis_function(manfile, def, param_map);
+		qb_list_for_each(iter, &combined_list) {
+			cf = qb_list_entry(iter, struct combined_function, list);
+			if (cf->def) {
+				print_synopsis_function(manfile, cf->def, &cf->params);
+			}
+		}
+		if (dialect->id == DIALECT_MAN) {
+			fprintf(manfile, ".fi\n");
+		}
+	}
+
+	if (print_params && num_param_descs) {
+		fprintf(manfile, "%s PARAMS\n", dialect->section);
+
+		qb_list_for_each(iter, &params_list) {
+			pi = qb_list_entry(iter, struct param_info, list);
+			fprintf(manfile, "\\fB");
+			print_padded(manfile, pi->paramname, max_param_name_len);
+			fprintf(manfile, " \\fP\\fI%s\\fP\n", pi->paramdesc);
+			fprintf(manfile, "%s\n", dialect->paragraph);
+		}
+
+		/* The other functions on the page often share parameters, those are only given once */
+		qb_list_for_each(iter, &combined_list) {
+			cf = qb_list_entry(iter, struct combined_function, list);
+			qb_list_for_each(param_iter, &cf->params) {
+				pi = qb_list_entry(param_iter, struct param_info, list);
+				if (!pi->paramdesc || param_described_before(pi->paramname, cf)) {
+					continue;
+				}
+				fprintf(manfile, "\\fB");
+				print_padded(manfile, pi->paramname, max_param_name_len);
+				fprintf(manfile, " \\fP\\fI%s\\fP\n", pi->paramdesc);
+				fprintf(manfile, "%s\n", dialect->paragraph);
+			}
+		}
+	}
+
+	if (combined) {
+		/* Each function gets a subsection, starting with its brief description */
+		fprintf(manfile, "%s DESCRIPTION\n", dialect->section);
+		fprintf(manfile, "%s \"%s()\"\n", dialect->subsection, name);
+		if (brief && not_all_whitespace(brief)) {
+			fprintf(manfile, "%s\n%s\n", dialect->paragraph, brief);
+		}
+		if (detailed) {
+			man_print_long_string(manfile, detailed);
+		}
+		qb_list_for_each(iter, &combined_list) {
+			cf = qb_list_entry(iter, struct combined_function, list);
+			fprintf(manfile, "%s \"%s()\"\n", dialect->subsection, cf->name);
+			if (cf->brief && not_all_whitespace(cf->brief)) {
+				fprintf(manfile, "%s\n%s\n", dialect->paragraph, cf->brief);
+			}
+			if (cf->detailed) {
+				man_print_long_string(manfile, cf->detailed);
+			}
+		}
+	} else if (detailed) {
+		fprintf(manfile, "%s DESCRIPTION\n", dialect->section);
+		man_print_long_string(manfile, detailed);
+	}
+
+	if (qb_map_count_get(used_structures_map)) {
+		int first_struct = 1;
+
+		map_iter = qb_map_iter_create(used_structures_map);
+		for (p = qb_map_iter_next(map_iter, &data); p; p = qb_map_iter_next(map_iter, &data)) {
+			struct struct_info *si;
+			const char *refid = p;
+			char *refname = data;
+
+			/* If it's not been read in - go and look for it */
+			si = qb_map_get(structures_map, refid);
+			if (!si) {
+				if (!read_structure_from_xml(refid, refname)) {
+					si = qb_map_get(structures_map, refid);
+				}
+			}
+			if (si && si->structname && !symbol_documented(si->structname)) {
+				si = NULL;
+			}
+
+			/* Only print header if the struct files exist - sometimes they don't */
+			if (si && first_struct) {
+				fprintf(manfile, "%s STRUCTURES\n", dialect->section);
+				first_struct = 0;
+			}
+			if (si) {
+				print_structure(manfile, si);
+				fprintf(manfile, "%s\n", dialect->paragraph);
+			}
+		}
+		qb_map_iter_free(map_iter);
+
+		fprintf(manfile, ".RE\n");
+	}
+
+	any_text = returntext || !qb_list_empty(&retval_list);
+	qb_list_for_each(iter, &combined_list) {
+		cf = qb_list_entry(iter, struct combined_function, list);
+		any_text |= cf->returntext || !qb_list_empty(&cf->retvals);
+	}
+	if (any_text) {
+		fprintf(manfile, "%s RETURN VALUE\n", dialect->section);
+		if (!combined) {
+			print_return_value(manfile, returntext, &retval_list);
+		} else {
+			if (returntext || !qb_list_empty(&retval_list)) {
+				fprintf(manfile, "%s \"%s()\"\n", dialect->subsection, name);
+				print_return_value(manfile, returntext, &retval_list);
+			}
+			qb_list_for_each(iter, &combined_list) {
+				cf = qb_list_entry(iter, struct combined_function, list);
+				if (cf->returntext || !qb_list_empty(&cf->retvals)) {
+					fprintf(manfile, "%s \"%s()\"\n", dialect->subsection, cf->name);
+					print_return_value(manfile, cf->returntext, &cf->retvals);
+				}
+			}
+		}
+	}
+
+	if (name != headerfile) {
+		print_versions(manfile, name);
+	}
+
+	num_notes = count_notes(notes);
+	qb_list_for_each(iter, &combined_list) {
+		cf = qb_list_entry(iter, struct combined_function, list);
+		num_notes += count_notes(&cf->notes);
+	}
+	if (name == headerfile) {
+		print_header_includes(manfile);
+	}
+	print_references(manfile, "USES", &uses_list);
+	print_references(manfile, "USED BY", &used_by_list);
+
+	if (num_notes) {
+		fprintf(manfile, "%s %s\n", dialect->section, num_notes > 1 ? "NOTES" : "NOTE");
+		if (combined && !qb_list_empty(notes)) {
+			fprintf(manfile, "%s \"%s()\"\n", dialect->subsection, name);
+		}
+		print_notes(manfile, notes);
+		qb_list_for_each(iter, &combined_list) {
+			cf = qb_list_entry(iter, struct combined_function, list);
+			if (!qb_list_empty(&cf->notes)) {
+				fprintf(manfile, "%s \"%s()\"\n", dialect->subsection, cf->name);
+				print_notes(manfile, &cf->notes);
+			}
+		}
+	}
+
+	if (source_location && name != headerfile && source_file[0]) {
+		char *file = man_text_dup(source_file);
+
+		fprintf(manfile, "%s SOURCE\n", dialect->section);
+		fprintf(manfile, "%s\n", dialect->paragraph);
+		fprintf(manfile, "Defined in \\fB%s\\fR", file ? file : source_file);
+		if (source_line) {
+			fprintf(manfile, " line %d", source_line);
+		}
+		fprintf(manfile, ".\n");
+		free(file);
+	}
+
+	fprintf(manfile, "%s SEE ALSO\n", dialect->section);
+	fprintf(manfile, "%s\n", dialect->paragraph);
+	fprintf(manfile, ".nh\n");
+	fprintf(manfile, ".ad l\n");
+
+	see_also_entries = print_see_also_functions(manfile, name);
+
+	if (name == headerfile && examples_dir && !qb_list_empty(&examples_list)) {
+		fprintf(manfile, "%s\\fI%s\\fR(%s)", see_also_entries ? ", " : "",
+			examples_pagename(), man_section);
+		see_also_entries++;
+	}
+	if (name == headerfile && constants_page && !qb_list_empty(&defines_list)) {
+		fprintf(manfile, "%s\\fI%s\\fR(%s)", see_also_entries ? ", " : "",
+			constants_pagename(), man_section);
+		see_also_entries++;
+	}
+	if (struct_pages || enum_pages) {
+		see_also_entries += print_struct_see_also(manfile, name, see_also_entries);
+	}
+	if (db_see_also) {
+		see_also_entries += print_db_see_also(manfile, see_also_entries);
+	}
+	print_extra_see_also(manfile, name, see_also_entries);
+
+	fprintf(manfile, "\n");
+	fprintf(manfile, ".ad\n");
+	fprintf(manfile, ".hy\n");
+	print_manpage_footer(manfile);
+	free_page_info();
+
+	if (close_output_file(manfile, manfilename, name)) {
+		return -1;
+	}
+	if (wrap_width && wrap_page(manfilename, name)) {
+		return -1;
+	}
+	if (page_hook) {
+		ret = run_page_hook(manfilename, name, name == headerfile ? "header" : "function");
+		if (ret) {
+			return ret;
+		}
+	}
+	if (lint_output) {
+		lint_page(manfilename, name);
+	}
+	return 0;
+}
+
+/*
+ * With --exported-symbols only the library's ABI gets documented. Also
+ * notes which exported symbols we've seen, for check_exported_symbols().
+ */
+static int function_exported(xmlNode *cur_node, const char *name)
+{
+	struct exported_symbol *es;
+	char location[PATH_MAX + 16];
+
+	if (!exported_symbols_file) {
+		return 1;
+	}
+	es = find_exported_symbol(name);
+	if (es) {
+		es->documented = 1;
+		return 1;
+	}
+	lint_problem(node_location(cur_node, location, sizeof(location)), name, "documented but not exported");
+	return 0;
+}
+
+/* Same as traverse_members, but to collect function names */
+static void collect_functions(xmlNode *cur_node, void *arg)
+{
+	xmlNode *this_tag;
+	char *kind;
+	char *name = NULL;
+
+	if (cur_node->name && strcmp((char *)cur_node->name, "memberdef") == 0) {
+
+		kind = get_attr(cur_node, "kind");
+		if (kind && strcmp(kind, "function") == 0) {
+
+			for (this_tag = cur_node->children; this_tag; this_tag = this_tag->next) {
+				if (this_tag->type == XML_ELEMENT_NODE && strcmp((char *)this_tag->name, "name") == 0) {
+					name = strdup((char *)this_tag->children->content);
+				}
+			}
+
+			if (name && (!symbol_wanted(name) || !function_wanted(cur_node) ||
+				     !function_exported(cur_node, name))) {
+				log_msg(LOG_VERBOSE, "Leaving out %s\n", name);
+				free(name);
+				name = NULL;
+			}
+			if (name) {
+				struct function_info *fi = calloc(1, sizeof(struct function_info));
+
+				if (fi) {
+					fi->name = name;
+					fi->node = cur_node;
+					fi->seq = num_functions;
+					fi->group = get_function_group(cur_node);
+					qb_map_put(function_map, name, fi);
+					num_functions++;
+				} else {
+					free(name);
+				}
+			}
+		}
+		free(kind);
+	}
+}
+
+/* Remember any functions, or the header itself, that have example code */
+static void collect_examples(xmlNode *cur_node, void *arg)
+{
+	struct example_info *ei;
+	xmlNode *detailed;
+	xmlNode *brief_node;
+	xmlNode *this_tag;
+	char *kind;
+	char *name = NULL;
+	char *brief = NULL;
+
+	kind = get_attr(cur_node, "kind");
+	if (kind && strcmp(kind, "file") == 0) {
+		name = strdup(headerfile);
+	} else if (kind && strcmp(kind, "function") == 0) {
+		for (this_tag = cur_node->children; this_tag; this_tag = this_tag->next) {
+			if (this_tag->type == XML_ELEMENT_NODE && strcmp((char *)this_tag->name, "name") == 0) {
+				name = strdup((char *)this_tag->children->content);
+			}
+		}
+	}
+	free(kind);
+	if (!name) {
+		return;
+	}
+
+	detailed = find_child(cur_node, "detaileddescription");
+	if (!detailed || !find_node(detailed, "programlisting")) {
+		free(name);
+		return;
+	}
+
+	brief_node = find_child(cur_node, "briefdescription");
+	if (brief_node) {
+		xmlChar *content = xmlNodeGetContent(brief_node);
+		char *start = (char *)content;
+		char *end;
+
+		while (isspace(*start)) {
+			start++;
+		}
+		end = start + strlen(start);
+		while (end > start && isspace(end[-1])) {
+			*--end = '\0';
+		}
+		brief = strdup(start);
+		xmlFree(content);
+	}
+
+	ei = malloc(sizeof(struct example_info));
+	if (ei) {
+		ei->pagename = name;
+		ei->brief = brief;
+		qb_list_add_tail(&ei->list, &examples_list);
+	} else {
+		free(name);
+		free(brief);
+	}
+}
+
+static int print_examples_page(void)
+{
+	char manfilename[PATH_MAX * 2];
+	char brief[PATH_MAX + 32];
+	char tag[PATH_MAX];
+	struct example_info *ei;
+	struct qb_list_head *iter;
+	const char *pagename = examples_pagename();
+	FILE *manfile;
+	uint64_t page_hash;
+	int ret;
+
+	snprintf(manfilename, sizeof(manfilename), "%s/%s.%s", output_dir, pagename, man_section);
+	page_hash = get_page_hash();
+	if (page_is_current(manfilename, page_hash)) {
+		log_msg(LOG_VERBOSE, "%s is up to date\n", manfilename);
+		return 0;
+	}
+
+	ret = open_output_file(manfilename, pagename, "w+", &manfile);
+	if (ret) {
+		return ret == 1 ? 0 : -1;
+	}
+	log_msg(LOG_INFO, "Printing examples manpage for %s\n", headerfile);
+
+	print_generated_comment(manfile);
+	fprintf(manfile, HASH_COMMENT "%016" PRIx64 "\n", page_hash);
+	print_th(manfile, pagename, man_section, page_date());
+	snprintf(brief, sizeof(brief), "example code for %s%s", header_prefix, headerfile);
+	print_name_section(manfile, pagename, NULL, brief);
+	fprintf(manfile, "%s DESCRIPTION\n", dialect->section);
+	fprintf(manfile, "%s\n", dialect->paragraph);
+	fprintf(manfile, "These examples are taken from the documentation for \\fB%s%s\\fR.\n", header_prefix, headerfile);
+	fprintf(manfile, "Each one is named after the man page it appears in.\n");
+
+	qb_list_for_each(iter, &examples_list) {
+		ei = qb_list_entry(iter, struct example_info, list);
+		snprintf(tag, sizeof(tag), "%s.c", ei->pagename);
+		print_list_tag(manfile, dialect->bold, tag);
+		fprintf(manfile, "%s\n", (ei->brief && ei->brief[0]) ? ei->brief : ei->pagename);
+	}
+
+	fprintf(manfile, "%s SEE ALSO\n", dialect->section);
+	fprintf(manfile, "%s\n", dialect->paragraph);
+	fprintf(manfile, ".nh\n");
+	fprintf(manfile, ".ad l\n");
+	qb_list_for_each(iter, &examples_list) {
+		ei = qb_list_entry(iter, struct example_info, list);
+		fprintf(manfile, "\\fI%s\\fR(%s)%s", ei->pagename, man_section,
+			iter->next != &examples_list ? ", " : "");
+	}
+	print_extra_see_also(manfile, pagename, 1);
+	fprintf(manfile, "\n");
+	fprintf(manfile, ".ad\n");
+	fprintf(manfile, ".hy\n");
+	print_manpage_footer(manfile);
+
+	if (close_output_file(manfile, manfilename, pagename)) {
+		return -1;
+	}
+	if (wrap_width && wrap_page(manfilename, pagename)) {
+		return -1;
+	}
+	if (page_hook) {
+		ret = run_page_hook(manfilename, pagename, "examples");
+		if (ret) {
+			return ret;
+		}
+	}
+	if (lint_output) {
+		lint_page(manfilename, pagename);
+	}
+	return 0;
+}
+
+static void free_examples(void)
+{
+	struct example_info *ei;
+	struct qb_list_head *iter;
+	struct qb_list_head *tmp;
+
+	qb_list_for_each_safe(iter, tmp, &examples_list) {
+		ei = qb_list_entry(iter, struct example_info, list);
+		qb_list_del(&ei->list);
+		free(ei->pagename);
+		free(ei->brief);
+		free(ei);
+	}
+}
+
+/*
+ * Remember the #defines for --constants-page. Unless --constants-all is
+ * given only ALLCAPS ones are constants, the rest are usually macros that
+ * look like functions.
+ */
+static void collect_defines(xmlNode *cur_node, void *arg)
+{
+	struct define_info *di;
+	xmlNode *this_tag;
+	cstring_t name;
+	cstring_t desc = NULL;
+	const char *c;
+	char *kind;
+	char *macro = NULL;
+	char *value = NULL;
+	int params = 0;
+
+	kind = get_attr(cur_node, "kind");
+	if (!kind || strcmp(kind, "define") != 0) {
+		free(kind);
+		return;
+	}
+	free(kind);
+
+	name = cstring_alloc();
+	for (this_tag = cur_node->children; this_tag; this_tag = this_tag->next) {
+		if (this_tag->type != XML_ELEMENT_NODE) {
+			continue;
+		}
+		if (this_tag->children && strcmp((char *)this_tag->name, "name") == 0) {
+			macro = (char *)this_tag->children->content;
+			name = cstring_append_chars(name, macro);
+		}
+		if (strcmp((char *)this_tag->name, "param") == 0) {
+			xmlNode *defname = find_child(this_tag, "defname");
+
+			name = cstring_append_chars(name, params++ ? ", " : "(");
+			if (defname && defname->children) {
+				name = cstring_append_chars(name, (char *)defname->children->content);
+			}
+		}
+		if (this_tag->children && strcmp((char *)this_tag->name, "initializer") == 0) {
+			value = (char *)this_tag->children->content;
+		}
+		if (strcmp((char *)this_tag->name, "briefdescription") == 0 ||
+		    strcmp((char *)this_tag->name, "detaileddescription") == 0) {
+			cstring_t text = get_texttree(NULL, this_tag, NULL, NULL);
+
+			desc = desc ? cstring_append_cstring(desc, text) : text;
+			if (desc != text) {
+				cstring_free(text);
+			}
+		}
+	}
+	if (params) {
+		name = cstring_append_chars(name, ")");
+	}
+
+	di = malloc(sizeof(struct define_info));
+	if (di) {
+		di->name = cstring_to_chars(name);
+		di->prefix_len = strcspn(di->name, "(");
+		for (c = di->name; c < di->name + di->prefix_len; c++) {
+			if (islower((unsigned char)*c)) {
+				break;
+			}
+		}
+		if (!macro || !symbol_documented(macro) ||
+		    (!constants_all && (params || c < di->name + di->prefix_len))) {
+			free(di->name);
+			free(di);
+			di = NULL;
+		}
+	}
+	if (di) {
+		/* Grouped by everything up to the last underscore, QB_LOG_ for QB_LOG_INFO */
+		while (di->prefix_len > 0 && di->name[di->prefix_len - 1] != '_') {
+			di->prefix_len--;
+		}
+		/* Long macros only go on the page of the header */
+		di->value = value && !strchr(value, '\n') ? strdup(value) : NULL;
+		di->desc = desc ? cstring_to_chars(desc) : NULL;
+		if (di->desc && !not_all_whitespace(di->desc)) {
+			free(di->desc);
+			di->desc = NULL;
+		}
+		di->printed = 0;
+		qb_list_add_tail(&di->list, &defines_list);
+	}
+	cstring_free(name);
+	if (desc) {
+		cstring_free(desc);
+	}
+}
+
+/* Whether the #define 'name' is on the --constants-page */
+static int define_listed(const char *name)
+{
+	struct define_info *di;
+	struct qb_list_head *iter;
+	size_t len = strlen(name);
+
+	qb_list_for_each(iter, &defines_list) {
+		di = qb_list_entry(iter, struct define_info, list);
+		if (strncmp(di->name, name, len) == 0 && (di->name[len] == '\0' || di->name[len] == '(')) {
+			return 1;
+		}
+	}
+	return 0;
+}
+
+/* How many of the defines start with the prefix of 'di' */
+static int define_prefix_count(struct define_info *di)
+{
+	struct define_info *other;
+	struct qb_list_head *iter;
+	int count = 0;
+
+	if (di->prefix_len == 0) {
+		return 0;
+	}
+	qb_list_for_each(iter, &defines_list) {
+		other = qb_list_entry(iter, struct define_info, list);
+		if (other->prefix_len == di->prefix_len &&
+		    strncmp(other->name, di->name, di->prefix_len) == 0) {
+			count++;
+		}
+	}
+	return count;
+}
+
+static void print_define(FILE *manfile, struct define_info *di)
+{
+	char chunk[2] = {0, 0};
+	const char *c;
+	cstring_t tag;
+	char *text;
+
+	tag = cstring_alloc();
+	tag = cstring_append_chars(tag, "\\fB");
+	tag = cstring_append_chars(tag, di->name);
+	tag = cstring_append_chars(tag, "\\fR");
+	if (di->value) {
+		tag = cstring_append_chars(tag, " ");
+		for (c = di->value; *c; c++) {
+			chunk[0] = *c;
+			tag = cstring_append_chars(tag, *c == '\\' ? "\\e" : chunk);
+		}
+	}
+	text = cstring_to_chars(tag);
+	cstring_free(tag);
+	if (text) {
+		print_list_tag(manfile, NULL, text);
+		free(text);
+	}
+	if (di->desc) {
+		print_member_desc(manfile, di->desc);
+	}
+	di->printed = 1;
+}
+
+/*
+ * --constants-page, the #defines in the header on a page of their own.
+ * Ones with the same prefix go in a subsection together, as long as
+ * there's more than one subsection.
+ */
+static int print_constants_page(void)
+{
+	char manfilename[PATH_MAX * 2];
+	char brief[PATH_MAX + 32];
+	struct define_info *di;
+	struct define_info *other;
+	struct qb_list_head *iter;
+	struct qb_list_head *iter2;
+	const char *pagename = constants_pagename();
+	FILE *manfile;
+	uint64_t page_hash;
+	int groups = 0;
+	int others = 0;
+	int ret;
+
+	snprintf(manfilename, sizeof(manfilename), "%s/%s.%s", output_dir, pagename, man_section);
+	page_hash = get_page_hash();
+	if (page_is_current(manfilename, page_hash)) {
+		log_msg(LOG_VERBOSE, "%s is up to date\n", manfilename);
+		return 0;
+	}
+
+	ret = open_output_file(manfilename, pagename, "w+", &manfile);
+	if (ret) {
+		return ret == 1 ? 0 : -1;
+	}
+	log_msg(LOG_INFO, "Printing constants manpage for %s\n", headerfile);
+
+	print_generated_comment(manfile);
+	fprintf(manfile, HASH_COMMENT "%016" PRIx64 "\n", page_hash);
+	print_th(manfile, pagename, man_section, page_date());
+	snprintf(brief, sizeof(brief), "constants defined in %s%s", header_prefix, headerfile);
+	print_name_section(manfile, pagename, NULL, brief);
+	print_synopsis_include(manfile);
+	fprintf(manfile, ".fi\n");
+	fprintf(manfile, "%s DESCRIPTION\n", dialect->section);
+	fprintf(manfile, "%s\n", dialect->paragraph);
+	fprintf(manfile, "The %s defined in \\fB%s%s\\fR, with their values.\n",
+		constants_all ? "constants and macros" : "constants", header_prefix, headerfile);
+
+	qb_list_for_each(iter, &defines_list) {
+		di = qb_list_entry(iter, struct define_info, list);
+		if (define_prefix_count(di) > 1) {
+			groups++;
+		} else {
+			others = 1;
+		}
+	}
+
+	fprintf(manfile, "%s CONSTANTS\n", dialect->section);
+	qb_list_for_each(iter, &defines_list) {
+		di = qb_list_entry(iter, struct define_info, list);
+		if (di->printed || define_prefix_count(di) < 2) {
+			continue;
+		}
+		if (groups + others > 1) {
+			fprintf(manfile, "%s \"%.*s*\"\n", dialect->subsection, (int)di->prefix_len, di->name);
+		}
+		qb_list_for_each(iter2, &defines_list) {
+			other = qb_list_entry(iter2, struct define_info, list);
+			if (!other->printed && other->prefix_len == di->prefix_len &&
+			    strncmp(other->name, di->name, di->prefix_len) == 0) {
+				print_define(manfile, other);
+			}
+		}
+	}
+	if (others && groups) {
+		fprintf(manfile, "%s Other\n", dialect->subsection);
+	}
+	if (others) {
+		qb_list_for_each(iter, &defines_list) {
+			di = qb_list_entry(iter, struct define_info, list);
+			if (!di->printed) {
+				print_define(manfile, di);
+			}
+		}
+	}
+
+	fprintf(manfile, "%s SEE ALSO\n", dialect->section);
+	fprintf(manfile, "%s\n", dialect->paragraph);
+	fprintf(manfile, ".nh\n");
+	fprintf(manfile, ".ad l\n");
+	if (print_general) {
+		fprintf(manfile, "\\fI%s\\fR(%s)", headerfile, man_section);
+	}
+	print_extra_see_also(manfile, pagename, print_general);
+	fprintf(manfile, "\n");
+	fprintf(manfile, ".ad\n");
+	fprintf(manfile, ".hy\n");
+	print_manpage_footer(manfile);
+
+	if (close_output_file(manfile, manfilename, pagename)) {
+		return -1;
+	}
+	if (wrap_width && wrap_page(manfilename, pagename)) {
+		return -1;
+	}
+	if (page_hook) {
+		ret = run_page_hook(manfilename, pagename, "constants");
+		if (ret) {
+			return ret;
+		}
+	}
+	if (lint_output) {
+		lint_page(manfilename, pagename);
+	}
+	return 0;
+}
+
+static void free_defines(void)
+{
+	struct define_info *di;
+	struct qb_list_head *iter;
+	struct qb_list_head *tmp;
+
+	qb_list_for_each_safe(iter, tmp, &defines_list) {
+		di = qb_list_entry(iter, struct define_info, list);
+		qb_list_del(&di->list);
+		free(di->name);
+		free(di->value);
+		free(di->desc);
+		free(di);
+	}
+}
+
+/* Same as traverse_members, but to collect enums. The behave like structures for,
+   but, for some reason, are in the main XML file rather than their own */
+static void collect_enums(xmlNode *cur_node, void *arg)
+{
+	xmlNode *this_tag;
+	struct struct_info *si;
+	char *kind;
+	char *refid = NULL;
+	char *name = NULL;
+
+	if (cur_node->name && strcmp((char *)cur_node->name, "memberdef") == 0) {
+
+		kind = get_attr(cur_node, "kind");
+		if (kind && strcmp(kind, "enum") == 0) {
+			refid = get_attr(cur_node, "id");
+
+			for (this_tag = cur_node->children; this_tag; this_tag = this_tag->next) {
+				if (this_tag->type == XML_ELEMENT_NODE && strcmp((char *)this_tag->name, "name") == 0) {
+					name = strdup((char *)this_tag->children->content);
+				}
+			}
+
+			if (name) {
+				si = malloc(sizeof(struct struct_info));
+				if (si) {
+					memset(si, 0, sizeof(*si));
+					si->kind = STRUCTINFO_ENUM;
+					qb_list_init(&si->params_list);
+					si->structname = name;
+					traverse_node(cur_node, "enumvalue", read_struct, si);
+					qb_map_put(structures_map, refid, si);
+					refid = name = NULL;
+				}
+			}
+			free(name);
+			free(refid);
+		}
+		free(kind);
+	}
+}
+
+/*
+ * When running with -j each worker process is forked after a file has been
+ * read, and only writes every num_jobs'th page of it. Everyone sees the pages
+ * in the same order so nothing gets missed or written twice.
+ */
+static int page_is_ours(void)
+{
+	return (page_counter++ % num_jobs) == job_slot;
+}
+
+/* Whether we want a page for this memberdef (or the header, if arg is set) at all */
+static int page_wanted(xmlNode *cur_node, void *arg)
+{
+	struct function_info *fi;
+	xmlNode *name_node;
+	const char *name;
+
+	if (arg == headerfile) {
+		return !preview_name || strcmp(headerfile, preview_name) == 0;
+	}
+	name_node = find_child(cur_node, "name");
+	if (!name_node || !name_node->children || !name_node->children->content) {
+		return !preview_name;
+	}
+	name = (char *)name_node->children->content;
+	if (!symbol_documented(name)) {
+		return 0;
+	}
+	/* It's on the page of the first function with the same prefix */
+	fi = qb_map_get(function_map, name);
+	if (fi && fi->combined_with) {
+		return 0;
+	}
+	return !preview_name || strcmp(name, preview_name) == 0;
+}
+
+/*
+ * The brief description ends up on one line in NAME, where whatis and
+ * apropos read it, so it wants to be a single plain sentence. Squash it
+ * onto one line without font changes, and if doxygen gave us more than
+ * one sentence move the rest to the start of the DESCRIPTION.
+ */
+static void normalise_brief(char **brief, char **detailed)
+{
+	char *src;
+	char *dst;
+	char *end;
+	char *rest = NULL;
+	char *newdetail;
+	size_t len;
+
+	if (!*brief) {
+		return;
+	}
+
+	for (src = dst = *brief; *src; src++) {
+		if (isspace((unsigned char)*src)) {
+			if (dst > *brief && dst[-1] != ' ') {
+				*dst++ = ' ';
+			}
+			continue;
+		}
+		/* Font changes: \fB, \f(BI and \f[BI], leaving src on their last character */
+		if (src[0] == '\\' && src[1] == 'f' && src[2]) {
+			src += 2;
+			if (*src == '(') {
+				src += (src[1] && src[2]) ? 2 : strlen(src) - 1;
+			} else if (*src == '[') {
+				src += strcspn(src, "]");
+				if (!*src) {
+					src--;
+				}
+			}
+			continue;
+		}
+		*dst++ = *src;
+	}
+	*dst = '\0';
+	while (dst > *brief && dst[-1] == ' ') {
+		*--dst = '\0';
+	}
+
+	/* A full stop followed by a capital letter starts the next sentence */
+	for (end = *brief; (end = strstr(end, ". ")); end++) {
+		if (isupper((unsigned char)end[2])) {
+			rest = end + 2;
+			break;
+		}
+	}
+	if (!rest) {
+		return;
+	}
+
+	len = strlen(rest) + 2 + (*detailed ? strlen(*detailed) : 0);
+	newdetail = malloc(len);
+	if (!newdetail) {
+		return;
+	}
+	snprintf(newdetail, len, "%s\n%s", rest, *detailed ? *detailed : "");
+	free(*detailed);
+	*detailed = newdetail;
+	end[1] = '\0';
+}
+
+/*
+ * The text of a memberdef (or the compounddef for the header page). The
+ * parameters go on params_list, and any @param and @retval descriptions
+ * are added to params_list and retval_list.
+ */
+static void read_member(xmlNode *cur_node, char **def, char **args, char **name, char **brief,
+			char **detailed, char **returntext, struct qb_list_head *notes)
+{
+	char location[PATH_MAX + 16];
+	xmlNode *this_tag;
+	int type;
+
+	for (this_tag = cur_node->children; this_tag; this_tag = this_tag->next)
+	{
+		if (!this_tag->children || !this_tag->children->content)
+			continue;
+
+		if (this_tag->type == XML_ELEMENT_NODE && strcmp((char *)this_tag->name, "definition") == 0)
+			*def = strdup((char *)this_tag->children->content);
+		if (this_tag->type == XML_ELEMENT_NODE && strcmp((char *)this_tag->name, "argsstring") == 0)
+			*args = strdup((char *)this_tag->children->content);
+		if (this_tag->type == XML_ELEMENT_NODE && strcmp((char *)this_tag->name, "name") == 0)
+			*name = strdup((char *)this_tag->children->content);
+
+		if (this_tag->type == XML_ELEMENT_NODE && strcmp((char *)this_tag->name, "briefdescription") == 0) {
+			cstring_t tmp = get_texttree(&type, this_tag, returntext, notes);
+			if (!*brief) {
+				*brief = cstring_to_chars(tmp);
+			} else {
+				report_error(D2M_ERR_DOC, node_location(cur_node, location, sizeof(location)), *name,
+					     "more than one briefdescription");
+			}
+			cstring_free(tmp);
+		}
+		if (this_tag->type == XML_ELEMENT_NODE && strcmp((char *)this_tag->name, "detaileddescription") == 0) {
+			cstring_t tmp = get_texttree(&type, this_tag, returntext, notes);
+			if (!*detailed) {
+				*detailed = cstring_to_chars(tmp);
+			} else {
+				report_error(D2M_ERR_DOC, node_location(cur_node, location, sizeof(location)), *name,
+					     "more than one detaileddescription");
+			}
+			cstring_free(tmp);
+		}
+		/* Get all the params */
+		if (this_tag->type == XML_ELEMENT_NODE && strcmp((char *)this_tag->name, "param") == 0) {
+			cstring_t param_type = get_child(this_tag, "type");
+			cstring_t param_name = get_child(this_tag, "declname");
+			struct param_info *pi = malloc(sizeof(struct param_info));
+			if (pi) {
+				pi->paramname = cstring_to_chars(param_name);
+				pi->paramtype = cstring_to_chars(param_type);
+				pi->paramdesc = NULL;
+				pi->paramvalue = NULL;
+				qb_list_add_tail(&pi->list, &params_list);
+			}
+			cstring_free(param_type);
+			cstring_free(param_name);
+		}
+	}
+}
+
+/* Move the entries of one list to another, empty, one */
+static void move_list(struct qb_list_head *from, struct qb_list_head *to)
+{
+	qb_list_init(to);
+	qb_list_splice(from, to);
+	qb_list_init(from);
+}
+
+/*
+ * --combine-by-prefix, functions starting with one of the prefixes are
+ * documented on the page of the first of them in --sort order. The longest
+ * prefix that matches wins, so eg qb_loop_ and qb_loop_timer_ can both be
+ * given. Returns the length of the prefix, and which one it is in *match.
+ */
+static size_t combine_prefix_len(const char *name, const char **match)
+{
+	const char *prefix;
+	size_t best = 0;
+	size_t len;
+
+	for (prefix = combine_prefix; *prefix; prefix += len + (prefix[len] == ',')) {
+		len = strcspn(prefix, ",");
+		if (len > best && strncmp(name, prefix, len) == 0) {
+			best = len;
+			*match = prefix;
+		}
+	}
+	return best;
+}
+
+static void combine_functions(void)
+{
+	struct function_info **functions;
+	const char *match = NULL;
+	const char *other = NULL;
+	size_t num;
+	size_t len;
+	size_t i;
+	size_t j;
+
+	functions = sorted_functions(&num);
+	for (i = 0; functions && i < num; i++) {
+		len = combine_prefix_len(functions[i]->name, &match);
+		if (!len || !symbol_documented(functions[i]->name)) {
+			continue;
+		}
+		for (j = 0; j < i; j++) {
+			if (!functions[j]->combined_with && symbol_documented(functions[j]->name) &&
+			    combine_prefix_len(functions[j]->name, &other) == len && other == match) {
+				functions[i]->combined_with = functions[j];
+				break;
+			}
+		}
+	}
+	free(functions);
+}
+
+/* Read the other functions that go on the page for 'leader' onto combined_list */
+static void read_combined_functions(const char *leader)
+{
+	struct function_info **functions;
+	struct combined_function *cf;
+	struct qb_list_head saved_params;
+	struct qb_list_head saved_retvals;
+	char *args;
+	size_t num;
+	size_t i;
+
+	functions = sorted_functions(&num);
+	for (i = 0; functions && i < num; i++) {
+		if (!functions[i]->combined_with || strcmp(functions[i]->combined_with->name, leader) != 0) {
+			continue;
+		}
+		cf = calloc(1, sizeof(struct combined_function));
+		if (!cf) {
+			break;
+		}
+		qb_list_init(&cf->notes);
+
+		/* get_texttree() adds to the global lists, so it gets empty ones for each function */
+		move_list(&params_list, &saved_params);
+		move_list(&retval_list, &saved_retvals);
+		args = NULL;
+		read_member(functions[i]->node, &cf->def, &args, &cf->name, &cf->brief,
+			    &cf->detailed, &cf->returntext, &cf->notes);
+		normalise_brief(&cf->brief, &cf->detailed);
+		free(args);
+		move_list(&params_list, &cf->params);
+		move_list(&retval_list, &cf->retvals);
+		move_list(&saved_params, &params_list);
+		move_list(&saved_retvals, &retval_list);
+
+		if (!cf->name) {
+			cf->name = strdup(functions[i]->name);
+		}
+		lint_function(functions[i]->node, cf->name, cf->brief, cf->detailed, cf->returntext,
+			      &cf->params, &cf->retvals);
+		qb_list_add_tail(&cf->list, &combined_list);
+	}
+	free(functions);
+}
+
+static void traverse_members(xmlNode *cur_node, void *arg)
+{
+	qb_list_init(&params_list);
+
+	/* Don't carry on writing pages after something has gone wrong */
+	if (worst_error >= D2M_ERR_PARSE && !keep_going) {
+		return;
+	}
+
+	/* if arg == NULL then we're generating a page for the whole header file */
+	if ((cur_node->name && (strcmp((char *)cur_node->name, "memberdef") == 0)) ||
+	    ((arg == NULL) && cur_node->name && strcmp((char *)cur_node->name, "compounddef")) == 0) {
+		char *kind = NULL;
+		char *def = NULL;
+		char *args = NULL;
+		char *name = NULL;
+		char *brief = NULL;
+		char *detailed = NULL;
+		char *returntext = NULL;
+		struct qb_list_head notes;
+
+		kind=def=args=name=NULL;
+		qb_list_init(&notes);
+
+		kind = get_attr(cur_node, "kind");
+
+		/* Only functions and the header itself get pages */
+		if ((arg != headerfile && (!kind || strcmp(kind, "function") != 0)) ||
+		    !page_wanted(cur_node, arg) || !page_is_ours()) {
+			free(kind);
+			return;
+		}
+		set_source_location(cur_node);
+
+		read_member(cur_node, &def, &args, &name, &brief, &detailed, &returntext, &notes);
+		normalise_brief(&brief, &detailed);
+
+		if (arg == headerfile) {
+			if (spell_command && lint_wanted(LINT_SPELLING)) {
+				add_spell_text(cur_node, headerfile);
+			}
+			/* Print header page */
+			if (print_man && examples_dir) {
+				write_example_file(headerfile, cur_node);
+			}
+			if (print_man) {
+				if (print_manpage((char*)headerfile, def, brief, args, detailed, &params_list, returntext, &notes) == 0) {
+					pages_written++;
+				}
+			}
+			else {
+				if (print_term((char*)headerfile, def, brief, args, detailed, &params_list, returntext, &notes) == 0) {
+					pages_written++;
+				}
+				free_page_info();
+			}
+		}
+
+		if (kind && strcmp(kind, "function") == 0) {
+
+			lint_function(cur_node, name, brief, detailed, returntext, &params_list, &retval_list);
+
+			if (!name) {
+				report_error(D2M_ERR_PARSE, current_xml_file, NULL, "no name found for function");
+			} else {
+				if (print_man) {
+					if (examples_dir) {
+						write_example_file(name, cur_node);
+					}
+					if (combine_prefix) {
+						read_combined_functions(name);
+					}
+					if (references) {
+						collect_references(cur_node, name);
+					}
+					if (print_manpage(name, def, brief, args, detailed, &params_list, returntext, &notes) == 0) {
+						pages_written++;
+					}
+					free_references();
+				}
+				else {
+					if (print_term(name, def, brief, args, detailed, &params_list, returntext, &notes) == 0) {
+						pages_written++;
+					}
+					free_page_info();
+				}
+			}
+
+		}
+
+		set_source_location(NULL);
+		free(kind);
+		free(def);
+		free(args);
+		free(name);
+		free(brief);
+		free(detailed);
+		free(returntext);
+		free_notes(&notes);
+	}
+}
+
+
+static void traverse_node(xmlNode *parentnode, const char *leafname, void (do_members(xmlNode*, void*)), void *arg)
+{
+	xmlNode *cur_node;
+
+	for (cur_node = parentnode->children; cur_node; cur_node = cur_node->next) {
+		if (cur_node->type == XML_ELEMENT_NODE && cur_node->name
+		    && strcmp((char*)cur_node->name, leafname)==0) {
+			if (verbosity >= LOG_DEBUG) {
+				char *id = get_attr(cur_node, "id");
+
+				log_msg(LOG_DEBUG, "%s:%ld: <%s%s%s>\n",
+					cur_node->doc && cur_node->doc->URL ? (char *)cur_node->doc->URL : "", xmlGetLineNo(cur_node),
+					leafname, id ? " id=" : "", id ? id : "");
+				free(id);
+			}
+			do_members(cur_node, arg);
+			continue;
+		}
+		if (cur_node->type == XML_ELEMENT_NODE) {
+			traverse_node(cur_node, leafname, do_members, arg);
+		}
+	}
+}
+
+
+static void usage(const char *name)
+{
+	printf("Usage:\n");
+	printf("      %s [OPTIONS] <XML file> [<XML file> ...]\n", name);
+	printf("\n");
+	printf(" This is a tool to generate API manpages from a doxygen-annotated header file.\n");
+	printf(" First run doxygen on the file and then run this program against the main XML file\n");
+	printf(" it created and the directory containing the ancilliary files. It will then\n");
+	printf(" output a lot of *.3 man page files which you can then ship with your library.\n");
+	printf("\n");
+	printf(" You will need to give this program the name of the generated .xml file for each\n");
+	printf(" .h file in your library, either all at once or by invoking it once per file.\n");
+	printf(" This file will usually be called\n");
+	printf(" something like <include-file>_8h.xml, eg qbipcs_8h.xml\n");
+	printf(" or use a quoted glob pattern such as 'qb*_8h.xml' to match several of them.\n");
+	printf("\n");
+	printf(" If you want HTML output then simpy use nroff on the generated files as you\n");
+	printf(" would do with any other man page.\n");
+	printf("\n");
+	printf(" What to do (only one of these):\n");
+	printf("       -a            Show the man pages on stdout, formatted for the terminal\n");
+	printf("       -m            Write man page files to <output dir>\n");
+	printf("       --list-symbols Just list the functions, structures, enums, typedefs and #defines in the XML files\n");
+	printf("       --preview <name> Show the page for function <name> (or the header) with man, without writing anything\n");
+	printf("       --selftest    Check the generated pages for the bundled fixtures (or those in -d <dir>)\n");
+	printf("       --verify-reproducible Generate the pages twice, in different orders, and check they are the same\n");
+	printf("       --emit-rules <automake|meson|cmake> Print build rules that run doxygen and then doxygen2man on every header in -d <dir>\n");
+	printf("       -h            Print this usage text\n");
+	printf("\n");
+	printf(" Input and output:\n");
+	printf("       -d <dir>      Directory for XML files (./xml/)\n");
+	printf("       --all         Process every header in the XML directory, instead of naming the XML files\n");
+	printf("       --tagfile <file>[=<loc>] Cite references to symbols in another project's doxygen tagfile\n");
+	printf("       --xml-encoding <enc> Read the XML files as <enc>, whatever they say they are\n");
+	printf("       -o <dir>      Write all man pages to <dir>, creating it if needed (default .)\n");
+	printf("       --output-archive <file> Write the man pages to a tar archive, eg docs.tar.gz, instead of -o\n");
+	printf("       --man-dirs    Put the pages in a man<section> directory under <dir> or in the archive\n");
+	printf("       --fhs-layout  Like --man-dirs, and gzip the pages as distributions install them\n");
+	printf("       -O <dir>      Directory for the orignal header file. Often needed by -c below\n");
+	printf("       --config <file>     Read options from <file>, one long option name (and '= value') per line\n");
+	printf("       -j <jobs>     Write man pages using <jobs> parallel processes (default 1)\n");
+	printf("       --write-threads <n> Generate pages in memory and open, write and close the files from <n> threads\n");
+	printf("       --force       Regenerate pages even if their input has not changed,\n");
+	printf("                     and overwrite files that doxygen2man did not write\n");
+	printf("       --no-clobber  Never overwrite an existing page\n");
+	printf("       --backup      Rename an existing page to <page>~ before overwriting it\n");
+	printf("       --keep-going  Carry on after errors and write as many pages as possible\n");
+	printf("       --no-pager    Don't send -a output to a terminal through $PAGER\n");
+	printf("       --text-pages  With -a, write each page to <output dir>/<name>.txt instead of stdout\n");
+	printf("       -q            Don't print progress messages\n");
+	printf("       -v            Print more about what's going on, -vv to trace the XML being read\n");
+	printf("\n");
+	printf(" Which pages and symbols:\n");
+	printf("       -g            Print general man page for the whole header file\n");
+	printf("       --examples-dir <dir> Write code examples to <dir> and add a page listing them\n");
+	printf("       --struct-pages Give each structure a page of its own in section 3type\n");
+	printf("       --enum-pages  Give each enum a page of its own in section 3type\n");
+	printf("       --constants-page Put the header's #defines on a <header>_constants page\n");
+	printf("       --constants-all Include #defines that aren't ALLCAPS (eg macros) on the constants page\n");
+	printf("       --include-symbols <regex>  Only document functions, structures and defines matching <regex>\n");
+	printf("       --exclude-symbols <regex>  Don't document functions, structures and defines matching <regex>\n");
+	printf("       --symbol-prefix <prefix>[,<prefix>...] Only document symbols starting with <prefix>, eg qb_,QB_\n");
+	printf("       --combine-by-prefix <prefix>[,<prefix>...] Document the functions starting with <prefix> on one page\n");
+	printf("       --exclude-static Don't document static functions defined in the header\n");
+	printf("       --exclude-inline Don't document inline functions defined in the header\n");
+	printf("       --exported-symbols <file> Only document functions listed in <file>, a list of names or an ld version script\n");
+	printf("       --symbol-info <file> Add VERSIONS and STABILITY sections from <file>, lines of: <symbol> <version>|- [<status>]\n");
+	printf("       --sort <source|alpha|group> Order of pages and SEE ALSO lists (default source)\n");
+	printf("       --index-page <name> Write a page called <name> listing every function with its brief description\n");
+	printf("       --deprecated-page <name> Write a section 7 page called <name> listing every deprecated symbol\n");
+	printf("\n");
+	printf(" Page content:\n");
+	printf("       -P            Print PARAMS section\n");
+	printf("       --synopsis-width <n> Wrap SYNOPSIS declarations to fit in <n> columns (default 80)\n");
+	printf("       --max-param-type <n> Put parameter types of <n> columns or more on a line of their own\n");
+	printf("       --wrap-width <n> Wrap lines of text in the pages at <n> columns\n");
+	printf("       --example-macros Wrap code listings in .EX/.EE rather than .nf/.fi\n");
+	printf("       --dialect <dialect> Write the pages with the man (default) or groff macros\n");
+	printf("       --style <class>=<font>,... Fonts for type, function, param, code, emphasis, ref and title:\n");
+	printf("                     bold, italic, underline, bold-italic or roman\n");
+	printf("       --references  Add USES and USED BY sections from doxygen's REFERENCES_RELATION and REFERENCED_BY_RELATION\n");
+	printf("       --source-location Add a SOURCE section giving the header file and line each function is declared at\n");
+	printf("       --header-includes Add an INCLUDES section to the header page (-g) with the headers it includes and is included by\n");
+	printf("       -s <s>        Write man pages into section <s> <default 3)\n");
+	printf("       -p <package>  Use <package> name. default <Package>\n");
+	printf("       -H <header>   Set header (default \"Programmer's Manual\"\n");
+	printf("       --title-case <upper|asis> How to write the page name in the .TH line (default upper)\n");
+	printf("       --th-source <source> Source field of the .TH line (default the -p package and --package-version)\n");
+	printf("       --package-version <version> Version of the package, eg 2.0.8, for the .TH line\n");
+	printf("       --th-manual <manual> Manual field of the .TH line (default the -H header)\n");
+	printf("       --generated-comment <text> Replace the 'Automatically generated' comment at the top of each page\n");
+	printf("       --no-generated-comment Leave out the 'Automatically generated' comment\n");
+	printf("       --source-revision <rev>|auto Note the revision the pages were generated from, auto asks git\n");
+	printf("       --colophon    Add a COLOPHON section saying what the page is part of and the --source-revision\n");
+	printf("       -I <include>  Set include filename (default taken from xml)\n");
+	printf("       -i <prefix>   Prefix for include files. eg qb/ (default \"\")\n");
+	printf("       -D <date>     Date to print at top of man pages, YYYY-MM-DD or eg 'January 2021' (default: today)\n");
+	printf("       --date-from-header Use the date the header file (see -O) was last changed instead of -D\n");
+	printf("       --no-dates    Leave the date out of .TH and the years out of the copyright line\n");
+	printf("       --see-also [<page>:]<name>(<section>),... Add to SEE ALSO on every page, or just <page>\n");
+	printf("       --see-also-group Only list functions in the same doxygen group in SEE ALSO\n");
+	printf("       --bugs-url <url>    Add a REPORTING BUGS section with the URL for bug reports\n");
+	printf("       --bugs-email <addr> Add a REPORTING BUGS section with the email address for bug reports\n");
+	printf("       --page-hook <command> Pass each page through <command>, which can change it or drop it\n");
+	printf("\n");
+	printf(" Copyright and licence:\n");
+	printf("       -c            Use the Copyright date from the header file (if one can be found)\n");
+	printf("       -C <company>  Company name in copyright (defaults to Red Hat)\n");
+	printf("       -S <year>     Start year to print at end of copyright line (default: 2010)\n");
+	printf("       -Y <year>     Year to print at end of copyright line (default: today's year)\n");
+	printf("       --license-file <file> Add a LICENSE section with the text in <file>\n");
+	printf("       --require-spdx Fail if a header file (see -O) has no SPDX-License-Identifier\n");
+	printf("\n");
+	printf(" Packaging:\n");
+	printf("       --manifest <file> Write a list of all the pages generated to <file>\n");
+	printf("       --install-metadata <file> Write where each page is installed, its section, compression and header to <file>\n");
+	printf("       --whatis-file <file> Write a whatis line (name (section) - brief) for each page to <file>\n");
+	printf("       --json-index <file> Write the kind, brief, signature and page of each symbol to <file> as JSON\n");
+	printf("       --tags-file <file> Write a ctags file for the documented symbols to <file>\n");
+	printf("       --generate-tagfile <file> Write a doxygen tagfile so other projects can link to the pages\n");
+	printf("       --symbol-db <file> Keep the symbols of every run in <file>, so refs to other headers name their pages\n");
+	printf("       --old-manifest <file> Report pages listed in <file> that are no longer generated\n");
+	printf("       --alias-stubs Write .so pages pointing to the header page for pages in --old-manifest\n");
+	printf("       --alias-links <so|symlink|hardlink> Make aliases, for --alias-stubs and --combine-by-prefix, as .so pages or links (default so)\n");
+	printf("       --clean       Remove pages in --old-manifest (or the last --manifest) that are no longer generated\n");
+	printf("       --emit-install-rules <make|meson|cmake> Print rules to install the generated pages\n");
+	printf("\n");
+	printf(" Checking:\n");
+	printf("       --lint-output Check each page written with mandoc -Tlint, or some simple checks if there's no mandoc\n");
+	printf("       --lint-docs   Check the doxygen comments, eg \\param names that aren't parameters\n");
+	printf("       --no-lint <check>,... Leave out some of the --lint-docs checks\n");
+	printf("       --lint-level <check>=error|warn|off,... How each check is reported: undocumented,\n");
+	printf("                     brief, params, return, deprecated, spelling\n");
+	printf("       --spell-check <command> Check the descriptions with <command>, eg 'aspell list'\n");
+	printf("       --spell-words <file> Words for --spell-check to accept, one per line\n");
+	printf("       --strict      Problems found by --lint-output or --lint-docs are errors rather than warnings\n");
+	printf("       --todo-report <file> Write the @todo and @bug items from all of the headers to <file>\n");
+	printf("       --todo-format <text|json> Format of the --todo-report (default text)\n");
+	printf("\n");
+	printf(" All of the single letter options also have long names, see doxygen2man(1).\n");
+}
+
+static long get_year(const char *optionarg, char optionchar)
+{
+	char *end;
+	long year = strtol(optionarg, &end, 10);
+	/*
+	 * Don't make too many assumptions about the year. I was on call at the
+	 * 2000 rollover. #experience
+	 */
+	if (year == LONG_MIN || year == LONG_MAX ||
+	    year < 1900 || *end != '\0') {
+		fprintf(stderr, "Value passed to -%c is not a valid year number\n", optionchar);
+		return 0;
+	}
+	return year;
+}
+
+/* -D, the forms of date that man pages usually have */
+static int check_date(const char *optionarg)
+{
+	const char *formats[] = {"%Y-%m-%d", "%B %Y", "%B %d, %Y", "%d %B %Y", NULL};
+	struct tm tm;
+	struct tm check;
+	const char *end;
+	int i;
+
+	for (i = 0; formats[i]; i++) {
+		memset(&tm, 0, sizeof(tm));
+		end = strptime(optionarg, formats[i], &tm);
+		if (!end || *end != '\0') {
+			continue;
+		}
+		/* strptime() takes 2021-02-30, mktime() moves it on into March */
+		if (tm.tm_mday == 0) {
+			tm.tm_mday = 1;
+		}
+		check = tm;
+		check.tm_isdst = -1;
+		if (mktime(&check) == -1 || check.tm_mday != tm.tm_mday ||
+		    check.tm_mon != tm.tm_mon || check.tm_year != tm.tm_year) {
+			fprintf(stderr, "Value passed to -D is not a date that exists\n");
+			return -1;
+		}
+		return 0;
+	}
+	fprintf(stderr, "Value passed to -D is not a date, use YYYY-MM-DD or eg 'January 2021'\n");
+	return -1;
+}
+
+/*
+ * --lint-level, a comma separated list of <check>=<level>. --no-lint is
+ * the same with just the checks, which are all turned off.
+ */
+static int set_lint_levels(const char *optionarg, const char *option)
+{
+	static const char *level_names[] = {"", "off", "warn", "error", NULL};
+	enum lint_level levels[LINT_NUM_CHECKS];
+	enum lint_level level;
+	const char *c = optionarg;
+	size_t namelen;
+	size_t len;
+	int check;
+
+	memcpy(levels, lint_levels, sizeof(levels));
+	while (*c) {
+		len = strcspn(c, ",");
+		namelen = strcspn(c, ",=");
+		for (check = 0; check < LINT_NUM_CHECKS; check++) {
+			if (strlen(lint_check_names[check]) == namelen &&
+			    strncmp(c, lint_check_names[check], namelen) == 0) {
+				break;
+			}
+		}
+		if (check == LINT_NUM_CHECKS) {
+			fprintf(stderr, "Value passed to %s has an unknown check '%.*s'\n", option, (int)namelen, c);
+			return -1;
+		}
+
+		if (strcmp(option, "--no-lint") == 0 && namelen == len) {
+			level = LINT_OFF;
+		} else if (strcmp(option, "--no-lint") != 0 && namelen < len) {
+			for (level = LINT_OFF; level_names[level]; level++) {
+				if (strlen(level_names[level]) == len - namelen - 1 &&
+				    strncmp(c + namelen + 1, level_names[level], len - namelen - 1) == 0) {
+					break;
+				}
+			}
+			if (!level_names[level]) {
+				fprintf(stderr, "Value passed to %s needs error, warn or off for '%.*s'\n",
+					option, (int)namelen, c);
+				return -1;
+			}
+		} else {
+			fprintf(stderr, "Value passed to %s is not a list of %s\n", option,
+				strcmp(option, "--no-lint") == 0 ? "checks" : "<check>=<level>");
+			return -1;
+		}
+		levels[check] = level;
+
+		c += len;
+		c += strspn(c, ",");
+	}
+	memcpy(lint_levels, levels, sizeof(levels));
+	return 0;
+}
+
+/*
+ * --style, a comma separated list of <class>=<font>. troff has no underline
+ * font, it's italic, which nroff shows underlined on a terminal.
+ */
+static int set_styles(const char *optionarg)
+{
+	static const char *font_names[] = {"bold", "italic", "underline", "bold-italic", "roman", NULL};
+	static const char *fonts[] = {"\\fB", "\\fI", "\\fI", "\\f(BI", "\\fR"};
+	const char *c = optionarg;
+	size_t namelen;
+	size_t len;
+	int class;
+	int font;
+
+	while (*c) {
+		len = strcspn(c, ",");
+		namelen = strcspn(c, ",=");
+		for (class = 0; class < STYLE_NUM_CLASSES; class++) {
+			if (strlen(style_class_names[class]) == namelen &&
+			    strncmp(c, style_class_names[class], namelen) == 0) {
+				break;
+			}
+		}
+		if (class == STYLE_NUM_CLASSES) {
+			fprintf(stderr, "Value passed to --style has an unknown class '%.*s'\n", (int)namelen, c);
+			return -1;
+		}
+		if (namelen == len) {
+			fprintf(stderr, "Value passed to --style is not a list of <class>=<font>\n");
+			return -1;
+		}
+		for (font = 0; font_names[font]; font++) {
+			if (strlen(font_names[font]) == len - namelen - 1 &&
+			    strncmp(c + namelen + 1, font_names[font], len - namelen - 1) == 0) {
+				break;
+			}
+		}
+		if (!font_names[font]) {
+			fprintf(stderr, "Value passed to --style needs bold, italic, underline, bold-italic or roman for '%.*s'\n",
+				(int)namelen, c);
+			return -1;
+		}
+		styles[class] = fonts[font];
+
+		c += len;
+		c += strspn(c, ",");
+	}
+	return 0;
+}
+
+/* -s, a section number, optionally with a suffix such as 3qb */
+static int check_section(const char *optionarg)
+{
+	size_t i;
+
+	if (!strchr("123456789nlo", optionarg[0]) || optionarg[0] == '\0' ||
+	    strlen(optionarg) > 16) {
+		goto bad;
+	}
+	for (i = 1; optionarg[i]; i++) {
+		if (!isalnum(optionarg[i])) {
+			goto bad;
+		}
+	}
+	return 0;
+bad:
+	fprintf(stderr, "Value passed to -s is not a man page section (1-9, n, l or o, with an optional suffix)\n");
+	return -1;
+}
+
+/* Anything that goes in a quoted field of the .TH line */
+static int check_th_field(const char *optionarg, const char *option)
+{
+	if (strpbrk(optionarg, "\"\n")) {
+		fprintf(stderr, "Value passed to %s can't contain quotes or newlines\n", option);
+		return -1;
+	}
+	return 0;
+}
+
+/* -d, the XML has to be somewhere */
+static int check_dir(const char *optionarg, char optionchar)
+{
+	struct stat st;
+
+	if (stat(optionarg, &st) == -1 || !S_ISDIR(st.st_mode)) {
+		fprintf(stderr, "Value passed to -%c is not a directory: %s\n", optionchar, optionarg);
+		return -1;
+	}
+	return 0;
+}
+
+/*
+ * Collect all of the Copyright lines from the top of the header file, in
+ * either style of comment, one per line of the COPYRIGHT section.
+ */
+static void read_header_copyright(void)
+{
+	char file_path[PATH_MAX];
+	char file_line[256];
+	char *start;
+	char *end;
+	size_t used = 0;
+	FILE *hfile;
+	int lineno = 0;
+
+	snprintf(file_path, sizeof(file_path), "%s/%s", header_src_dir, headerfile);
+	hfile = fopen(file_path, "r");
+	if (hfile) {
+		/* Don't look too far, this should be at the top */
+		while (!feof(hfile) && (lineno++ < 30)) {
+			if (!fgets(file_line, sizeof(file_line)-1, hfile)) {
+				break;
+			}
+
+			/* Skip the comment leader, whatever it looks like */
+			start = file_line + strspn(file_line, " \t");
+			if (strncmp(start, "//", 2) == 0 || strncmp(start, "/*", 2) == 0) {
+				start += 2;
+			}
+			start += strspn(start, " \t*");
+			if (strncmp(start, "Copyright", 9) != 0 || !strchr(" \t(:", start[9])) {
+				continue;
+			}
+
+			/* ... and the end of a one-line comment */
+			end = start + strlen(start);
+			while (end > start && isspace(end[-1])) {
+				end--;
+			}
+			if (end - start >= 2 && strncmp(end - 2, "*/", 2) == 0) {
+				end -= 2;
+				while (end > start && isspace(end[-1])) {
+					end--;
+				}
+			}
+			*end = '\0';
+
+			used += snprintf(header_copyright + used, sizeof(header_copyright) - used, "%s%s\n",
+					 used ? ".br\n" : "", start);
+			if (used >= sizeof(header_copyright)) {
+				header_copyright[sizeof(header_copyright) - 1] = '\0';
+				break;
+			}
+		}
+		fclose(hfile);
+	}
+}
+
+/* --date-from-header, the date the header file was last changed */
+static void read_header_date(void)
+{
+	char file_path[PATH_MAX];
+	struct stat st;
+	struct tm *tm;
+
+	snprintf(file_path, sizeof(file_path), "%s/%s", header_src_dir, headerfile);
+	if (stat(file_path, &st) == -1 || !(tm = localtime(&st.st_mtime))) {
+		report_error(D2M_ERR_DOC, file_path, NULL, "unable to get the header's date for --date-from-header: %s",
+			     strerror(errno));
+		return;
+	}
+	strftime(header_date, sizeof(header_date), "%Y-%m-%d", tm);
+}
+
+/* The SPDX-License-Identifier tag near the top of the header file, if it has one */
+static void read_header_spdx(void)
+{
+	char file_path[PATH_MAX];
+	char file_line[256];
+	char *start;
+	FILE *hfile;
+	int lineno = 0;
+
+	snprintf(file_path, sizeof(file_path), "%s/%s", header_src_dir, headerfile);
+	hfile = fopen(file_path, "r");
+	if (!hfile) {
+		return;
+	}
+	while (lineno++ < 30 && fgets(file_line, sizeof(file_line), hfile)) {
+		start = strstr(file_line, "SPDX-License-Identifier:");
+		if (start) {
+			start += strlen("SPDX-License-Identifier:");
+			start += strspn(start, " \t");
+			/* The expression ends at the end of the line, or the comment */
+			start[strcspn(start, "\r\n")] = '\0';
+			if (strstr(start, "*/")) {
+				*strstr(start, "*/") = '\0';
+			}
+			while (*start && isspace(start[strlen(start) - 1])) {
+				start[strlen(start) - 1] = '\0';
+			}
+			snprintf(header_spdx, sizeof(header_spdx), "%s", start);
+			break;
+		}
+	}
+	fclose(hfile);
+}
+
+/*
+ * Remember all of the pages that this run produces. This is filled in from the
+ * collection passes so every -j worker has the whole list, not just the pages
+ * it writes itself. 'brief' is handed over to the page.
+ */
+static void add_page(const char *pagename, const char *header_name, const char *kind, char *brief)
+{
+	struct page_info *pi;
+
+	pi = malloc(sizeof(struct page_info));
+	if (!pi) {
+		free(brief);
+		return;
+	}
+	pi->header = strdup(header_name);
+	pi->kind = kind;
+	pi->brief = brief;
+	pi->alias_of = NULL;
+	qb_map_put(all_pages, strdup(pagename), pi);
+}
+
+static void add_symbol_page(const char *name, const char *kind, char *brief)
+{
+	char pagename[PATH_MAX + 16];
+
+	snprintf(pagename, sizeof(pagename), "%s.%s", name, man_section);
+	add_page(pagename, headerfile, kind, brief);
+}
+
+/* A function on another function's page still gets a page of its own name, pointing there */
+static void add_alias_page(const char *name, const char *target)
+{
+	char pagename[PATH_MAX];
+	struct page_info *pi;
+
+	add_symbol_page(name, "alias", NULL);
+	snprintf(pagename, sizeof(pagename), "%s.%s", name, man_section);
+	pi = qb_map_get(all_pages, pagename);
+	if (pi) {
+		snprintf(pagename, sizeof(pagename), "%s.%s", target, man_section);
+		pi->alias_of = strdup(pagename);
+	}
+}
+
+/* The NAME line description, without any markup */
+static char *plain_brief(xmlNode *node)
+{
+	xmlNode *brief_node = find_child(node, "briefdescription");
+	xmlChar *content;
+	char *brief = NULL;
+	char *rest = NULL;
+
+	if (!brief_node) {
+		return NULL;
+	}
+	content = xmlNodeGetContent(brief_node);
+	if (content) {
+		brief = strdup((char *)content);
+		xmlFree(content);
+	}
+	normalise_brief(&brief, &rest);
+	free(rest);
+	return brief;
+}
+
+/* The SEE ALSO of a structure's page, each page with a function that uses it. Returns how many */
+static int print_struct_users(FILE *manfile, const char *name)
+{
+	struct function_info *fi;
+	struct function_info *page_fi;
+	struct function_info *other;
+	int count = 0;
+	size_t i;
+	size_t j;
+
+	for (i = 0; i < see_also_count; i++) {
+		fi = see_also_order[i];
+		if (!function_uses_type(fi, name)) {
+			continue;
+		}
+
+		/* A combined page is only listed once, under its first function */
+		page_fi = fi->combined_with ? fi->combined_with : fi;
+		for (j = 0; j < i; j++) {
+			other = see_also_order[j];
+			if ((other->combined_with ? other->combined_with : other) == page_fi &&
+			    function_uses_type(other, name)) {
+				break;
+			}
+		}
+		if (j < i) {
+			continue;
+		}
+		fprintf(manfile, "%s\\fI%s\\fR(%s)", count ? ", " : "", page_fi->name, man_section);
+		count++;
+	}
+	return count;
+}
+
+/*
+ * The VALUES of an --enum-pages page, every enumerator with its value.
+ * One without an initializer is one more than the one before it.
+ */
+static void print_enum_values(FILE *manfile, struct struct_info *si)
+{
+	struct qb_list_head *iter;
+	struct param_info *pi;
+	const char *prev_name = NULL;
+	char tag[1024];
+	char *end;
+	long prev_value = -1;
+	int prev_known = 1;
+
+	fprintf(manfile, "%s VALUES\n", dialect->section);
+	qb_list_for_each(iter, &si->params_list) {
+		pi = qb_list_entry(iter, struct param_info, list);
+		if (pi->paramvalue) {
+			snprintf(tag, sizeof(tag), "\\fB%s\\fR = %s", pi->paramname, pi->paramvalue);
+			prev_value = strtol(pi->paramvalue, &end, 0);
+			prev_known = (end != pi->paramvalue && *end == '\0');
+		} else if (prev_known) {
+			snprintf(tag, sizeof(tag), "\\fB%s\\fR = %ld", pi->paramname, ++prev_value);
+		} else {
+			snprintf(tag, sizeof(tag), "\\fB%s\\fR = %s + 1", pi->paramname, prev_name);
+		}
+		print_list_tag(manfile, NULL, tag);
+		prev_name = pi->paramname;
+		if (pi->paramdesc) {
+			print_member_desc(manfile, pi->paramdesc);
+		}
+	}
+}
+
+/*
+ * --struct-pages and --enum-pages, a page for a structure or an enum on its
+ * own, in the type section. The members are in the SYNOPSIS and any that are
+ * documented get a description of their own. An enum's description is in its
+ * memberdef, so it's passed in.
+ */
+static int print_struct_page(const char *refid, struct struct_info *si,
+			     const char *brief_description, char *description)
+{
+	const char *kind = si->kind == STRUCTINFO_ENUM ? "enum" : "struct";
+	char manfilename[PATH_MAX];
+	char tag[1024];
+	struct qb_list_head *iter;
+	struct param_info *pi;
+	uint64_t page_hash;
+	FILE *manfile;
+	char *brief;
+	char *rest = NULL;
+	int max_param_type_len = 0;
+	int any_desc = 0;
+	int see_also_entries;
+	int ret;
+
+	snprintf(manfilename, sizeof(manfilename), "%s/%s.%s", output_dir, si->structname, type_section());
+
+	/*
+	 * The structure's own XML file goes into the hash like any structure a
+	 * function uses, an enum is in the header's
+	 */
+	if (si->kind == STRUCTINFO_STRUCT) {
+		qb_map_put(used_structures_map, strdup(refid), strdup(si->structname));
+	}
+	page_hash = get_page_hash();
+	if (page_is_current(manfilename, page_hash)) {
+		log_msg(LOG_VERBOSE, "%s is up to date\n", manfilename);
+		free_page_info();
+		return 0;
+	}
+
+	ret = open_output_file(manfilename, si->structname, "w+", &manfile);
+	if (ret) {
+		free_page_info();
+		return ret == 1 ? 0 : -1;
+	}
+	log_msg(LOG_INFO, "Printing %s manpage for %s\n",
+		si->kind == STRUCTINFO_STRUCT ? "structure" : "enum", si->structname);
+
+	brief = brief_description ? strdup(brief_description) : NULL;
+	normalise_brief(&brief, &rest);
+
+	print_generated_comment(manfile);
+	fprintf(manfile, HASH_COMMENT "%016" PRIx64 "\n", page_hash);
+	print_th(manfile, si->structname, type_section(), page_date());
+
+	print_name_section(manfile, si->structname, NULL, brief);
+
+	qb_list_for_each(iter, &si->params_list) {
+		pi = qb_list_entry(iter, struct param_info, list);
+		if (display_width(pi->paramtype) > max_param_type_len) {
+			max_param_type_len = display_width(pi->paramtype);
+		}
+		if (pi->paramdesc) {
+			any_desc = 1;
+		}
+	}
+
+	print_synopsis_include(manfile);
+	fprintf(manfile, ".sp\n");
+	fprintf(manfile, "\\fB%s %s {\\fP\n", kind, si->structname);
+	qb_list_for_each(iter, &si->params_list) {
+		pi = qb_list_entry(iter, struct param_info, list);
+		if (si->kind == STRUCTINFO_ENUM) {
+			fprintf(manfile, "    \\fB%s\\fP%s%s%s\n", pi->paramname,
+				pi->paramvalue ? " = " : "", pi->paramvalue ? pi->paramvalue : "",
+				iter->next != &si->params_list ? "," : "");
+		} else {
+			print_param(manfile, pi, max_param_type_len, 1, ";");
+		}
+	}
+	fprintf(manfile, "\\fB};\\fP\n");
+	fprintf(manfile, "%s\n", dialect->nofill_end);
+
+	if (rest || (description && not_all_whitespace(description))) {
+		fprintf(manfile, "%s DESCRIPTION\n", dialect->section);
+		if (rest) {
+			man_print_long_string(manfile, rest);
+		}
+		if (description) {
+			man_print_long_string(manfile, description);
+		}
+	}
+
+	if (si->kind == STRUCTINFO_ENUM) {
+		print_enum_values(manfile, si);
+	} else if (any_desc) {
+		fprintf(manfile, "%s MEMBERS\n", dialect->section);
+		qb_list_for_each(iter, &si->params_list) {
+			pi = qb_list_entry(iter, struct param_info, list);
+			if (!pi->paramdesc) {
+				continue;
+			}
+			/* Just the name, not the array size or a function pointer's arguments */
+			snprintf(tag, sizeof(tag), "%.*s", (int)strcspn(pi->paramname, ")[("), pi->paramname);
+			print_list_tag(manfile, dialect->italic, tag);
+			print_member_desc(manfile, pi->paramdesc);
+		}
+	}
+
+	fprintf(manfile, "%s SEE ALSO\n", dialect->section);
+	fprintf(manfile, "%s\n", dialect->paragraph);
+	fprintf(manfile, ".nh\n");
+	fprintf(manfile, ".ad l\n");
+	see_also_entries = print_struct_users(manfile, si->structname);
+	if (print_general) {
+		fprintf(manfile, "%s\\fI%s\\fR(%s)", see_also_entries ? ", " : "", headerfile, man_section);
+		see_also_entries++;
+	}
+	print_extra_see_also(manfile, si->structname, see_also_entries);
+	fprintf(manfile, "\n");
+	fprintf(manfile, ".ad\n");
+	fprintf(manfile, ".hy\n");
+	print_manpage_footer(manfile);
+	free_page_info();
+	free(brief);
+	free(rest);
+
+	if (close_output_file(manfile, manfilename, si->structname)) {
+		return -1;
+	}
+	if (wrap_width && wrap_page(manfilename, si->structname)) {
+		return -1;
+	}
+	if (page_hook) {
+		ret = run_page_hook(manfilename, si->structname, kind);
+		if (ret) {
+			return ret;
+		}
+	}
+	if (lint_output) {
+		lint_page(manfilename, si->structname);
+	}
+	return 0;
+}
+
+/* The structure an <innerclass> refers to, if it gets a --struct-pages page */
+static struct struct_info *struct_page_info(xmlNode *cur_node, char **refid)
+{
+	struct struct_info *si = NULL;
+	const char *name;
+	char *id;
+
+	id = get_attr(cur_node, "refid");
+	if (!id || strncmp(id, "struct", 6) != 0 || !cur_node->children || !cur_node->children->content) {
+		goto out;
+	}
+	/* Nested structures are documented with the one they're in */
+	name = (char *)cur_node->children->content;
+	if (strstr(name, "::") || !symbol_documented(name)) {
+		goto out;
+	}
+	si = qb_map_get(structures_map, id);
+	if (!si && read_structure_from_xml(id, name) == 0) {
+		si = qb_map_get(structures_map, id);
+	}
+out:
+	if (si) {
+		*refid = id;
+	} else {
+		free(id);
+	}
+	return si;
+}
+
+/* Called from traverse_node() */
+static void add_struct_page(xmlNode *cur_node, void *arg)
+{
+	struct struct_info *si;
+	char pagename[PATH_MAX];
+	char *brief;
+	char *rest = NULL;
+	char *refid;
+
+	si = struct_page_info(cur_node, &refid);
+	if (!si) {
+		return;
+	}
+	brief = si->brief_description ? strdup(si->brief_description) : NULL;
+	normalise_brief(&brief, &rest);
+	free(rest);
+	snprintf(pagename, sizeof(pagename), "%s.%s", si->structname, type_section());
+	add_page(pagename, headerfile, "struct", brief);
+	free(refid);
+}
+
+/* Called from traverse_node(), after the functions' pages */
+static void traverse_struct(xmlNode *cur_node, void *arg)
+{
+	struct struct_info *si;
+	char *refid;
+
+	if (worst_error >= D2M_ERR_PARSE && !keep_going) {
+		return;
+	}
+	si = struct_page_info(cur_node, &refid);
+	if (!si) {
+		return;
+	}
+	if ((!preview_name || strcmp(si->structname, preview_name) == 0) && page_is_ours()) {
+		if (print_struct_page(refid, si, si->brief_description, si->description) == 0) {
+			pages_written++;
+		}
+	}
+	free(refid);
+}
+
+/* The enum a <memberdef> is, if it gets an --enum-pages page. Anonymous ones are called @0 etc */
+static struct struct_info *enum_page_info(xmlNode *cur_node, char **refid)
+{
+	struct struct_info *si = NULL;
+	char *kind;
+	char *id = NULL;
+
+	kind = get_attr(cur_node, "kind");
+	if (kind && strcmp(kind, "enum") == 0) {
+		id = get_attr(cur_node, "id");
+		si = id ? qb_map_get(structures_map, id) : NULL;
+	}
+	if (si && (si->structname[0] == '@' || !symbol_documented(si->structname))) {
+		si = NULL;
+	}
+	if (si) {
+		*refid = id;
+	} else {
+		free(id);
+	}
+	free(kind);
+	return si;
+}
+
+/* Called from traverse_node() */
+static void add_enum_page(xmlNode *cur_node, void *arg)
+{
+	struct struct_info *si;
+	char pagename[PATH_MAX];
+	char *refid;
+
+	si = enum_page_info(cur_node, &refid);
+	if (!si) {
+		return;
+	}
+	snprintf(pagename, sizeof(pagename), "%s.%s", si->structname, type_section());
+	add_page(pagename, headerfile, "enum", plain_brief(cur_node));
+	free(refid);
+}
+
+/* Called from traverse_node(), after the structures' pages */
+static void traverse_enum(xmlNode *cur_node, void *arg)
+{
+	struct struct_info *si;
+	struct struct_info desc;
+	char *refid;
+
+	if (worst_error >= D2M_ERR_PARSE && !keep_going) {
+		return;
+	}
+	si = enum_page_info(cur_node, &refid);
+	if (!si) {
+		return;
+	}
+	if ((!preview_name || strcmp(si->structname, preview_name) == 0) && page_is_ours()) {
+		memset(&desc, 0, sizeof(desc));
+		read_structdesc(cur_node, &desc);
+		if (print_struct_page(refid, si, desc.brief_description, desc.description) == 0) {
+			pages_written++;
+		}
+		free(desc.brief_description);
+		free(desc.description);
+	}
+	free(refid);
+}
+
+static void add_file_pages(xmlNode *compounddef)
+{
+	qb_map_iter_t *map_iter;
+	struct function_info *fi;
+	const char *p;
+	void *data;
+	char brief[PATH_MAX + 32];
+
+	qb_map_put(all_headers, strdup(headerfile), strdup(headerfile));
+
+	map_iter = qb_map_iter_create(function_map);
+	for (p = qb_map_iter_next(map_iter, &data); p; p = qb_map_iter_next(map_iter, &data)) {
+		fi = data;
+		if (!symbol_documented(p)) {
+			continue;
+		}
+		if (fi->combined_with) {
+			add_alias_page(p, fi->combined_with->name);
+		} else {
+			add_symbol_page(p, "function", plain_brief(fi->node));
+		}
+	}
+	qb_map_iter_free(map_iter);
+
+	if (struct_pages && compounddef) {
+		traverse_node(compounddef, "innerclass", add_struct_page, NULL);
+	}
+	if (enum_pages && compounddef) {
+		traverse_node(compounddef, "memberdef", add_enum_page, NULL);
+	}
+	if (print_general) {
+		add_symbol_page(headerfile, "header", compounddef ? plain_brief(compounddef) : NULL);
+	}
+	if (examples_dir && !qb_list_empty(&examples_list)) {
+		snprintf(brief, sizeof(brief), "example code for %s%s", header_prefix, headerfile);
+		add_symbol_page(examples_pagename(), "examples", strdup(brief));
+	}
+	if (!qb_list_empty(&defines_list)) {
+		snprintf(brief, sizeof(brief), "constants defined in %s%s", header_prefix, headerfile);
+		add_symbol_page(constants_pagename(), "constants", strdup(brief));
+	}
+}
+
+/*
+ * The symbols for --json-index and --tags-file. 'signature' and 'brief' are
+ * handed over, 'node' has the <location>. 'section' is the one the page is
+ * in, NULL for the usual one. A name can only be in the index once, the
+ * first header it's in wins.
+ */
+static void add_index_symbol(const char *name, const char *kind, char *signature, char *brief,
+			     const char *pagename, const char *section, xmlNode *node)
+{
+	struct index_symbol *is;
+	xmlNode *location;
+	char page[PATH_MAX * 2];
+	char *line;
+
+	if (qb_map_get(index_symbols, name) || !(is = malloc(sizeof(struct index_symbol)))) {
+		free(signature);
+		free(brief);
+		return;
+	}
+	is->kind = kind;
+	is->header = strdup(headerfile);
+	is->brief = brief;
+	is->signature = signature;
+	is->arglist = NULL;
+	is->page = NULL;
+	if (pagename && print_man) {
+		snprintf(page, sizeof(page), "%s%s.%s%s", listed_dir(output_dir),
+			 pagename, section ? section : man_section, page_suffix);
+		is->page = strdup(page);
+	}
+	is->file = NULL;
+	is->line = 0;
+	location = node ? find_child(node, "location") : NULL;
+	if (location) {
+		is->file = get_attr(location, "file");
+		line = get_attr(location, "line");
+		if (line) {
+			is->line = atoi(line);
+			free(line);
+		}
+	}
+	qb_map_put(index_symbols, strdup(name), is);
+}
+
+/* The text of a child element, with any markup taken out */
+static char *child_content(xmlNode *node, const char *tag)
+{
+	xmlNode *child = find_child(node, tag);
+	xmlChar *content;
+	char *text = NULL;
+
+	if (child && (content = xmlNodeGetContent(child))) {
+		text = strdup((char *)content);
+		xmlFree(content);
+	}
+	return text;
+}
+
+static void index_member(xmlNode *cur_node, void *arg)
+{
+	struct function_info *fi;
+	struct index_symbol *is;
+	char signature[4096];
+	const char *page;
+	const char *section;
+	xmlNode *value;
+	char *kind;
+	char *name;
+	char *def;
+	char *args;
+	char *init;
+
+	kind = get_attr(cur_node, "kind");
+	name = child_content(cur_node, "name");
+	if (!kind || !name || !symbol_documented(name)) {
+		goto out;
+	}
+
+	/* Functions have their own page, everything else is on the header page */
+	if (strcmp(kind, "function") == 0) {
+		fi = qb_map_get(function_map, name);
+		if (fi) {
+			def = child_content(cur_node, "definition");
+			args = child_content(cur_node, "argsstring");
+			snprintf(signature, sizeof(signature), "%s%s", def ? def : name, args ? args : "");
+			add_index_symbol(name, "function", strdup(signature), plain_brief(cur_node),
+					 fi->combined_with ? fi->combined_with->name : name, NULL, cur_node);
+			is = qb_map_get(index_symbols, name);
+			if (is && args && !is->arglist) {
+				is->arglist = strdup(args);
+			}
+			free(def);
+			free(args);
+		}
+	} else if (strcmp(kind, "enum") == 0) {
+		/* An --enum-pages enum and its values are on its own page */
+		if (enum_pages && name[0] != '@') {
+			page = name;
+			section = type_section();
+		} else {
+			page = print_general ? headerfile : NULL;
+			section = NULL;
+		}
+		snprintf(signature, sizeof(signature), "enum %s", name);
+		add_index_symbol(name, "enum", strdup(signature), plain_brief(cur_node), page, section, cur_node);
+		for (value = cur_node->children; value; value = value->next) {
+			char *value_name;
+
+			if (value->type != XML_ELEMENT_NODE || strcmp((char *)value->name, "enumvalue") != 0 ||
+			    !(value_name = child_content(value, "name"))) {
+				continue;
+			}
+			init = child_content(value, "initializer");
+			snprintf(signature, sizeof(signature), "%s%s%s", value_name, init ? " " : "", init ? init : "");
+			/* enum values don't have a location of their own */
+			add_index_symbol(value_name, "enumvalue", strdup(signature), plain_brief(value),
+					 page, section, cur_node);
+			free(init);
+			free(value_name);
+		}
+	} else if (strcmp(kind, "define") == 0) {
+		init = child_content(cur_node, "initializer");
+		snprintf(signature, sizeof(signature), "#define %s%s%s", name, init ? " " : "", init ? init : "");
+		if (constants_page && define_listed(name)) {
+			page = constants_pagename();
+		} else {
+			page = print_general ? headerfile : NULL;
+		}
+		add_index_symbol(name, "define", strdup(signature), plain_brief(cur_node), page, NULL, cur_node);
+		free(init);
+	} else if (strcmp(kind, "typedef") == 0) {
+		def = child_content(cur_node, "definition");
+		add_index_symbol(name, "typedef", def, plain_brief(cur_node),
+				 print_general ? headerfile : NULL, NULL, cur_node);
+	}
+out:
+	free(kind);
+	free(name);
+}
+
+/* Structures and unions have their own XML file, which has the brief description and location */
+static void index_innerclass(xmlNode *cur_node, void *arg)
+{
+	char fname[PATH_MAX];
+	char signature[1024];
+	const char *kind;
+	xmlNode *rootdoc;
+	xmlNode *compounddef = NULL;
+	xmlDocPtr doc;
+	char *brief = NULL;
+	char *refid;
+	char *name;
+
+	refid = get_attr(cur_node, "refid");
+	if (!refid || !cur_node->children || !cur_node->children->content) {
+		free(refid);
+		return;
+	}
+	name = (char *)cur_node->children->content;
+	if (strncmp(refid, "struct", 6) == 0) {
+		kind = "struct";
+	} else if (strncmp(refid, "union", 5) == 0) {
+		kind = "union";
+	} else {
+		free(refid);
+		return;
+	}
+	if (!symbol_documented(name)) {
+		free(refid);
+		return;
+	}
+
+	snprintf(fname, sizeof(fname), "%s/%s.xml", xml_dir, refid);
+	doc = xmlReadFile(fname, xml_encoding, XML_PARSE_COMPACT | XML_PARSE_NOERROR | XML_PARSE_NOWARNING);
+	if (doc) {
+		rootdoc = xmlDocGetRootElement(doc);
+		compounddef = rootdoc ? find_node(rootdoc, "compounddef") : NULL;
+		if (compounddef) {
+			brief = plain_brief(compounddef);
+		}
+	}
+	snprintf(signature, sizeof(signature), "%s %s", kind, name);
+	if (struct_pages && strcmp(kind, "struct") == 0 && !strstr(name, "::")) {
+		add_index_symbol(name, kind, strdup(signature), brief, name, type_section(), compounddef);
+	} else {
+		add_index_symbol(name, kind, strdup(signature), brief, print_general ? headerfile : NULL,
+				 NULL, compounddef);
+	}
+	if (doc) {
+		xmlFreeDoc(doc);
+	}
+	free(refid);
+}
+
+/*
+ * Make 'pagename' show 'target', with a .so page or a link as --alias-links
+ * says. Links are made to the name the page will have after --fhs-layout
+ * has compressed it, a hard link is kept when it's compressed.
+ */
+static int write_alias(const char *pagename, const char *target)
+{
+	char manfilename[PATH_MAX];
+	char targetname[PATH_MAX];
+	char linktext[PATH_MAX];
+	const char *suffix = "";
+	struct stat st;
+	FILE *manfile;
+	ssize_t len;
+	int ret;
+
+	if (alias_links == ALIAS_SO) {
+		snprintf(manfilename, sizeof(manfilename), "%s/%s", output_dir, pagename);
+		ret = open_output_file(manfilename, pagename, "w", &manfile);
+		if (ret) {
+			return ret == 1 ? 0 : -1;
+		}
+		/* The hash is just so we know it's ours, with or without the comment */
+		print_generated_comment(manfile);
+		fprintf(manfile, HASH_COMMENT "%016" PRIx64 "\n", hash_string(options_hash, target));
+		fprintf(manfile, ".so man%s/%s\n", man_section, target);
+		if (close_output_file(manfile, manfilename, pagename)) {
+			return -1;
+		}
+		log_msg(LOG_INFO, "Writing %s as an alias for %s\n", manfilename, target);
+		return 0;
+	}
+
+	if (compress && alias_links == ALIAS_SYMLINK) {
+		suffix = ".gz";
+	}
+	snprintf(manfilename, sizeof(manfilename), "%s/%s%s", output_dir, pagename, suffix);
+	if (alias_links == ALIAS_SYMLINK) {
+		snprintf(targetname, sizeof(targetname), "%s%s", target, suffix);
+		len = readlink(manfilename, linktext, sizeof(linktext) - 1);
+		if (len >= 0) {
+			linktext[len] = '\0';
+			if (strcmp(linktext, targetname) == 0) {
+				return 0;
+			}
+		}
+	} else {
+		snprintf(targetname, sizeof(targetname), "%s/%s", output_dir, target);
+		if (lstat(manfilename, &st) == 0) {
+			ino_t ino = st.st_ino;
+
+			if (stat(targetname, &st) == 0 && st.st_ino == ino) {
+				return 0;
+			}
+		}
+	}
+
+	ret = replace_output_file(manfilename, pagename);
+	if (ret) {
+		return ret == 1 ? 0 : -1;
+	}
+	/* replace_output_file() leaves an ordinary page there, a link can't overwrite it */
+	if (unlink(manfilename) == -1 && errno != ENOENT) {
+		report_error(D2M_ERR_IO, manfilename, pagename, "unable to remove old page: %s", strerror(errno));
+		return -1;
+	}
+	if (alias_links == ALIAS_SYMLINK) {
+		ret = symlink(targetname, manfilename);
+	} else {
+		ret = link(targetname, manfilename);
+	}
+	if (ret == -1) {
+		report_error(D2M_ERR_IO, manfilename, pagename, "unable to link to %s: %s", target, strerror(errno));
+		return -1;
+	}
+	log_msg(LOG_INFO, "Linking %s to %s\n", manfilename, target);
+	return 0;
+}
+
+/* Point a page that has gone away at the page for the header it used to be in */
+static int write_alias_stub(const char *pagename, const char *old_header)
+{
+	char headerpage[PATH_MAX];
+
+	snprintf(headerpage, sizeof(headerpage), "%s.%s", old_header, man_section);
+	if (!qb_map_get(all_pages, headerpage)) {
+		report_error(D2M_ERR_IO, old_manifest, pagename, "no page for %s to point at, use -g", old_header);
+		return -1;
+	}
+	if (write_alias(pagename, headerpage)) {
+		return -1;
+	}
+	add_page(pagename, old_header, "alias", NULL);
+	return 0;
+}
+
+/* The names of the other functions on --combine-by-prefix pages */
+static int write_alias_pages(void)
+{
+	qb_map_iter_t *map_iter;
+	struct page_info *pi;
+	const char *page;
+	void *data;
+	int ret = 0;
+
+	map_iter = qb_map_iter_create(all_pages);
+	for (page = qb_map_iter_next(map_iter, &data); page; page = qb_map_iter_next(map_iter, &data)) {
+		pi = data;
+		if (!pi->alias_of) {
+			continue;
+		}
+		/* The page could have been dropped by --page-hook */
+		if (!qb_map_get(all_pages, pi->alias_of)) {
+			log_msg(LOG_VERBOSE, "Not writing %s, there is no %s\n", page, pi->alias_of);
+			continue;
+		}
+		if (write_alias(page, pi->alias_of)) {
+			ret = -1;
+		}
+	}
+	qb_map_iter_free(map_iter);
+	return ret;
+}
+
+/*
+ * --clean, remove a page that is no longer generated, if it's one of ours.
+ * We can't look inside a compressed page, but it's in our manifest.
+ */
+static int remove_page(const char *pagename, int compressed)
+{
+	char manfilename[PATH_MAX * 2];
+	struct stat st;
+	int ours;
+
+	/* Cut short, it would be some other file */
+	if (snprintf(manfilename, sizeof(manfilename), "%s/%s%s", page_dir(pagename), pagename,
+		     compressed ? ".gz" : "") >= (int)sizeof(manfilename)) {
+		report_error(D2M_ERR_IO, NULL, pagename, "not removing, the path is too long");
+		return -1;
+	}
+	if (lstat(manfilename, &st) == 0 && S_ISLNK(st.st_mode)) {
+		/* --alias-links */
+		ours = 1;
+	} else if (compressed) {
+		ours = access(manfilename, F_OK) == 0 ? 1 : -1;
+	} else {
+		ours = is_generated_file(manfilename);
+	}
+	if (ours == -1) {
+		return 0;
+	}
+	if (!ours) {
+		report_error(D2M_ERR_DOC, manfilename, pagename, "not removing, file was not generated by doxygen2man");
+		return 0;
+	}
+	if (unlink(manfilename) == -1) {
+		report_error(D2M_ERR_IO, manfilename, pagename, "unable to remove: %s", strerror(errno));
+		return -1;
+	}
+	log_msg(LOG_INFO, "Removed %s\n", manfilename);
+	return 0;
+}
+
+/*
+ * Compare the pages from an earlier run with this one, so that we know about
+ * functions that have been renamed or removed and 'man' won't find any more.
+ * Each line of the manifest has the page file name, optionally followed by a
+ * tab and the header file it came from. Pages from headers that we haven't
+ * seen in this run are ignored. Missing pages are reported, and maybe
+ * removed or replaced by an alias.
+ */
+static int check_old_manifest(const char *manifest)
+{
+	char line[PATH_MAX * 2];
+	char *pagename;
+	char *old_header;
+	char *saveptr = NULL;
+	size_t len;
+	FILE *f;
+	int compressed;
+	int missing = 0;
+	int ret = 0;
+
+	f = fopen(manifest, "r");
+	if (!f) {
+		/* No manifest from a previous run is nothing to clean */
+		if (errno == ENOENT && manifest != old_manifest) {
+			return 0;
+		}
+		report_error(D2M_ERR_IO, manifest, NULL, "unable to open manifest: %s", strerror(errno));
+		return -1;
+	}
+
+	while (fgets(line, sizeof(line), f)) {
+		pagename = strtok_r(line, "\t\n", &saveptr);
+		if (!pagename || pagename[0] == '#') {
+			continue;
+		}
+		old_header = strtok_r(NULL, "\t\n", &saveptr);
+
+		/* The manifest might have the whole path */
+		if (strrchr(pagename, '/')) {
+			pagename = strrchr(pagename, '/') + 1;
+		}
+		/* --fhs-layout */
+		len = strlen(pagename);
+		compressed = len > 3 && strcmp(pagename + len - 3, ".gz") == 0;
+		if (compressed) {
+			pagename[len - 3] = '\0';
+		}
+
+		if (qb_map_get(all_pages, pagename)) {
+			continue;
+		}
+		if (old_header && !qb_map_get(all_headers, old_header)) {
+			continue;
+		}
+
+		fprintf(stderr, "Page %s%s%s is no longer generated\n", pagename,
+			old_header ? " from " : "", old_header ? old_header : "");
+		missing++;
+
+		if (alias_stubs) {
+			if (!old_header) {
+				report_error(D2M_ERR_IO, manifest, pagename, "no header file given, no alias written");
+				ret = -1;
+			} else if (write_alias_stub(pagename, old_header)) {
+				ret = -1;
+			}
+		} else if (clean) {
+			if (remove_page(pagename, compressed)) {
+				ret = -1;
+			}
+		}
+	}
+	fclose(f);
+
+	log_msg(LOG_INFO, "%d page%s from %s no longer generated\n", missing, missing == 1 ? "" : "s", manifest);
+	return ret;
+}
+
+/* For --list-symbols, called from traverse_node() */
+static void list_member(xmlNode *cur_node, void *arg)
+{
+	xmlNode *name_node;
+	char *kind;
+
+	kind = get_attr(cur_node, "kind");
+	name_node = find_child(cur_node, "name");
+	if (kind && name_node && name_node->children && name_node->children->content &&
+	    symbol_documented((char *)name_node->children->content) &&
+	    (strcmp(kind, "function") != 0 ||
+	     (function_wanted(cur_node) && function_exported(cur_node, (char *)name_node->children->content))) &&
+	    (strcmp(kind, "function") == 0 || strcmp(kind, "enum") == 0 ||
+	     strcmp(kind, "define") == 0 || strcmp(kind, "typedef") == 0)) {
+		printf("%s\t%s\t%s\n", (char *)name_node->children->content, kind, headerfile);
+	}
+	free(kind);
+}
+
+/* Structures and unions are in their own XML files, the header just refers to them */
+static void list_innerclass(xmlNode *cur_node, void *arg)
+{
+	char *refid = get_attr(cur_node, "refid");
+
+	if (refid && cur_node->children && cur_node->children->content &&
+	    symbol_documented((char *)cur_node->children->content)) {
+		if (strncmp(refid, "struct", 6) == 0) {
+			printf("%s\t%s\t%s\n", (char *)cur_node->children->content, "struct", headerfile);
+		} else if (strncmp(refid, "union", 5) == 0) {
+			printf("%s\t%s\t%s\n", (char *)cur_node->children->content, "union", headerfile);
+		}
+	}
+	free(refid);
+}
+
+/*
+ * The list of pages for packaging, one per line: the path, the header file it
+ * documents and what sort of page it is. --old-manifest reads the same format.
+ */
+static int write_manifest(void)
+{
+	qb_map_iter_t *map_iter;
+	struct page_info *pi;
+	const char *page;
+	void *data;
+	FILE *f;
+
+	f = fopen(manifest_file, "w");
+	if (!f) {
+		report_error(D2M_ERR_IO, manifest_file, NULL, "unable to write manifest: %s", strerror(errno));
+		return -1;
+	}
+
+	fprintf(f, "# Generated by doxygen2man: page, header, kind\n");
+	map_iter = qb_map_iter_create(all_pages);
+	for (page = qb_map_iter_next(map_iter, &data); page; page = qb_map_iter_next(map_iter, &data)) {
+		pi = data;
+		fprintf(f, "%s%s%s\t%s\t%s\n", listed_dir(page_dir(page)), page, page_suffix, pi->header, pi->kind);
+	}
+	qb_map_iter_free(map_iter);
+
+	if (fclose(f)) {
+		report_error(D2M_ERR_IO, manifest_file, NULL, "unable to write manifest: %s", strerror(errno));
+		return -1;
+	}
+	return 0;
+}
+
+struct index_entry {
+	const char *name;	/* page name, without the section */
+	size_t len;
+	const char *brief;
+};
+
+static int compare_index_entries(const void *a, const void *b)
+{
+	const struct index_entry *ea = a;
+	const struct index_entry *eb = b;
+	int ret = strncasecmp(ea->name, eb->name, ea->len < eb->len ? ea->len : eb->len);
+
+	if (ret == 0) {
+		ret = (ea->len > eb->len) - (ea->len < eb->len);
+	}
+	return ret;
+}
+
+/*
+ * --index-page, every function from all the headers in the run in
+ * alphabetical order with its brief description, under a subsection
+ * for each letter.
+ */
+static int print_index_page(void)
+{
+	char manfilename[PATH_MAX * 2];
+	char pagename[PATH_MAX];
+	char brief[1024];
+	struct index_entry *entries;
+	struct page_info *pi;
+	qb_map_iter_t *map_iter;
+	const char *page;
+	void *data;
+	FILE *manfile;
+	uint64_t page_hash = options_hash;
+	size_t num = 0;
+	size_t i;
+	int letter = 0;
+	int count = 0;
+	int ret;
+
+	entries = calloc(qb_map_count_get(all_pages) + 1, sizeof(struct index_entry));
+	if (!entries) {
+		return -1;
+	}
+	map_iter = qb_map_iter_create(all_pages);
+	for (page = qb_map_iter_next(map_iter, &data); page; page = qb_map_iter_next(map_iter, &data)) {
+		pi = data;
+		if (strcmp(pi->kind, "function") != 0) {
+			continue;
+		}
+		entries[num].name = page;
+		entries[num].len = strlen(page) - strlen(man_section) - 1;
+		entries[num].brief = pi->brief;
+		page_hash = hash_string(page_hash, page);
+		page_hash = hash_string(page_hash, pi->brief);
+		num++;
+	}
+	qb_map_iter_free(map_iter);
+	qsort(entries, num, sizeof(struct index_entry), compare_index_entries);
+
+	snprintf(brief, sizeof(brief), "index of %s functions", package_name);
+	snprintf(pagename, sizeof(pagename), "%s.%s", index_page, man_section);
+	add_page(pagename, "", "index", strdup(brief));
+	snprintf(manfilename, sizeof(manfilename), "%s/%s", output_dir, pagename);
+	if (page_is_current(manfilename, page_hash)) {
+		log_msg(LOG_VERBOSE, "%s is up to date\n", manfilename);
+		free(entries);
+		return 0;
+	}
+
+	ret = open_output_file(manfilename, index_page, "w+", &manfile);
+	if (ret) {
+		free(entries);
+		return ret == 1 ? 0 : -1;
+	}
+	log_msg(LOG_INFO, "Printing index manpage %s\n", index_page);
+
+	print_generated_comment(manfile);
+	fprintf(manfile, HASH_COMMENT "%016" PRIx64 "\n", page_hash);
+	print_th(manfile, index_page, man_section, page_date());
+	print_name_section(manfile, index_page, NULL, brief);
+	fprintf(manfile, "%s DESCRIPTION\n", dialect->section);
+	fprintf(manfile, "%s\n", dialect->paragraph);
+	fprintf(manfile, "Every function documented in the %s manual pages, in alphabetical order.\n", package_name);
+
+	for (i = 0; i < num; i++) {
+		if (toupper(entries[i].name[0]) != letter) {
+			letter = toupper(entries[i].name[0]);
+			fprintf(manfile, "%s \"%c\"\n", dialect->subsection, letter);
+		}
+		print_xref_tag(manfile, entries[i].name, (int)entries[i].len, man_section);
+		fprintf(manfile, "%s\n", (entries[i].brief && entries[i].brief[0]) ? entries[i].brief : "\\&");
+	}
+	free(entries);
+
+	if (print_general || !qb_list_empty(&extra_see_also_list)) {
+		fprintf(manfile, "%s SEE ALSO\n", dialect->section);
+		fprintf(manfile, "%s\n", dialect->paragraph);
+		fprintf(manfile, ".nh\n");
+		fprintf(manfile, ".ad l\n");
+		if (print_general) {
+			map_iter = qb_map_iter_create(all_headers);
+			for (page = qb_map_iter_next(map_iter, &data); page; page = qb_map_iter_next(map_iter, &data)) {
+				fprintf(manfile, "%s\\fI%s\\fR(%s)", count++ ? ", " : "", page, man_section);
+			}
+			qb_map_iter_free(map_iter);
+		}
+		print_extra_see_also(manfile, index_page, count);
+		fprintf(manfile, "\n");
+		fprintf(manfile, ".ad\n");
+		fprintf(manfile, ".hy\n");
+	}
+	print_manpage_footer(manfile);
+
+	if (close_output_file(manfile, manfilename, index_page)) {
+		return -1;
+	}
+	if (wrap_width && wrap_page(manfilename, index_page)) {
+		return -1;
+	}
+	if (page_hook) {
+		ret = run_page_hook(manfilename, index_page, "index");
+		if (ret) {
+			return ret;
+		}
+	}
+	if (lint_output) {
+		lint_page(manfilename, index_page);
+	}
+	pages_written++;
+	return 0;
+}
+
+/* The first <xrefsect> (@deprecated, @todo, @bug) of 'kind' in 'node' */
+static xmlNode *find_xrefsect(xmlNode *node, const char *kind)
+{
+	xmlNode *this_tag;
+	xmlNode *found;
+	size_t len = strlen(kind);
+	char *id;
+	int match;
+
+	for (this_tag = node->children; this_tag; this_tag = this_tag->next) {
+		if (this_tag->type != XML_ELEMENT_NODE) {
+			continue;
+		}
+		if (strcmp((char *)this_tag->name, "xrefsect") == 0) {
+			/* The ids are like deprecated_1_deprecated000001 */
+			id = get_attr(this_tag, "id");
+			match = id && strncmp(id, kind, len) == 0 && id[len] == '_';
+			free(id);
+			if (match) {
+				return this_tag;
+			}
+			continue;
+		}
+		found = find_xrefsect(this_tag, kind);
+		if (found) {
+			return found;
+		}
+	}
+	return NULL;
+}
+
+/* The word after the first of 'words' in 'text' that 'accept' likes */
+static char *word_after(const char *text, const char **words, int (*accept)(const char *, size_t))
+{
+	const char *p;
+	const char *word;
+	size_t len;
+	int i;
+	int tries;
+
+	for (i = 0; words[i]; i++) {
+		for (p = text; (p = strcasestr(p, words[i])) != NULL; ) {
+			if (p != text && isalnum((unsigned char)p[-1])) {
+				p++;
+				continue;
+			}
+			p += strlen(words[i]);
+			/* "in libqb 2.0" has the version one word further on */
+			for (word = p, tries = 0; tries < 2; tries++) {
+				word += strspn(word, " \t\n");
+				len = strcspn(word, " \t\n,;:()");
+				while (len && word[len - 1] == '.') {
+					len--;
+				}
+				if (len && accept(word, len)) {
+					return strndup(word, len);
+				}
+				word += strcspn(word, " \t\n");
+			}
+		}
+	}
+	return NULL;
+}
+
+/* A function or other symbol, rather than just a word, has an underscore or brackets after it */
+static int accept_symbol(const char *word, size_t len)
+{
+	size_t i;
+
+	if (!isalpha((unsigned char)word[0]) && word[0] != '_') {
+		return 0;
+	}
+	for (i = 0; i < len; i++) {
+		if (!isalnum((unsigned char)word[i]) && word[i] != '_') {
+			return 0;
+		}
+	}
+	return memchr(word, '_', len) || word[len] == '(';
+}
+
+static int accept_version(const char *word, size_t len)
+{
+	size_t i;
+
+	for (i = 0; i < len; i++) {
+		if (isdigit((unsigned char)word[i])) {
+			return 1;
+		}
+	}
+	return 0;
+}
+
+/*
+ * Pick the replacement and the version out of the deprecation text, eg
+ * "since 1.0.3, use qb_ipcs_rate_limit() instead"
+ */
+static void parse_deprecation(const char *text, char **replacement, char **version)
+{
+	static const char *use_words[] = {"use ", "replaced by ", "in favour of ", "in favor of ", "see ", NULL};
+	static const char *version_words[] = {"since ", "as of ", "from ", "in ", NULL};
+
+	*replacement = word_after(text, use_words, accept_symbol);
+	*version = word_after(text, version_words, accept_version);
+}
+
+/* The page a symbol is documented on, its own if it has one */
+static char *symbol_page(const char *name, const char *kind)
+{
+	char page[PATH_MAX + 16];
+
+	snprintf(page, sizeof(page), "%s.%s", name, man_section);
+	if (qb_map_get(all_pages, page)) {
+		return strdup(page);
+	}
+	snprintf(page, sizeof(page), "%s.%s", name, type_section());
+	if (qb_map_get(all_pages, page)) {
+		return strdup(page);
+	}
+	if (strcmp(kind, "define") == 0 && constants_page && define_listed(name)) {
+		snprintf(page, sizeof(page), "%s.%s", constants_pagename(), man_section);
+		return strdup(page);
+	}
+	if (print_general) {
+		snprintf(page, sizeof(page), "%s.%s", headerfile, man_section);
+		return strdup(page);
+	}
+	return NULL;
+}
+
+/*
+ * --deprecated-page, called from traverse_node(). A symbol is deprecated
+ * if it has a @deprecated or its --symbol-info status starts with
+ * 'deprecated'.
+ */
+static void collect_deprecated(xmlNode *cur_node, void *arg)
+{
+	struct deprecated_info *di;
+	struct symbol_info *sym;
+	xmlNode *xref;
+	xmlNode *desc;
+	xmlChar *content = NULL;
+	const char *plain = NULL;
+	char *text = NULL;
+	char *kind;
+	char *name;
+	size_t len;
+
+	kind = get_attr(cur_node, "kind");
+	name = child_content(cur_node, "name");
+	if (!kind || !name || name[0] == '@' || !symbol_documented(name) ||
+	    qb_map_get(deprecated_map, name)) {
+		goto out;
+	}
+
+	sym = symbol_info_map ? qb_map_get(symbol_info_map, name) : NULL;
+	xref = find_xrefsect(cur_node, "deprecated");
+	desc = xref ? find_child(xref, "xrefdescription") : NULL;
+	if (desc) {
+		cstring_t tmp = get_texttree(NULL, desc, NULL, NULL);
+
+		text = cstring_to_chars(tmp);
+		cstring_free(tmp);
+		content = xmlNodeGetContent(desc);
+		plain = (const char *)content;
+	} else if (sym && sym->status && strncasecmp(sym->status, "deprecated", 10) == 0) {
+		plain = sym->status + 10;
+		plain += strspn(plain, " ,:;-");
+		text = man_text_dup(plain);
+	} else {
+		goto out;
+	}
+
+	di = calloc(1, sizeof(struct deprecated_info));
+	if (!di) {
+		free(text);
+		goto out;
+	}
+	di->header = strdup(headerfile);
+	di->page = symbol_page(name, kind);
+	di->text = text;
+	if (di->text && !not_all_whitespace(di->text)) {
+		free(di->text);
+		di->text = NULL;
+	}
+	for (len = di->text ? strlen(di->text) : 0; len && isspace((unsigned char)di->text[len - 1]); len--) {
+		di->text[len - 1] = '\0';
+	}
+	parse_deprecation(plain ? plain : "", &di->replacement, &di->version);
+	qb_map_put(deprecated_map, name, di);
+	name = NULL;
+out:
+	if (content) {
+		xmlFree(content);
+	}
+	free(kind);
+	free(name);
+}
+
+static const char *deprecated_pagename(void)
+{
+	static char pagename[PATH_MAX];
+
+	snprintf(pagename, sizeof(pagename), "%s.7", deprecated_page);
+	return pagename;
+}
+
+/*
+ * --deprecated-page, every deprecated symbol from all of the headers in
+ * the run with what to use instead. It goes in section 7 as it's about
+ * the library rather than one function.
+ */
+static int print_deprecated_page(void)
+{
+	char manfilename[PATH_MAX * 3];
+	const char *pagename = deprecated_pagename();
+	char page[PATH_MAX];
+	char brief[1024];
+	struct deprecated_info *di;
+	qb_map_iter_t *map_iter;
+	const char *name;
+	const char *section;
+	void *data;
+	FILE *manfile;
+	uint64_t page_hash = options_hash;
+	size_t len = 0;
+	int count = 0;
+	int ret;
+
+	map_iter = qb_map_iter_create(deprecated_map);
+	for (name = qb_map_iter_next(map_iter, &data); name; name = qb_map_iter_next(map_iter, &data)) {
+		di = data;
+		page_hash = hash_string(page_hash, name);
+		page_hash = hash_string(page_hash, di->header);
+		page_hash = hash_string(page_hash, di->page);
+		page_hash = hash_string(page_hash, di->text);
+		page_hash = hash_string(page_hash, di->replacement);
+		page_hash = hash_string(page_hash, di->version);
+	}
+	qb_map_iter_free(map_iter);
+
+	snprintf(brief, sizeof(brief), "deprecated %s interfaces", package_name);
+	add_page(pagename, "", "deprecated", strdup(brief));
+	snprintf(manfilename, sizeof(manfilename), "%s/%s", page_dir(pagename), pagename);
+	if (page_is_current(manfilename, page_hash)) {
+		log_msg(LOG_VERBOSE, "%s is up to date\n", manfilename);
+		return 0;
+	}
+
+	ret = open_output_file(manfilename, deprecated_page, "w+", &manfile);
+	if (ret) {
+		return ret == 1 ? 0 : -1;
+	}
+	log_msg(LOG_INFO, "Printing deprecated manpage %s\n", deprecated_page);
+
+	print_generated_comment(manfile);
+	fprintf(manfile, HASH_COMMENT "%016" PRIx64 "\n", page_hash);
+	print_th(manfile, deprecated_page, "7", page_date());
+	print_name_section(manfile, deprecated_page, NULL, brief);
+	fprintf(manfile, "%s DESCRIPTION\n", dialect->section);
+	fprintf(manfile, "%s\n", dialect->paragraph);
+	if (qb_map_count_get(deprecated_map) == 0) {
+		fprintf(manfile, "Nothing in the %s manual pages is deprecated.\n", package_name);
+	} else {
+		fprintf(manfile, "These %s functions and other symbols are deprecated and may be removed\n", package_name);
+		fprintf(manfile, "in a later version. New code should use the replacement, where there is one.\n");
+	}
+
+	map_iter = qb_map_iter_create(deprecated_map);
+	for (name = qb_map_iter_next(map_iter, &data); name; name = qb_map_iter_next(map_iter, &data)) {
+		di = data;
+		section = di->page ? page_section(di->page, &len) : NULL;
+		if (section && len == strlen(name) && strncmp(di->page, name, len) == 0) {
+			print_xref_tag(manfile, name, (int)len, section);
+		} else {
+			print_list_tag(manfile, dialect->bold, name);
+		}
+		if (di->text) {
+			print_member_desc(manfile, di->text);
+			fprintf(manfile, ".br\n");
+		}
+		if (di->replacement) {
+			snprintf(page, sizeof(page), "%s.%s", di->replacement, man_section);
+			if (qb_map_get(all_pages, page)) {
+				fprintf(manfile, "Replacement: \\fB%s\\fR(%s)\n", di->replacement, man_section);
+			} else {
+				fprintf(manfile, "Replacement: \\fB%s\\fR\n", di->replacement);
+			}
+			fprintf(manfile, ".br\n");
+		}
+		if (di->version) {
+			fprintf(manfile, "Deprecated in: %s\n", di->version);
+			fprintf(manfile, ".br\n");
+		}
+		if (di->page && (len != strlen(name) || strncmp(di->page, name, len) != 0)) {
+			fprintf(manfile, "Documented in: \\fI%.*s\\fR(%s)\n", (int)len, di->page, section);
+		} else {
+			fprintf(manfile, "Header: \\fB%s%s\\fR\n", header_prefix, di->header);
+		}
+	}
+	qb_map_iter_free(map_iter);
+
+	if (index_page || print_general || !qb_list_empty(&extra_see_also_list)) {
+		fprintf(manfile, "%s SEE ALSO\n", dialect->section);
+		fprintf(manfile, "%s\n", dialect->paragraph);
+		fprintf(manfile, ".nh\n");
+		fprintf(manfile, ".ad l\n");
+		if (index_page) {
+			fprintf(manfile, "\\fI%s\\fR(%s)", index_page, man_section);
+			count++;
+		}
+		if (print_general) {
+			map_iter = qb_map_iter_create(all_headers);
+			for (name = qb_map_iter_next(map_iter, &data); name; name = qb_map_iter_next(map_iter, &data)) {
+				fprintf(manfile, "%s\\fI%s\\fR(%s)", count++ ? ", " : "", name, man_section);
+			}
+			qb_map_iter_free(map_iter);
+		}
+		print_extra_see_also(manfile, deprecated_page, count);
+		fprintf(manfile, "\n");
+		fprintf(manfile, ".ad\n");
+		fprintf(manfile, ".hy\n");
+	}
+	print_manpage_footer(manfile);
+
+	if (close_output_file(manfile, manfilename, deprecated_page)) {
+		return -1;
+	}
+	if (wrap_width && wrap_page(manfilename, deprecated_page)) {
+		return -1;
+	}
+	if (page_hook) {
+		ret = run_page_hook(manfilename, deprecated_page, "deprecated");
+		if (ret) {
+			return ret;
+		}
+	}
+	if (lint_output) {
+		lint_page(manfilename, deprecated_page);
+	}
+	pages_written++;
+	return 0;
+}
+
+/*
+ * --install-metadata, for packaging scripts. Tab separated records, the
+ * first field says what the record is. Fields are only ever added to the
+ * end of a record, anything else means a new format number.
+ *
+ *   doxygen2man-install-metadata <format>
+ *   generator doxygen2man <version>
+ *   page <file> <path under mandir> <name> <section> <compression> <header> <kind>
+ */
+static int write_install_metadata(void)
+{
+	qb_map_iter_t *map_iter;
+	struct page_info *pi;
+	const char *page;
+	const char *section;
+	void *data;
+	size_t len;
+	FILE *f;
+
+	f = fopen(install_metadata, "w");
+	if (!f) {
+		report_error(D2M_ERR_IO, install_metadata, NULL, "unable to write install metadata: %s", strerror(errno));
+		return -1;
+	}
+
+	fprintf(f, "doxygen2man-install-metadata\t1\n");
+	fprintf(f, "generator\tdoxygen2man\t%s\n", DOXYGEN2MAN_VERSION);
+	map_iter = qb_map_iter_create(all_pages);
+	for (page = qb_map_iter_next(map_iter, &data); page; page = qb_map_iter_next(map_iter, &data)) {
+		pi = data;
+		section = page_section(page, &len);
+		fprintf(f, "page\t%s%s%s\tman%c/%s%s\t%.*s\t%s\t%s\t%s\t%s\n",
+			listed_dir(page_dir(page)), page, page_suffix,
+			section[0] ? section[0] : man_section[0], page, page_suffix,
+			(int)len, page, section,
+			compress ? "gzip" : "none",
+			pi->header, pi->kind);
+	}
+	qb_map_iter_free(map_iter);
+
+	if (fclose(f)) {
+		report_error(D2M_ERR_IO, install_metadata, NULL, "unable to write install metadata: %s", strerror(errno));
+		return -1;
+	}
+	return 0;
+}
+
+/*
+ * A whatis database for makewhatis or to ship pre-built, one line per page
+ * in the form "name (section) - brief", like whatis(1) prints. The .so
+ * stubs from --alias-stubs aren't real pages so they are left out.
+ */
+static int write_whatis(void)
+{
+	qb_map_iter_t *map_iter;
+	struct page_info *pi;
+	const char *page;
+	const char *section;
+	void *data;
+	size_t len;
+	FILE *f;
+
+	f = fopen(whatis_file, "w");
+	if (!f) {
+		report_error(D2M_ERR_IO, whatis_file, NULL, "unable to write whatis file: %s", strerror(errno));
+		return -1;
+	}
+
+	map_iter = qb_map_iter_create(all_pages);
+	for (page = qb_map_iter_next(map_iter, &data); page; page = qb_map_iter_next(map_iter, &data)) {
+		pi = data;
+		if (strcmp(pi->kind, "alias") == 0) {
+			continue;
+		}
+		section = page_section(page, &len);
+		fprintf(f, "%.*s (%s) - %s\n", (int)len, page, section,
+			(pi->brief && pi->brief[0]) ? pi->brief : "");
+	}
+	qb_map_iter_free(map_iter);
+
+	if (fclose(f)) {
+		report_error(D2M_ERR_IO, whatis_file, NULL, "unable to write whatis file: %s", strerror(errno));
+		return -1;
+	}
+	return 0;
+}
+
+static void json_string(FILE *f, const char *str)
+{
+	const unsigned char *p;
+
+	if (!str) {
+		fprintf(f, "null");
+		return;
+	}
+	fputc('"', f);
+	for (p = (const unsigned char *)str; *p; p++) {
+		if (*p == '"' || *p == '\\') {
+			fprintf(f, "\\%c", *p);
+		} else if (*p < 0x20) {
+			fprintf(f, "\\u%04x", *p);
+		} else {
+			fputc(*p, f);
+		}
+	}
+	fputc('"', f);
+}
+
+/*
+ * For editors to show the documentation without reading the pages: one
+ * object keyed by symbol name, in name order, one symbol per line.
+ */
+static int write_json_index(void)
+{
+	qb_map_iter_t *map_iter;
+	struct index_symbol *is;
+	const char *name;
+	void *data;
+	int count = 0;
+	FILE *f;
+
+	f = fopen(json_index_file, "w");
+	if (!f) {
+		report_error(D2M_ERR_IO, json_index_file, NULL, "unable to write JSON index: %s", strerror(errno));
+		return -1;
+	}
+
+	fprintf(f, "{");
+	map_iter = qb_map_iter_create(index_symbols);
+	for (name = qb_map_iter_next(map_iter, &data); name; name = qb_map_iter_next(map_iter, &data)) {
+		is = data;
+		fprintf(f, "%s\n", count++ ? "," : "");
+		json_string(f, name);
+		fprintf(f, ":{\"kind\":");
+		json_string(f, is->kind);
+		fprintf(f, ",\"header\":");
+		json_string(f, is->header);
+		fprintf(f, ",\"brief\":");
+		json_string(f, is->brief);
+		fprintf(f, ",\"signature\":");
+		json_string(f, is->signature);
+		fprintf(f, ",\"page\":");
+		json_string(f, is->page);
+		fprintf(f, "}");
+	}
+	qb_map_iter_free(map_iter);
+	fprintf(f, "\n}\n");
+
+	if (fclose(f)) {
+		report_error(D2M_ERR_IO, json_index_file, NULL, "unable to write JSON index: %s", strerror(errno));
+		return -1;
+	}
+	return 0;
+}
+
+static struct db_symbol *new_db_symbol(const char *kind, const char *header_name, const char *page, size_t page_len)
+{
+	struct db_symbol *ds = calloc(1, sizeof(struct db_symbol));
+
+	if (ds) {
+		ds->kind = strdup(kind);
+		ds->header = strdup(header_name);
+		ds->page = page ? strndup(page, page_len) : NULL;
+	}
+	return ds;
+}
+
+/*
+ * --symbol-db, what this run documented goes in place of whatever the
+ * database had for the same headers, and the other headers are kept.
+ * Separate runs for other headers may be finishing at the same time, so
+ * it's done under a lock on <file>.lock, re-reading the database rather
+ * than using what was there when this run started. The new database is
+ * written to a temporary file and renamed, so a run that fails part way
+ * through doesn't leave half of one.
+ */
+static int write_symbol_db(void)
+{
+	char lockname[PATH_MAX + 8];
+	char tmpname[PATH_MAX + 8];
+	qb_map_t *current;
+	qb_map_t *symbols;
+	qb_map_iter_t *map_iter;
+	struct index_symbol *is;
+	struct db_symbol *ds;
+	const char *name;
+	const char *base;
+	void *data;
+	int count = 0;
+	int lock_fd;
+	int fd;
+	int ret = -1;
+	mode_t mask;
+	FILE *f;
+
+	snprintf(lockname, sizeof(lockname), "%s.lock", symbol_db_file);
+	lock_fd = open(lockname, O_RDWR | O_CREAT, 0666);
+	if (lock_fd == -1) {
+		report_error(D2M_ERR_IO, lockname, NULL, "unable to lock symbol database: %s", strerror(errno));
+		return -1;
+	}
+	if (flock(lock_fd, LOCK_EX) == -1) {
+		report_error(D2M_ERR_IO, lockname, NULL, "unable to lock symbol database: %s", strerror(errno));
+		close(lock_fd);
+		return -1;
+	}
+
+	current = new_symbol_db_map();
+	f = fopen(symbol_db_file, "r");
+	if (f) {
+		/* Its bad lines were reported when this run read it */
+		parse_symbol_db(f, current, 0);
+		fclose(f);
+	} else if (errno != ENOENT) {
+		report_error(D2M_ERR_IO, symbol_db_file, NULL, "unable to read symbol database: %s", strerror(errno));
+		qb_map_destroy(current);
+		close(lock_fd);
+		return -1;
+	}
+
+	symbols = new_symbol_db_map();
+	map_iter = qb_map_iter_create(current);
+	for (name = qb_map_iter_next(map_iter, &data); name; name = qb_map_iter_next(map_iter, &data)) {
+		ds = data;
+		if (qb_map_get(all_headers, ds->header)) {
+			continue;
+		}
+		ds = new_db_symbol(ds->kind, ds->header, ds->page, ds->page ? strlen(ds->page) : 0);
+		if (ds) {
+			qb_map_put(symbols, strdup(name), ds);
+		}
+	}
+	qb_map_iter_free(map_iter);
+	qb_map_destroy(current);
+
+	map_iter = qb_map_iter_create(index_symbols);
+	for (name = qb_map_iter_next(map_iter, &data); name; name = qb_map_iter_next(map_iter, &data)) {
+		is = data;
+		if (qb_map_get(symbols, name)) {
+			qb_map_rm(symbols, name);
+		}
+		/* The page's name, without the directory or a .gz */
+		base = NULL;
+		if (is->page) {
+			base = strrchr(is->page, '/') ? strrchr(is->page, '/') + 1 : is->page;
+		}
+		ds = new_db_symbol(is->kind, is->header, base, base ? strlen(base) - strlen(page_suffix) : 0);
+		if (ds) {
+			qb_map_put(symbols, strdup(name), ds);
+		}
+	}
+	qb_map_iter_free(map_iter);
+
+	/* Next to the database, so that rename() can replace it */
+	snprintf(tmpname, sizeof(tmpname), "%s.XXXXXX", symbol_db_file);
+	fd = mkstemp(tmpname);
+	if (fd == -1) {
+		report_error(D2M_ERR_IO, tmpname, NULL, "unable to write symbol database: %s", strerror(errno));
+		goto out;
+	}
+	/* mkstemp() makes it 0600, it should have the mode of any other file we write */
+	mask = umask(0);
+	umask(mask);
+	if (fchmod(fd, 0666 & ~mask) == -1 || !(f = fdopen(fd, "w"))) {
+		report_error(D2M_ERR_IO, tmpname, NULL, "unable to write symbol database: %s", strerror(errno));
+		close(fd);
+		unlink(tmpname);
+		goto out;
+	}
+
+	fprintf(f, "{");
+	map_iter = qb_map_iter_create(symbols);
+	for (name = qb_map_iter_next(map_iter, &data); name; name = qb_map_iter_next(map_iter, &data)) {
+		ds = data;
+		fprintf(f, "%s\n", count++ ? "," : "");
+		json_string(f, name);
+		fprintf(f, ":{\"kind\":");
+		json_string(f, ds->kind);
+		fprintf(f, ",\"header\":");
+		json_string(f, ds->header);
+		fprintf(f, ",\"page\":");
+		json_string(f, ds->page);
+		fprintf(f, "}");
+	}
+	qb_map_iter_free(map_iter);
+	fprintf(f, "\n}\n");
+
+	if (fclose(f)) {
+		report_error(D2M_ERR_IO, tmpname, NULL, "unable to write symbol database: %s", strerror(errno));
+		unlink(tmpname);
+		goto out;
+	}
+	if (rename(tmpname, symbol_db_file) == -1) {
+		report_error(D2M_ERR_IO, symbol_db_file, NULL, "unable to replace symbol database: %s", strerror(errno));
+		unlink(tmpname);
+		goto out;
+	}
+	ret = 0;
+out:
+	qb_map_destroy(symbols);
+	/* Closing it releases the lock */
+	close(lock_fd);
+	return ret;
+}
+
+/*
+ * --todo-report, the @todo and @bug items in the description of a
+ * symbol. The descriptions are searched rather than the whole node, so
+ * that a header doesn't pick up the items of all its functions.
+ */
+static void add_todo_items(xmlNode *node, const char *symbol, const char *file, int line)
+{
+	struct todo_item *ti;
+	xmlNode *this_tag;
+	xmlNode *desc;
+	xmlChar *content;
+	const char *kind;
+	char *id;
+	char *text;
+	char *from;
+	char *to;
+
+	for (this_tag = node->children; this_tag; this_tag = this_tag->next) {
+		if (this_tag->type != XML_ELEMENT_NODE) {
+			continue;
+		}
+		if (strcmp((char *)this_tag->name, "xrefsect") != 0) {
+			add_todo_items(this_tag, symbol, file, line);
+			continue;
+		}
+		id = get_attr(this_tag, "id");
+		if (id && strncmp(id, "todo_", 5) == 0) {
+			kind = "todo";
+		} else if (id && strncmp(id, "bug_", 4) == 0) {
+			kind = "bug";
+		} else {
+			kind = NULL;
+		}
+		free(id);
+		desc = find_child(this_tag, "xrefdescription");
+		if (!kind || !desc) {
+			continue;
+		}
+
+		/* One line of plain text, whatever the markup was */
+		content = xmlNodeGetContent(desc);
+		text = strdup(content ? (char *)content : "");
+		if (content) {
+			xmlFree(content);
+		}
+		if (!text) {
+			continue;
+		}
+		for (from = to = text; *from; from++) {
+			if (!isspace((unsigned char)*from)) {
+				*to++ = *from;
+			} else if (to != text && !isspace((unsigned char)from[1]) && from[1]) {
+				*to++ = ' ';
+			}
+		}
+		*to = '\0';
+
+		ti = calloc(1, sizeof(struct todo_item));
+		if (!ti) {
+			free(text);
+			continue;
+		}
+		ti->kind = strdup(kind);
+		ti->symbol = strdup(symbol);
+		ti->header = strdup(headerfile);
+		ti->file = file ? strdup(file) : NULL;
+		ti->line = line;
+		ti->text = text;
+		qb_list_add_tail(&ti->list, &todo_list);
+	}
+}
+
+/* --todo-report, called from traverse_node() for headers, structures and their members */
+static void collect_todos(xmlNode *cur_node, void *arg)
+{
+	char symbol[1024];
+	xmlNode *location;
+	xmlNode *this_tag;
+	xmlNode *parent;
+	char *name;
+	char *scope = NULL;
+	char *file;
+	char *line;
+
+	if (strcmp((char *)cur_node->name, "compounddef") == 0) {
+		name = child_content(cur_node, "compoundname");
+	} else {
+		name = child_content(cur_node, "name");
+		/* Members of a structure get its name in front, as there are lots called 'data' */
+		parent = cur_node->parent ? cur_node->parent->parent : NULL;
+		if (parent && parent->type == XML_ELEMENT_NODE &&
+		    strcmp((char *)parent->name, "compounddef") == 0) {
+			scope = get_attr(parent, "kind");
+			if (scope && (strcmp(scope, "struct") == 0 || strcmp(scope, "union") == 0)) {
+				free(scope);
+				scope = child_content(parent, "compoundname");
+			} else {
+				free(scope);
+				scope = NULL;
+			}
+		}
+	}
+	if (!name) {
+		return;
+	}
+	snprintf(symbol, sizeof(symbol), "%s%s%s", scope ? scope : "", scope ? "." : "", name);
+
+	location = find_child(cur_node, "location");
+	file = location ? get_attr(location, "file") : NULL;
+	line = location ? get_attr(location, "line") : NULL;
+	for (this_tag = cur_node->children; this_tag; this_tag = this_tag->next) {
+		if (this_tag->type == XML_ELEMENT_NODE &&
+		    (strcmp((char *)this_tag->name, "briefdescription") == 0 ||
+		     strcmp((char *)this_tag->name, "detaileddescription") == 0)) {
+			add_todo_items(this_tag, symbol, file, line ? atoi(line) : 0);
+		}
+	}
+	free(file);
+	free(line);
+	free(scope);
+	free(name);
+}
+
+static void free_todo_list(void)
+{
+	struct qb_list_head *iter;
+	struct qb_list_head *tmp;
+	struct todo_item *ti;
+
+	qb_list_for_each_safe(iter, tmp, &todo_list) {
+		ti = qb_list_entry(iter, struct todo_item, list);
+		qb_list_del(&ti->list);
+		free(ti->kind);
+		free(ti->symbol);
+		free(ti->header);
+		free(ti->file);
+		free(ti->text);
+		free(ti);
+	}
+}
+
+/*
+ * For the maintainers rather than the users, so it's not a man page. The
+ * text format is like a compiler's messages so that editors can jump to
+ * each item:
+ *   <file>:<line>: <todo|bug>: <symbol>: <text>
+ * (without the line for a header) and the JSON is an array of objects, one per line.
+ */
+static int write_todo_report(void)
+{
+	struct qb_list_head *iter;
+	struct todo_item *ti;
+	int count = 0;
+	FILE *f;
+
+	f = fopen(todo_report, "w");
+	if (!f) {
+		report_error(D2M_ERR_IO, todo_report, NULL, "unable to write todo report: %s", strerror(errno));
+		return -1;
+	}
+
+	if (todo_format == TODO_JSON) {
+		fprintf(f, "[");
+	}
+	qb_list_for_each(iter, &todo_list) {
+		ti = qb_list_entry(iter, struct todo_item, list);
+		if (todo_format == TODO_JSON) {
+			fprintf(f, "%s\n{\"kind\":", count ? "," : "");
+			json_string(f, ti->kind);
+			fprintf(f, ",\"symbol\":");
+			json_string(f, ti->symbol);
+			fprintf(f, ",\"header\":");
+			json_string(f, ti->header);
+			fprintf(f, ",\"file\":");
+			json_string(f, ti->file);
+			if (ti->line) {
+				fprintf(f, ",\"line\":%d", ti->line);
+			} else {
+				fprintf(f, ",\"line\":null");
+			}
+			fprintf(f, ",\"text\":");
+			json_string(f, ti->text);
+			fprintf(f, "}");
+		} else if (ti->line) {
+			fprintf(f, "%s:%d: %s: %s: %s\n", ti->file ? ti->file : ti->header, ti->line,
+				ti->kind, ti->symbol, ti->text);
+		} else {
+			fprintf(f, "%s: %s: %s: %s\n", ti->file ? ti->file : ti->header,
+				ti->kind, ti->symbol, ti->text);
+		}
+		count++;
+	}
+	if (todo_format == TODO_JSON) {
+		fprintf(f, "%s]\n", count ? "\n" : "");
+	}
+
+	if (fclose(f)) {
+		report_error(D2M_ERR_IO, todo_report, NULL, "unable to write todo report: %s", strerror(errno));
+		return -1;
+	}
+	log_msg(LOG_VERBOSE, "%d todo and bug item%s written to %s\n", count, count == 1 ? "" : "s", todo_report);
+	return 0;
+}
+
+/*
+ * A ctags file for editors without doxygen support. The names are in
+ * byte order, as the skiplist keeps them, so it can say it's sorted.
+ */
+static int write_tags_file(void)
+{
+	qb_map_iter_t *map_iter;
+	struct index_symbol *is;
+	const char *name;
+	const char *kind;
+	void *data;
+	FILE *f;
+
+	f = fopen(tags_file, "w");
+	if (!f) {
+		report_error(D2M_ERR_IO, tags_file, NULL, "unable to write tags file: %s", strerror(errno));
+		return -1;
+	}
+
+	fprintf(f, "!_TAG_FILE_FORMAT\t2\t/extended format/\n");
+	fprintf(f, "!_TAG_FILE_SORTED\t1\t/0=unsorted, 1=sorted, 2=foldcase/\n");
+	fprintf(f, "!_TAG_PROGRAM_NAME\tdoxygen2man\t//\n");
+	map_iter = qb_map_iter_create(index_symbols);
+	for (name = qb_map_iter_next(map_iter, &data); name; name = qb_map_iter_next(map_iter, &data)) {
+		is = data;
+		if (!is->file || !is->line) {
+			continue;
+		}
+		if (strcmp(is->kind, "function") == 0) {
+			kind = "f";
+		} else if (strcmp(is->kind, "struct") == 0) {
+			kind = "s";
+		} else if (strcmp(is->kind, "union") == 0) {
+			kind = "u";
+		} else if (strcmp(is->kind, "enum") == 0) {
+			kind = "g";
+		} else if (strcmp(is->kind, "enumvalue") == 0) {
+			kind = "e";
+		} else if (strcmp(is->kind, "define") == 0) {
+			kind = "d";
+		} else {
+			kind = "t";
+		}
+		fprintf(f, "%s\t%s\t%d;\"\t%s\n", name, is->file, is->line, kind);
+	}
+	qb_map_iter_free(map_iter);
+
+	if (fclose(f)) {
+		report_error(D2M_ERR_IO, tags_file, NULL, "unable to write tags file: %s", strerror(errno));
+		return -1;
+	}
+	return 0;
+}
+
+static void xml_string(FILE *f, const char *str)
+{
+	const char *p;
+
+	for (p = str; *p; p++) {
+		switch (*p) {
+		case '&':
+			fprintf(f, "&amp;");
+			break;
+		case '<':
+			fprintf(f, "&lt;");
+			break;
+		case '>':
+			fprintf(f, "&gt;");
+			break;
+		case '"':
+			fprintf(f, "&quot;");
+			break;
+		default:
+			fputc(*p, f);
+			break;
+		}
+	}
+}
+
+/* The page file name for a tagfile, without the directory or .gz */
+static void tagfile_page(FILE *f, const char *tag, const char *page)
+{
+	const char *base = strrchr(page, '/') ? strrchr(page, '/') + 1 : page;
+
+	fprintf(f, "<%s>", tag);
+	fprintf(f, "%.*s", (int)(strlen(base) - strlen(page_suffix)), base);
+	fprintf(f, "</%s>\n", tag);
+}
+
+/*
+ * A doxygen tagfile, so that other projects can link to our pages with
+ * TAGFILES. Each header is a file compound with its functions, enums,
+ * defines and typedefs as members, structures and unions are compounds
+ * of their own. Only symbols that have a page are listed, everything but
+ * the functions is on the header page so that needs -g.
+ */
+static int write_generate_tagfile(void)
+{
+	qb_map_iter_t *header_iter;
+	qb_map_iter_t *map_iter;
+	struct index_symbol *is;
+	const char *header_name;
+	const char *name;
+	char type[4096];
+	size_t len;
+	void *data;
+	FILE *f;
+
+	f = fopen(generate_tagfile, "w");
+	if (!f) {
+		report_error(D2M_ERR_IO, generate_tagfile, NULL, "unable to write tagfile: %s", strerror(errno));
+		return -1;
+	}
+
+	fprintf(f, "<?xml version='1.0' encoding='UTF-8' standalone='yes' ?>\n");
+	fprintf(f, "<tagfile>\n");
+	header_iter = qb_map_iter_create(all_headers);
+	for (header_name = qb_map_iter_next(header_iter, &data); header_name; header_name = qb_map_iter_next(header_iter, &data)) {
+		fprintf(f, "  <compound kind=\"file\">\n");
+		fprintf(f, "    <name>");
+		xml_string(f, header_name);
+		fprintf(f, "</name>\n");
+		if (print_general) {
+			fprintf(f, "    <filename>");
+			xml_string(f, header_name);
+			fprintf(f, ".%s</filename>\n", man_section);
+		}
+
+		map_iter = qb_map_iter_create(index_symbols);
+		for (name = qb_map_iter_next(map_iter, &data); name; name = qb_map_iter_next(map_iter, &data)) {
+			is = data;
+			if (!is->page || strcmp(is->header, header_name) != 0 ||
+			    strcmp(is->kind, "struct") == 0 || strcmp(is->kind, "union") == 0) {
+				continue;
+			}
+			fprintf(f, "    <member kind=\"%s\">\n", is->kind);
+			if (is->arglist) {
+				/* The signature is the type, the name and then the arglist */
+				snprintf(type, sizeof(type), "%.*s",
+					 (int)(strlen(is->signature) - strlen(is->arglist) - strlen(name)), is->signature);
+				len = strlen(type);
+				while (len && type[len - 1] == ' ') {
+					type[--len] = '\0';
+				}
+				fprintf(f, "      <type>");
+				xml_string(f, type);
+				fprintf(f, "</type>\n");
+			}
+			fprintf(f, "      <name>");
+			xml_string(f, name);
+			fprintf(f, "</name>\n");
+			fprintf(f, "      ");
+			tagfile_page(f, "anchorfile", is->page);
+			fprintf(f, "      <anchor></anchor>\n");
+			fprintf(f, "      <arglist>");
+			xml_string(f, is->arglist ? is->arglist : "");
+			fprintf(f, "</arglist>\n");
+			fprintf(f, "    </member>\n");
+		}
+		qb_map_iter_free(map_iter);
+		fprintf(f, "  </compound>\n");
+	}
+	qb_map_iter_free(header_iter);
+
+	map_iter = qb_map_iter_create(index_symbols);
+	for (name = qb_map_iter_next(map_iter, &data); name; name = qb_map_iter_next(map_iter, &data)) {
+		is = data;
+		if (!is->page || (strcmp(is->kind, "struct") != 0 && strcmp(is->kind, "union") != 0)) {
+			continue;
+		}
+		fprintf(f, "  <compound kind=\"%s\">\n", is->kind);
+		fprintf(f, "    <name>");
+		xml_string(f, name);
+		fprintf(f, "</name>\n");
+		fprintf(f, "    ");
+		tagfile_page(f, "filename", is->page);
+		fprintf(f, "  </compound>\n");
+	}
+	qb_map_iter_free(map_iter);
+	fprintf(f, "</tagfile>\n");
+
+	if (fclose(f)) {
+		report_error(D2M_ERR_IO, generate_tagfile, NULL, "unable to write tagfile: %s", strerror(errno));
+		return -1;
+	}
+	return 0;
+}
+
+/*
+ * Print something for the build system to include that installs exactly
+ * the pages we generated into the right man<section> directory. That's
+ * usually just the one, but the --deprecated-page is in section 7.
+ */
+static void write_install_rules(FILE *f)
+{
+	qb_map_iter_t *map_iter;
+	char sections[8][16];
+	const char *page;
+	const char *dir;
+	const char *s;
+	void *data;
+	size_t num_sections = 1;
+	size_t len;
+	size_t i;
+	size_t j;
+
+	/* Our own section first, then any others that pages are in */
+	snprintf(sections[0], sizeof(sections[0]), "%s", man_section);
+	map_iter = qb_map_iter_create(all_pages);
+	for (page = qb_map_iter_next(map_iter, &data); page; page = qb_map_iter_next(map_iter, &data)) {
+		s = page_section(page, &len);
+		for (j = 0; j < num_sections && s[0] && s[0] != sections[j][0]; j++) {
+		}
+		if (j == num_sections && num_sections < sizeof(sections) / sizeof(sections[0])) {
+			snprintf(sections[num_sections++], sizeof(sections[0]), "%c", s[0]);
+		}
+	}
+	qb_map_iter_free(map_iter);
+
+	switch (install_rules) {
+	case RULES_MAKE:
+		fprintf(f, "# Generated by doxygen2man, uses the automake variables mandir, MKDIR_P and INSTALL_DATA\n");
+		break;
+	case RULES_MESON:
+		fprintf(f, "# Generated by doxygen2man\n");
+		break;
+	case RULES_CMAKE:
+		fprintf(f, "# Generated by doxygen2man, needs include(GNUInstallDirs)\n");
+		break;
+	default:
+		return;
+	}
+
+	for (i = 0; i < num_sections; i++) {
+		switch (install_rules) {
+		case RULES_MAKE:
+			fprintf(f, "%sdoxygen2man_man%s_pages =", i ? "\n" : "", sections[i]);
+			break;
+		case RULES_MESON:
+			fprintf(f, "install_data(\n");
+			break;
+		case RULES_CMAKE:
+			fprintf(f, "install(FILES\n");
+			break;
+		default:
+			break;
+		}
+
+		map_iter = qb_map_iter_create(all_pages);
+		for (page = qb_map_iter_next(map_iter, &data); page; page = qb_map_iter_next(map_iter, &data)) {
+			s = page_section(page, &len);
+			if ((s[0] ? s[0] : man_section[0]) != sections[i][0]) {
+				continue;
+			}
+			dir = listed_dir(page_dir(page));
+			switch (install_rules) {
+			case RULES_MAKE:
+				fprintf(f, " \\\n\t%s%s%s", dir, page, page_suffix);
 				break;
-			case 'i':
-				header_prefix = optarg;
+			case RULES_MESON:
+				fprintf(f, "  '%s%s%s',\n", dir, page, page_suffix);
 				break;
-			case 'C':
-				company = optarg;
+			case RULES_CMAKE:
+				fprintf(f, "  \"%s%s%s\"\n", dir, page, page_suffix);
 				break;
-			case 's':
-				man_section = optarg;
+			default:
 				break;
-			case 'S':
-				start_year = get_year(optarg, 'S');
-				if (start_year == 0) {
-					return 1;
+			}
+		}
+		qb_map_iter_free(map_iter);
+
+		switch (install_rules) {
+		case RULES_MAKE:
+			fprintf(f, "\n");
+			break;
+		case RULES_MESON:
+			fprintf(f, "  install_dir: get_option('mandir') / 'man%s',\n", sections[i]);
+			fprintf(f, ")\n");
+			break;
+		case RULES_CMAKE:
+			fprintf(f, "  DESTINATION \"${CMAKE_INSTALL_MANDIR}/man%s\")\n", sections[i]);
+			break;
+		default:
+			break;
+		}
+	}
+
+	if (install_rules == RULES_MAKE) {
+		fprintf(f, "\n");
+		fprintf(f, "install-doxygen2man-pages:");
+		for (i = 0; i < num_sections; i++) {
+			fprintf(f, " $(doxygen2man_man%s_pages)", sections[i]);
+		}
+		fprintf(f, "\n");
+		for (i = 0; i < num_sections; i++) {
+			fprintf(f, "\t$(MKDIR_P) \"$(DESTDIR)$(mandir)/man%s\"\n", sections[i]);
+			fprintf(f, "\t$(INSTALL_DATA) $(doxygen2man_man%s_pages) \"$(DESTDIR)$(mandir)/man%s\"\n",
+				sections[i], sections[i]);
+		}
+		fprintf(f, "\n");
+		fprintf(f, "uninstall-doxygen2man-pages:\n");
+		for (i = 0; i < num_sections; i++) {
+			fprintf(f, "\tfor p in $(doxygen2man_man%s_pages); do rm -f \"$(DESTDIR)$(mandir)/man%s/`basename $$p`\"; done\n",
+				sections[i], sections[i]);
+		}
+		fprintf(f, "\n");
+		fprintf(f, ".PHONY: install-doxygen2man-pages uninstall-doxygen2man-pages\n");
+	}
+}
+
+/*
+ * The XML files of the headers that doxygen found, from index.xml. Sorted, so
+ * they don't change when doxygen changes its mind about the order. NULL if
+ * there isn't an index.xml.
+ */
+static qb_map_t *read_index_headers(void)
+{
+	char fname[PATH_MAX];
+	qb_map_t *headers;
+	xmlNode *rootdoc;
+	xmlNode *compound;
+	xmlNode *name_node;
+	xmlDocPtr doc;
+	char *kind;
+	char *refid;
+	char *name;
+
+	snprintf(fname, sizeof(fname), "%s/index.xml", xml_dir);
+	doc = xmlReadFile(fname, xml_encoding, XML_PARSE_COMPACT | XML_PARSE_NOERROR | XML_PARSE_NOWARNING);
+	if (!doc) {
+		return NULL;
+	}
+	rootdoc = xmlDocGetRootElement(doc);
+
+	headers = qb_skiplist_create();
+	qb_map_notify_add(headers, NULL, map_free_entry, QB_MAP_NOTIFY_FREE, NULL);
+	for (compound = rootdoc ? rootdoc->children : NULL; compound; compound = compound->next) {
+		if (compound->type != XML_ELEMENT_NODE || strcmp((char *)compound->name, "compound") != 0) {
+			continue;
+		}
+		kind = get_attr(compound, "kind");
+		refid = get_attr(compound, "refid");
+		name_node = find_child(compound, "name");
+		name = name_node && name_node->children ? (char *)name_node->children->content : NULL;
+		if (kind && strcmp(kind, "file") == 0 && refid &&
+		    name && strlen(name) > 2 && strcmp(name + strlen(name) - 2, ".h") == 0) {
+			snprintf(fname, sizeof(fname), "%s.xml", refid);
+			qb_map_put(headers, strdup(fname), NULL);
+		}
+		free(kind);
+		free(refid);
+	}
+	xmlFreeDoc(doc);
+	return headers;
+}
+
+/*
+ * --emit-rules, something for a project's doc build that runs doxygen and
+ * then generates the pages for every header that doxygen found. The XML
+ * file names come from index.xml, so doxygen has to have been run once.
+ * For CMake it's a module with functions to add the targets.
+ */
+static int write_build_rules(FILE *f)
+{
+	char fname[PATH_MAX];
+	qb_map_t *headers;
+	qb_map_iter_t *map_iter;
+	const char *xml_file;
+	void *data;
+
+	headers = read_index_headers();
+	if (!headers) {
+		snprintf(fname, sizeof(fname), "%s/index.xml", xml_dir);
+		report_error(D2M_ERR_PARSE, fname, NULL, "unable to read doxygen index, has doxygen been run?");
+		return -1;
+	}
+	if (qb_map_count_get(headers) == 0) {
+		snprintf(fname, sizeof(fname), "%s/index.xml", xml_dir);
+		report_error(D2M_ERR_PARSE, fname, NULL, "no header files in doxygen index");
+		qb_map_destroy(headers);
+		return -1;
+	}
+
+	switch (build_rules) {
+	case RULES_MAKE:
+		fprintf(f, "# Generated by doxygen2man --emit-rules automake\n");
+		fprintf(f, "# Set DOXYGEN, DOXYGEN2MAN, DOXYFILE and doxygen2man_flags before including this,\n");
+		fprintf(f, "# and add doxygen2man-pages.list to all-local and doxygen2man-clean to clean-local\n");
+		fprintf(f, "doxygen2man_xml =");
+		break;
+	case RULES_MESON:
+		fprintf(f, "# Generated by doxygen2man --emit-rules meson\n");
+		fprintf(f, "# Set doxygen and doxygen2man (from find_program()), doxyfile and\n");
+		fprintf(f, "# doxygen2man_flags (a list of options) before this in meson.build\n");
+		fprintf(f, "doxygen2man_xml = [\n");
+		break;
+	case RULES_CMAKE:
+		fprintf(f, "# Generated by doxygen2man --emit-rules cmake, save it as Doxygen2Man.cmake and include() it\n");
+		fprintf(f, "#\n");
+		fprintf(f, "# doxygen2man_add_doxygen(<target> <doxyfile> [DEPENDS <file>...])\n");
+		fprintf(f, "#   Run doxygen to write the XML files, again when <doxyfile> or a DEPENDS file changes\n");
+		fprintf(f, "# doxygen2man_add_header(<target> <xml file> [ALL] [DEPENDS <target>...] [FLAGS <option>...])\n");
+		fprintf(f, "#   Write the pages for one header, after the targets in DEPENDS\n");
+		fprintf(f, "# doxygen2man_add_pages(<target> [ALL] [DEPENDS <target>...] [FLAGS <option>...])\n");
+		fprintf(f, "#   doxygen2man_add_header(<target>-<name>) for every header, and <target> for all of them\n");
+		fprintf(f, "#\n");
+		fprintf(f, "# Each header target writes a --manifest, <target>.list, of its pages.\n");
+		fprintf(f, "# Needs CMake 3.9 or later.\n");
+		fprintf(f, "\n");
+		fprintf(f, "find_program(DOXYGEN2MAN_EXECUTABLE doxygen2man)\n");
+		fprintf(f, "set(DOXYGEN2MAN_XML_DIR \"%s%s\")\n",
+			xml_dir[0] == '/' ? "" : "${CMAKE_CURRENT_BINARY_DIR}/", xml_dir);
+		fprintf(f, "set(DOXYGEN2MAN_OUTPUT_DIR \"%s%s\")\n",
+			output_dir[0] == '/' ? "" : "${CMAKE_CURRENT_BINARY_DIR}/", output_dir);
+		fprintf(f, "set(DOXYGEN2MAN_SECTION \"%s\")\n", man_section);
+		fprintf(f, "set(DOXYGEN2MAN_HEADERS");
+		break;
+	default:
+		break;
+	}
+
+	map_iter = qb_map_iter_create(headers);
+	for (xml_file = qb_map_iter_next(map_iter, &data); xml_file; xml_file = qb_map_iter_next(map_iter, &data)) {
+		switch (build_rules) {
+		case RULES_MAKE:
+			fprintf(f, " \\\n\t%s", xml_file);
+			break;
+		case RULES_MESON:
+			fprintf(f, "  '%s',\n", xml_file);
+			break;
+		case RULES_CMAKE:
+			fprintf(f, "\n  %s", xml_file);
+			break;
+		default:
+			break;
+		}
+	}
+	qb_map_iter_free(map_iter);
+	qb_map_destroy(headers);
+
+	switch (build_rules) {
+	case RULES_MAKE:
+		fprintf(f, "\n\n");
+		fprintf(f, "doxygen2man.stamp: $(DOXYFILE)\n");
+		fprintf(f, "\t$(DOXYGEN) $(DOXYFILE)\n");
+		fprintf(f, "\ttouch $@\n");
+		fprintf(f, "\n");
+		fprintf(f, "doxygen2man-pages.list: doxygen2man.stamp\n");
+		fprintf(f, "\t$(MKDIR_P) \"%s\"\n", output_dir);
+		fprintf(f, "\t$(DOXYGEN2MAN) -m -d \"%s\" -o \"%s\" -s %s $(doxygen2man_flags) --manifest $@ $(doxygen2man_xml)\n",
+			xml_dir, output_dir, man_section);
+		fprintf(f, "\n");
+		fprintf(f, "doxygen2man-clean:\n");
+		fprintf(f, "\tif test -f doxygen2man-pages.list; then grep -v '^#' doxygen2man-pages.list | cut -f1 | xargs rm -f; fi\n");
+		fprintf(f, "\trm -f doxygen2man.stamp doxygen2man-pages.list\n");
+		fprintf(f, "\n");
+		fprintf(f, ".PHONY: doxygen2man-clean\n");
+		break;
+	case RULES_MESON:
+		fprintf(f, "]\n");
+		fprintf(f, "\n");
+		fprintf(f, "doxygen2man_doxygen = custom_target('doxygen2man-xml',\n");
+		fprintf(f, "  input: doxyfile,\n");
+		fprintf(f, "  output: 'doxygen2man.stamp',\n");
+		fprintf(f, "  command: [find_program('sh'), '-c', '\"$1\" \"$2\" && touch \"$3\"', 'sh', doxygen, '@INPUT@', '@OUTPUT@'],\n");
+		fprintf(f, ")\n");
+		fprintf(f, "\n");
+		fprintf(f, "doxygen2man_pages = custom_target('doxygen2man-pages',\n");
+		fprintf(f, "  input: doxygen2man_doxygen,\n");
+		fprintf(f, "  output: 'doxygen2man-pages.list',\n");
+		fprintf(f, "  command: [doxygen2man, '-m', '-d', '%s', '-o', '%s', '-s', '%s'] + doxygen2man_flags +\n",
+			xml_dir, output_dir, man_section);
+		fprintf(f, "    ['--manifest', '@OUTPUT@'] + doxygen2man_xml,\n");
+		fprintf(f, "  build_by_default: true,\n");
+		fprintf(f, ")\n");
+		break;
+	case RULES_CMAKE:
+		fprintf(f, ")\n");
+		fprintf(f, "\n");
+		fprintf(f, "function(doxygen2man_add_doxygen target doxyfile)\n");
+		fprintf(f, "  cmake_parse_arguments(D2M \"\" \"\" \"DEPENDS\" ${ARGN})\n");
+		fprintf(f, "  find_package(Doxygen REQUIRED)\n");
+		fprintf(f, "  get_filename_component(doxyfile \"${doxyfile}\" ABSOLUTE)\n");
+		fprintf(f, "  set(stamp \"${CMAKE_CURRENT_BINARY_DIR}/${target}.stamp\")\n");
+		fprintf(f, "  set(xml_files)\n");
+		fprintf(f, "  foreach(xml_file ${DOXYGEN2MAN_HEADERS})\n");
+		fprintf(f, "    list(APPEND xml_files \"${DOXYGEN2MAN_XML_DIR}/${xml_file}\")\n");
+		fprintf(f, "  endforeach()\n");
+		fprintf(f, "  add_custom_command(OUTPUT \"${stamp}\"\n");
+		fprintf(f, "    BYPRODUCTS ${xml_files}\n");
+		fprintf(f, "    COMMAND Doxygen::doxygen \"${doxyfile}\"\n");
+		fprintf(f, "    COMMAND \"${CMAKE_COMMAND}\" -E touch \"${stamp}\"\n");
+		fprintf(f, "    DEPENDS \"${doxyfile}\" ${D2M_DEPENDS}\n");
+		fprintf(f, "    WORKING_DIRECTORY \"${CMAKE_CURRENT_BINARY_DIR}\"\n");
+		fprintf(f, "    VERBATIM)\n");
+		fprintf(f, "  add_custom_target(${target} DEPENDS \"${stamp}\")\n");
+		fprintf(f, "endfunction()\n");
+		fprintf(f, "\n");
+		fprintf(f, "function(doxygen2man_add_header target xml_file)\n");
+		fprintf(f, "  cmake_parse_arguments(D2M \"ALL\" \"\" \"DEPENDS;FLAGS\" ${ARGN})\n");
+		fprintf(f, "  set(list_file \"${CMAKE_CURRENT_BINARY_DIR}/${target}.list\")\n");
+		fprintf(f, "  add_custom_command(OUTPUT \"${list_file}\"\n");
+		fprintf(f, "    COMMAND \"${CMAKE_COMMAND}\" -E make_directory \"${DOXYGEN2MAN_OUTPUT_DIR}\"\n");
+		fprintf(f, "    COMMAND \"${DOXYGEN2MAN_EXECUTABLE}\" -m -d \"${DOXYGEN2MAN_XML_DIR}\" -o \"${DOXYGEN2MAN_OUTPUT_DIR}\"\n");
+		fprintf(f, "            -s \"${DOXYGEN2MAN_SECTION}\" ${D2M_FLAGS} --manifest \"${list_file}\" \"${xml_file}\"\n");
+		fprintf(f, "    DEPENDS \"${DOXYGEN2MAN_XML_DIR}/${xml_file}\"\n");
+		fprintf(f, "    VERBATIM)\n");
+		fprintf(f, "  if(D2M_ALL)\n");
+		fprintf(f, "    add_custom_target(${target} ALL DEPENDS \"${list_file}\")\n");
+		fprintf(f, "  else()\n");
+		fprintf(f, "    add_custom_target(${target} DEPENDS \"${list_file}\")\n");
+		fprintf(f, "  endif()\n");
+		fprintf(f, "  if(D2M_DEPENDS)\n");
+		fprintf(f, "    add_dependencies(${target} ${D2M_DEPENDS})\n");
+		fprintf(f, "  endif()\n");
+		fprintf(f, "endfunction()\n");
+		fprintf(f, "\n");
+		fprintf(f, "function(doxygen2man_add_pages target)\n");
+		fprintf(f, "  cmake_parse_arguments(D2M \"ALL\" \"\" \"DEPENDS;FLAGS\" ${ARGN})\n");
+		fprintf(f, "  if(D2M_ALL)\n");
+		fprintf(f, "    add_custom_target(${target} ALL)\n");
+		fprintf(f, "  else()\n");
+		fprintf(f, "    add_custom_target(${target})\n");
+		fprintf(f, "  endif()\n");
+		fprintf(f, "  foreach(xml_file ${DOXYGEN2MAN_HEADERS})\n");
+		fprintf(f, "    get_filename_component(name \"${xml_file}\" NAME_WE)\n");
+		fprintf(f, "    doxygen2man_add_header(${target}-${name} \"${xml_file}\" DEPENDS ${D2M_DEPENDS} FLAGS ${D2M_FLAGS})\n");
+		fprintf(f, "    add_dependencies(${target} ${target}-${name})\n");
+		fprintf(f, "  endforeach()\n");
+		fprintf(f, "endfunction()\n");
+		break;
+	default:
+		break;
+	}
+	return 0;
+}
+
+/*
+ * Things that libxml2 had to recover from in the XML file we're reading, and
+ * the memberdef each one was in. That memberdef is left out.
+ */
+struct xml_problem {
+	int line;
+	int column;
+	char *message;
+	char *symbol;
+	xmlNode *member;
+	long member_line;	/* of the memberdef that was left out, 0 if none was */
+	struct qb_list_head list;
+};
+
+static struct qb_list_head xml_problems;
+
+static void collect_xml_problem(void *arg, xmlErrorPtr error)
+{
+	struct xml_problem *xp;
+
+	if (error->level < XML_ERR_ERROR) {
+		return;
+	}
+	xp = calloc(1, sizeof(struct xml_problem));
+	if (!xp) {
+		return;
+	}
+	xp->line = error->line;
+	xp->column = error->int2;
+	xp->message = strdup(error->message ? error->message : "XML error");
+	if (xp->message) {
+		xp->message[strcspn(xp->message, "\n")] = '\0';
+	}
+	qb_list_add_tail(&xp->list, &xml_problems);
+}
+
+/* The last memberdef that starts before 'line' */
+static void find_enclosing_member(xmlNode *node, long line, xmlNode **member)
+{
+	xmlNode *this_tag;
+
+	for (this_tag = node->children; this_tag; this_tag = this_tag->next) {
+		if (this_tag->type != XML_ELEMENT_NODE || xmlGetLineNo(this_tag) > line) {
+			continue;
+		}
+		if (strcmp((char *)this_tag->name, "memberdef") == 0) {
+			*member = this_tag;
+		}
+		find_enclosing_member(this_tag, line, member);
+	}
+}
+
+static void skip_broken_members(xmlNode *rootdoc)
+{
+	struct xml_problem *xp;
+	struct xml_problem *other;
+	struct qb_list_head *iter;
+	struct qb_list_head *iter2;
+	xmlNode *name_node;
+	xmlNode *parent;
+
+	qb_list_for_each(iter, &xml_problems) {
+		xp = qb_list_entry(iter, struct xml_problem, list);
+		find_enclosing_member(rootdoc, xp->line, &xp->member);
+		name_node = xp->member ? find_child(xp->member, "name") : find_node(rootdoc, "compoundname");
+		if (name_node && name_node->children && name_node->children->content) {
+			xp->symbol = strdup((char *)name_node->children->content);
+		}
+		xp->member_line = xp->member ? xmlGetLineNo(xp->member) : 0;
+	}
+
+	/*
+	 * Several problems can be in the same memberdef, and a broken one can
+	 * swallow the ones after it, so each node is only freed once.
+	 */
+	qb_list_for_each(iter, &xml_problems) {
+		xp = qb_list_entry(iter, struct xml_problem, list);
+		if (!xp->member) {
+			continue;
+		}
+		qb_list_for_each(iter2, &xml_problems) {
+			other = qb_list_entry(iter2, struct xml_problem, list);
+			for (parent = other->member; parent && other != xp; parent = parent->parent) {
+				if (parent == xp->member) {
+					other->member = NULL;
+					break;
 				}
+			}
+		}
+		xmlUnlinkNode(xp->member);
+		xmlFreeNode(xp->member);
+		xp->member = NULL;
+	}
+}
+
+/*
+ * Say where the problems were, and what was left out because of them. One
+ * broken tag usually upsets everything after it in the memberdef, so only
+ * the first problem in each one is worth reporting.
+ */
+static void report_xml_problems(const char *xml_filename)
+{
+	struct xml_problem *xp;
+	struct qb_list_head *iter;
+	struct qb_list_head *tmp;
+	char location[PATH_MAX + 32];
+	long last_member_line = -1;
+
+	qb_list_for_each_safe(iter, tmp, &xml_problems) {
+		xp = qb_list_entry(iter, struct xml_problem, list);
+		if (xp->member_line != last_member_line) {
+			snprintf(location, sizeof(location), "%s:%d:%d", xml_filename, xp->line, xp->column);
+			if (!xp->member_line) {
+				report_error(D2M_ERR_PARSE, location, xp->symbol, "%s", xp->message);
+			} else if (xp->symbol) {
+				report_error(D2M_ERR_PARSE, location, xp->symbol, "%s, no page written for it", xp->message);
+			} else {
+				report_error(D2M_ERR_PARSE, location, NULL, "%s, memberdef at line %ld left out",
+					     xp->message, xp->member_line);
+			}
+			last_member_line = xp->member_line;
+		}
+		qb_list_del(&xp->list);
+		free(xp->message);
+		free(xp->symbol);
+		free(xp);
+	}
+}
+
+/*
+ * The header XML files can be big, so each one is mapped and parsed from
+ * memory rather than going through libxml2's reads, and the page hash is
+ * made from the same copy rather than reading the file twice.
+ */
+static xmlDocPtr read_xml_file(const char *xml_filename, uint64_t *hash)
+{
+	int flags = XML_PARSE_COMPACT | XML_PARSE_RECOVER | XML_PARSE_HUGE;
+	struct stat st;
+	xmlDocPtr doc;
+	void *map;
+	int fd;
+
+	fd = open(xml_filename, O_RDONLY);
+	if (fd == -1) {
+		return NULL;
+	}
+	if (fstat(fd, &st) == -1 || st.st_size == 0) {
+		close(fd);
+		return NULL;
+	}
+	map = mmap(NULL, st.st_size, PROT_READ, MAP_PRIVATE, fd, 0);
+	close(fd);
+	if (map == MAP_FAILED) {
+		/* Not something that can be mapped (a pipe, say), let libxml2 read it */
+		hash_file(hash, xml_filename);
+		return xmlReadFile(xml_filename, xml_encoding, flags);
+	}
+
+	*hash = hash_bytes(*hash, map, st.st_size);
+	doc = xmlReadMemory(map, st.st_size, xml_filename, xml_encoding, flags);
+	munmap(map, st.st_size);
+	return doc;
+}
+
+/* The pages from one XML file, after everything has been collected from it */
+static void write_file_pages(xmlNode *rootdoc)
+{
+	struct function_info **functions;
+	size_t num;
+	size_t i;
+
+	functions = sorted_functions(&num);
+	for (i = 0; i < num; i++) {
+		traverse_members(functions[i]->node, NULL);
+	}
+	free(functions);
+
+	if (struct_pages && print_man) {
+		traverse_node(rootdoc, "innerclass", traverse_struct, NULL);
+	}
+	if (enum_pages && print_man) {
+		traverse_node(rootdoc, "memberdef", traverse_enum, NULL);
+	}
+
+	if (print_general) {
+		/* Generate and print a page for the headerfile itself */
+		traverse_node(rootdoc, "compounddef", traverse_members, (char *)headerfile);
+	}
+
+	if (print_man && examples_dir && !qb_list_empty(&examples_list) &&
+	    (worst_error < D2M_ERR_PARSE || keep_going) &&
+	    (!preview_name || strcmp(preview_name, examples_pagename()) == 0) && page_is_ours()) {
+		if (print_examples_page() == 0) {
+			pages_written++;
+		}
+	}
+	if (print_man && !qb_list_empty(&defines_list) &&
+	    (worst_error < D2M_ERR_PARSE || keep_going) &&
+	    (!preview_name || strcmp(preview_name, constants_pagename()) == 0) && page_is_ours()) {
+		if (print_constants_page() == 0) {
+			pages_written++;
+		}
+	}
+	if (spell_command && lint_wanted(LINT_SPELLING)) {
+		check_spelling();
+	}
+}
+
+/*
+ * -j, the file has been read and everything collected from it, then each
+ * worker writes every num_jobs'th page. What they did comes back in
+ * job_results, which they share with us.
+ */
+static void run_workers(xmlNode *rootdoc)
+{
+	pid_t pids[MAX_THREADS];
+	int status;
+	int started;
+	int i;
+
+	memset(job_results, 0, num_jobs * sizeof(struct job_result));
+	fflush(stdout);
+	fflush(stderr);
+	for (started = 0; started < num_jobs; started++) {
+		pids[started] = fork();
+		if (pids[started] == -1) {
+			report_error(D2M_ERR_IO, current_xml_file, NULL, "unable to start worker process: %s", strerror(errno));
+			break;
+		}
+		if (pids[started] == 0) {
+			job_slot = started;
+			pages_written = 0;
+			/* Keep progress messages from different workers on separate lines */
+			setvbuf(stdout, NULL, _IOLBF, 0);
+			write_file_pages(rootdoc);
+			finish_writes();
+			job_results[job_slot].pages_written = pages_written;
+			job_results[job_slot].worst_error = worst_error;
+			job_results[job_slot].page_counter = page_counter;
+			job_results[job_slot].finished = 1;
+			fflush(stdout);
+			fflush(stderr);
+			_exit(0);
+		}
+	}
+
+	for (i = 0; i < started; i++) {
+		if (waitpid(pids[i], &status, 0) == -1 || !WIFEXITED(status) || !job_results[i].finished) {
+			report_error(D2M_ERR_IO, current_xml_file, NULL, "worker process %d failed", i);
+			continue;
+		}
+		pages_written += job_results[i].pages_written;
+		if (job_results[i].worst_error > worst_error) {
+			worst_error = job_results[i].worst_error;
+		}
+		/* They all count the same pages, so the next file carries on from there */
+		page_counter = job_results[i].page_counter;
+	}
+}
+
+/* Read one doxygen XML file and generate all of the pages for it */
+static int process_file(const char *xml_file)
+{
+	char *xml_headerfile = NULL;
+	xmlNode *rootdoc;
+	xmlDocPtr doc;
+	char xml_filename[PATH_MAX];
+
+	log_msg(LOG_INFO, "reading %s ... ", xml_file);
+
+	snprintf(xml_filename, sizeof(xml_filename), "%s/%s", xml_dir, xml_file);
+	xml_file_hash = HASH_INIT;
+	current_xml_file = xml_file;
+	qb_list_init(&xml_problems);
+	xmlSetStructuredErrorFunc(NULL, (xmlStructuredErrorFunc)collect_xml_problem);
+	doc = read_xml_file(xml_filename, &xml_file_hash);
+	xmlSetStructuredErrorFunc(NULL, NULL);
+	rootdoc = doc ? xmlDocGetRootElement(doc) : NULL;
+	if (!rootdoc) {
+		if (!qb_list_empty(&xml_problems)) {
+			report_xml_problems(xml_filename);
+		} else {
+			report_error(D2M_ERR_PARSE, xml_filename, NULL, doc ? "can't find document root" : "unable to read XML file");
+		}
+		if (doc) {
+			xmlFreeDoc(doc);
+		}
+		return -1;
+	}
+	log_msg(LOG_INFO, "%s.\n", qb_list_empty(&xml_problems) ? "done" : "recovered");
+
+	/* Leave out anything that was broken, the rest of the pages can still be written */
+	skip_broken_members(rootdoc);
+
+	/* Get our header file name */
+	headerfile = user_headerfile;
+	header_copyright[0] = '\0';
+	if (!headerfile) {
+		traverse_node(rootdoc, "compounddef", read_headername, &xml_headerfile);
+		headerfile = xml_headerfile;
+
+		if (use_header_copyright) {
+			/* And get the copyright line from this file if we can */
+			read_header_copyright();
+		}
+	}
+
+	/* Default to *something* if it all goes wrong */
+	if (!headerfile) {
+		headerfile = "unknown.h";
+	}
+
+	header_spdx[0] = '\0';
+	read_header_spdx();
+	if (require_spdx && !header_spdx[0]) {
+		report_error(D2M_ERR_LINT, headerfile, NULL, "no SPDX-License-Identifier (or no header file in %s)", header_src_dir);
+	}
+
+	header_date[0] = '\0';
+	if (date_from_header) {
+		read_header_date();
+	}
+
+	if (list_symbols) {
+		traverse_node(rootdoc, "memberdef", list_member, NULL);
+		traverse_node(rootdoc, "innerclass", list_innerclass, NULL);
+		xmlFreeDoc(doc);
+		free(xml_headerfile);
+		report_xml_problems(xml_filename);
+		current_xml_file = NULL;
+		return 0;
+	}
+
+	qb_list_init(&params_list);
+	qb_list_init(&retval_list);
+	qb_list_init(&combined_list);
+	structures_map = qb_hashtable_create(hashtable_size);
+	function_map = qb_hashtable_create(hashtable_size);
+	used_structures_map = new_used_structures_map();
+	qb_map_notify_add(structures_map, NULL, map_free_struct, QB_MAP_NOTIFY_FREE, NULL);
+	qb_map_notify_add(function_map, NULL, map_free_function, QB_MAP_NOTIFY_FREE, NULL);
+	num_functions = 0;
+
+	/* Collect functions, this only needs their names */
+	traverse_node(rootdoc, "memberdef", collect_functions, NULL);
+	if (combine_prefix && print_man) {
+		combine_functions();
+	}
+	build_see_also_functions();
+
+	/* Collect enums */
+	traverse_node(rootdoc, "memberdef", collect_enums, NULL);
+
+	/* Collect #defines, for the constants page */
+	qb_list_init(&defines_list);
+	if (constants_page && print_man) {
+		traverse_node(rootdoc, "memberdef", collect_defines, NULL);
+	}
+
+	/* Collect the headers this one includes and is included by, for the header page */
+	qb_list_init(&includes_list);
+	qb_list_init(&includedby_list);
+	if (header_includes && print_general && print_man) {
+		traverse_node(rootdoc, "compounddef", collect_includes, NULL);
+	}
+
+	/* Collect pages with example code, for the examples page */
+	qb_list_init(&examples_list);
+	if (examples_dir) {
+		traverse_node(rootdoc, "memberdef", collect_examples, NULL);
+		if (print_general) {
+			traverse_node(rootdoc, "compounddef", collect_examples, NULL);
+		}
+	}
+
+	if (print_man) {
+		add_file_pages(find_node(rootdoc, "compounddef"));
+	}
+	if (json_index_file || tags_file || generate_tagfile || symbol_db_file) {
+		traverse_node(rootdoc, "memberdef", index_member, NULL);
+		traverse_node(rootdoc, "innerclass", index_innerclass, NULL);
+	}
+	if (deprecated_page && print_man) {
+		traverse_node(rootdoc, "memberdef", collect_deprecated, NULL);
+	}
+	if (todo_report) {
+		traverse_node(rootdoc, "compounddef", collect_todos, NULL);
+		traverse_node(rootdoc, "memberdef", collect_todos, NULL);
+	}
+
+	if (num_jobs > 1) {
+		run_workers(rootdoc);
+	} else {
+		write_file_pages(rootdoc);
+	}
+	free_examples();
+	free_defines();
+	free_includes();
+
+	qb_map_destroy(used_structures_map);
+	qb_map_destroy(function_map);
+	qb_map_destroy(structures_map);
+	free(see_also_functions);
+	see_also_functions = NULL;
+	free(see_also_order);
+	see_also_order = NULL;
+	see_also_count = 0;
+	see_also_names = 0;
+	xmlFreeDoc(doc);
+	free(xml_headerfile);
+
+	/* Only now, so that the good pages in the file are written first */
+	report_xml_problems(xml_filename);
+	current_xml_file = NULL;
+
+	return (worst_error >= D2M_ERR_PARSE && !keep_going) ? -1 : 0;
+}
+
+static int is_header_xml(const struct dirent *d)
+{
+	size_t len = strlen(d->d_name);
+
+	return len > 7 && strcmp(d->d_name + len - 7, "_8h.xml") == 0;
+}
+
+/*
+ * Generate man pages for all of the bundled fixtures into a
+ * temporary directory and check them.
+ */
+static int run_selftest(void)
+{
+	char tmpdir[PATH_MAX];
+	struct dirent **namelist;
+	const char *tmpbase;
+	int num_files;
+	int num_pages;
+	int errors = 0;
+	int i;
+
+	tmpbase = getenv("TMPDIR");
+	if (!tmpbase) {
+		tmpbase = "/tmp";
+	}
+	snprintf(tmpdir, sizeof(tmpdir), "%s/doxygen2man-selftest.XXXXXX", tmpbase);
+	if (!mkdtemp(tmpdir)) {
+		perror("unable to create selftest directory");
+		return EXIT_IO;
+	}
+
+	print_man = 1;
+	print_ascii = 0;
+	lint_strict = 1;
+	print_general = 1;
+	print_params = 1;
+	verbosity = LOG_QUIET;
+	output_dir = tmpdir;
+
+	num_files = scandir(xml_dir, &namelist, is_header_xml, alphasort);
+	if (num_files <= 0) {
+		fprintf(stderr, "selftest: no fixture files found in %s\n", xml_dir);
+		rmdir(tmpdir);
+		return EXIT_PARSE;
+	}
+	for (i = 0; i < num_files; i++) {
+		if (process_file(namelist[i]->d_name)) {
+			errors++;
+		}
+		free(namelist[i]);
+	}
+	free(namelist);
+
+	num_pages = scandir(tmpdir, &namelist, NULL, alphasort);
+	for (i = 0; i < num_pages; i++) {
+		if (namelist[i]->d_name[0] != '.') {
+			errors += check_page(tmpdir, namelist[i]->d_name, NULL, 1);
+		}
+	}
+	/* Tidy up once all the cross-references have been checked */
+	for (i = 0; i < num_pages; i++) {
+		char pagepath[PATH_MAX + sizeof(namelist[i]->d_name)];
+
+		if (namelist[i]->d_name[0] != '.') {
+			snprintf(pagepath, sizeof(pagepath), "%s/%s", tmpdir, namelist[i]->d_name);
+			unlink(pagepath);
+		}
+		free(namelist[i]);
+	}
+	if (num_pages > 0) {
+		free(namelist);
+	}
+	rmdir(tmpdir);
+
+	printf("selftest: %d fixture file%s, %d error%s\n",
+	       num_files, num_files == 1 ? "" : "s",
+	       errors, errors == 1 ? "" : "s");
+	return errors ? EXIT_LINT : 0;
+}
+
+static int handle_option(int opt, char *arg, const char *progname);
+
+/*
+ * Read options from a config file. Each line is the long name of an option
+ * followed, for options that take a value, by '=' and the value. Blank lines
+ * and lines starting with '#' are ignored. eg:
+ *
+ *   # libqb man pages
+ *   package = libqb
+ *   bugs-url = https://github.com/ClusterLabs/libqb/issues
+ *   params
+ */
+#define MAX_CONFIG_DEPTH 16
+
+static int read_config_file(const char *filename)
+{
+	/* The files being read, a config file can include another with 'config =' */
+	static struct {
+		dev_t dev;
+		ino_t ino;
+	} open_configs[MAX_CONFIG_DEPTH];
+	static int config_depth;
+	char line[4096];
+	struct stat st;
+	FILE *config;
+	int lineno = 0;
+	int ret = 0;
+	int i;
+
+	config = fopen(filename, "r");
+	if (!config) {
+		fprintf(stderr, "Cannot open config file %s: %s\n", filename, strerror(errno));
+		return -1;
+	}
+	if (fstat(fileno(config), &st) == -1) {
+		fprintf(stderr, "Cannot read config file %s: %s\n", filename, strerror(errno));
+		fclose(config);
+		return -1;
+	}
+	for (i = 0; i < config_depth; i++) {
+		if (open_configs[i].dev == st.st_dev && open_configs[i].ino == st.st_ino) {
+			fprintf(stderr, "Config file %s includes itself\n", filename);
+			fclose(config);
+			return -1;
+		}
+	}
+	if (config_depth == MAX_CONFIG_DEPTH) {
+		fprintf(stderr, "Config file %s is nested more than %d deep\n", filename, MAX_CONFIG_DEPTH);
+		fclose(config);
+		return -1;
+	}
+	open_configs[config_depth].dev = st.st_dev;
+	open_configs[config_depth].ino = st.st_ino;
+	config_depth++;
+
+	while (ret == 0 && fgets(line, sizeof(line), config)) {
+		struct option *lopt;
+		char *key = line;
+		char *value = NULL;
+		char *end;
+
+		lineno++;
+		while (isspace(*key)) {
+			key++;
+		}
+		if (*key == '\0' || *key == '#') {
+			continue;
+		}
+
+		end = strchr(key, '=');
+		if (end) {
+			*end = '\0';
+			value = end + 1;
+			while (isspace(*value)) {
+				value++;
+			}
+			end = value + strlen(value);
+			while (end > value && isspace(end[-1])) {
+				*--end = '\0';
+			}
+			/* Allow values to be quoted so they can keep leading/trailing spaces */
+			if (end - value >= 2 && value[0] == '"' && end[-1] == '"') {
+				end[-1] = '\0';
+				value++;
+			}
+		}
+		end = key + strlen(key);
+		while (end > key && isspace(end[-1])) {
+			*--end = '\0';
+		}
+
+		for (lopt = long_options; lopt->name; lopt++) {
+			if (strcmp(lopt->name, key) == 0) {
 				break;
-			case 'd':
-				xml_dir = optarg;
-				break;
-			case 'D':
-				manpage_date = optarg;
-				break;
-			case 'Y':
-				manpage_year = get_year(optarg, 'Y');
-				if (manpage_year == 0) {
-					return 1;
+			}
+		}
+		if (!lopt->name) {
+			fprintf(stderr, "%s:%d: unknown option '%s'\n", filename, lineno, key);
+			ret = -1;
+		} else if (lopt->has_arg == required_argument && !value) {
+			fprintf(stderr, "%s:%d: option '%s' needs a value\n", filename, lineno, key);
+			ret = -1;
+		} else if (lopt->has_arg == no_argument && value) {
+			fprintf(stderr, "%s:%d: option '%s' does not take a value\n", filename, lineno, key);
+			ret = -1;
+		} else {
+			/* The option variables keep pointers to their values */
+			ret = handle_option(lopt->val, value ? strdup(value) : NULL, filename);
+		}
+	}
+	config_depth--;
+	fclose(config);
+	return ret;
+}
+
+/* --include-symbols and --exclude-symbols, replacing any earlier pattern */
+static int compile_symbol_regex(regex_t *re, const char **pattern, const char *arg, const char *option)
+{
+	char errbuf[256];
+	int err;
+
+	if (*pattern) {
+		regfree(re);
+		*pattern = NULL;
+	}
+	err = regcomp(re, arg, REG_EXTENDED | REG_NOSUB);
+	if (err) {
+		regerror(err, re, errbuf, sizeof(errbuf));
+		fprintf(stderr, "Invalid regular expression for %s '%s': %s\n", option, arg, errbuf);
+		return -1;
+	}
+	*pattern = arg;
+	return 0;
+}
+
+/* --see-also [<page>:]<name>(<section>)[,<name>(<section>)...] */
+static int add_extra_see_also(const char *arg)
+{
+	struct extra_see_also *esa;
+	char *copy = strdup(arg);
+	char *page = NULL;
+	char *refs = copy;
+	char *ref;
+	char *paren;
+	char *saveptr = NULL;
+
+	if (!copy) {
+		return -1;
+	}
+	paren = strchr(copy, '(');
+	if (strchr(copy, ':') && (!paren || strchr(copy, ':') < paren)) {
+		page = copy;
+		refs = strchr(copy, ':');
+		*refs++ = '\0';
+	}
+
+	for (ref = strtok_r(refs, ",", &saveptr); ref; ref = strtok_r(NULL, ",", &saveptr)) {
+		while (isspace(*ref)) {
+			ref++;
+		}
+		paren = strchr(ref, '(');
+		if (!paren || paren == ref || ref[strlen(ref) - 1] != ')' || paren[1] == ')') {
+			fprintf(stderr, "--see-also entries must look like name(section), not '%s'\n", ref);
+			free(copy);
+			return -1;
+		}
+		esa = calloc(1, sizeof(struct extra_see_also));
+		if (!esa) {
+			free(copy);
+			return -1;
+		}
+		esa->page = page ? strdup(page) : NULL;
+		esa->name = strndup(ref, paren - ref);
+		esa->section = strndup(paren + 1, strlen(paren + 1) - 1);
+		qb_list_add_tail(&esa->list, &extra_see_also_list);
+	}
+	free(copy);
+	return 0;
+}
+
+static const char *mode_name(int opt)
+{
+	switch (opt) {
+	case 'a':
+		return "-a";
+	case 'm':
+		return "-m";
+	case OPT_LIST_SYMBOLS:
+		return "--list-symbols";
+	case OPT_PREVIEW:
+		return "--preview";
+	case OPT_EMIT_RULES:
+		return "--emit-rules";
+	case OPT_VERIFY_REPRODUCIBLE:
+		return "--verify-reproducible";
+	default:
+		return "--selftest";
+	}
+}
+
+/*
+ * -a, -m, --list-symbols, --preview, --selftest, --verify-reproducible and
+ * --emit-rules are different things to do, so only one of them can be given. One on the command line
+ * replaces one from a config file though.
+ */
+static int set_mode(int opt, const char *source)
+{
+	static const char *mode_source = NULL;
+	static int mode_opt = 0;
+
+	if (mode_source == source && mode_opt != opt) {
+		fprintf(stderr, "%s and %s can't be used together\n", mode_name(mode_opt), mode_name(opt));
+		return -1;
+	}
+	mode_source = source;
+	mode_opt = opt;
+
+	print_ascii = 0;
+	print_man = 0;
+	list_symbols = 0;
+	preview_name = NULL;
+	selftest = 0;
+	verify_reproducible = 0;
+	build_rules = RULES_NONE;
+	return 0;
+}
+
+static int set_dialect(const char *arg)
+{
+	size_t i;
+
+	for (i = 0; i < sizeof(dialects) / sizeof(dialects[0]); i++) {
+		if (strcmp(arg, dialects[i].name) == 0) {
+			dialect = &dialects[i];
+			return 0;
+		}
+	}
+	fprintf(stderr, "--dialect must be man or groff\n");
+	return -1;
+}
+
+/* A whole number from min to max, with nothing after it */
+static int get_number(const char *arg, const char *option, long min, long max, int *value)
+{
+	char *end;
+	long num;
+
+	errno = 0;
+	num = strtol(arg, &end, 10);
+	if (end == arg || *end != '\0' || errno == ERANGE) {
+		fprintf(stderr, "Value passed to %s is not a number: '%s'\n", option, arg);
+		return -1;
+	}
+	if (num < min || num > max) {
+		fprintf(stderr, "Value passed to %s must be from %ld to %ld\n", option, min, max);
+		return -1;
+	}
+	*value = num;
+	return 0;
+}
+
+/*
+ * Returns 0 if all is well, -1 if the option was invalid and 1
+ * if we should just exit (eg after printing the usage text)
+ */
+static int handle_option(int opt, char *arg, const char *progname)
+{
+	xmlCharEncodingHandlerPtr handler;
+
+	switch(opt)
+	{
+		case 'a':
+			if (set_mode(opt, progname)) {
+				return -1;
+			}
+			print_ascii = 1;
+			break;
+		case 'm':
+			if (set_mode(opt, progname)) {
+				return -1;
+			}
+			print_man = 1;
+			break;
+		case 'P':
+			print_params = 1;
+			break;
+		case 'g':
+			print_general = 1;
+			break;
+		case 'q':
+			verbosity = LOG_QUIET;
+			break;
+		case 'v':
+			verbosity++;
+			break;
+		case 'c':
+			use_header_copyright = 1;
+			break;
+		case 'I':
+			user_headerfile = arg;
+			break;
+		case 'i':
+			header_prefix = arg;
+			break;
+		case 'C':
+			company = arg;
+			break;
+		case 's':
+			if (check_section(arg)) {
+				return -1;
+			}
+			man_section = arg;
+			break;
+		case 'S':
+			start_year = get_year(arg, 'S');
+			if (start_year == 0) {
+				return -1;
+			}
+			break;
+		case 'd':
+			if (check_dir(arg, 'd')) {
+				return -1;
+			}
+			xml_dir = arg;
+			xml_dir_set = 1;
+			break;
+		case 'D':
+			if (check_date(arg)) {
+				return -1;
+			}
+			manpage_date = arg;
+			break;
+		case 'Y':
+			manpage_year = get_year(arg, 'Y');
+			if (manpage_year == 0) {
+				return -1;
+			}
+			break;
+		case 'p':
+			if (check_th_field(arg, "-p")) {
+				return -1;
+			}
+			package_name = arg;
+			break;
+		case 'H':
+			if (check_th_field(arg, "-H")) {
+				return -1;
+			}
+			header = arg;
+			break;
+		case OPT_TH_SOURCE:
+			if (check_th_field(arg, "--th-source")) {
+				return -1;
+			}
+			th_source = arg;
+			break;
+		case OPT_NO_DATES:
+			no_dates = 1;
+			break;
+		case OPT_DATE_FROM_HEADER:
+			date_from_header = 1;
+			break;
+		case OPT_PACKAGE_VERSION:
+			if (check_th_field(arg, "--package-version")) {
+				return -1;
+			}
+			package_version = arg;
+			break;
+		case OPT_TH_MANUAL:
+			if (check_th_field(arg, "--th-manual")) {
+				return -1;
+			}
+			th_manual = arg;
+			break;
+		case OPT_TITLE_CASE:
+			if (strcmp(arg, "upper") == 0) {
+				title_asis = 0;
+			} else if (strcmp(arg, "asis") == 0) {
+				title_asis = 1;
+			} else {
+				fprintf(stderr, "--title-case must be upper or asis\n");
+				return -1;
+			}
+			break;
+		case OPT_GENERATED_COMMENT:
+			generated_comment = arg;
+			no_generated_comment = 0;
+			break;
+		case OPT_NO_GENERATED_COMMENT:
+			no_generated_comment = 1;
+			break;
+		case OPT_SOURCE_REVISION:
+			if (!arg[0] || strchr(arg, '\n')) {
+				fprintf(stderr, "--source-revision must be a single line\n");
+				return -1;
+			}
+			source_revision = arg;
+			break;
+		case OPT_COLOPHON:
+			colophon = 1;
+			break;
+		case 'o':
+			output_dir = arg;
+			break;
+		case OPT_OUTPUT_ARCHIVE:
+			output_archive = arg;
+			break;
+		case OPT_MAN_DIRS:
+			man_dirs = 1;
+			break;
+		case OPT_JSON_INDEX:
+			json_index_file = arg;
+			break;
+		case OPT_TODO_REPORT:
+			todo_report = arg;
+			break;
+		case OPT_TODO_FORMAT:
+			if (strcmp(arg, "text") == 0) {
+				todo_format = TODO_TEXT;
+			} else if (strcmp(arg, "json") == 0) {
+				todo_format = TODO_JSON;
+			} else {
+				fprintf(stderr, "--todo-format must be one of text or json\n");
+				return -1;
+			}
+			break;
+		case OPT_TAGS_FILE:
+			tags_file = arg;
+			break;
+		case OPT_GENERATE_TAGFILE:
+			generate_tagfile = arg;
+			break;
+		case OPT_TAGFILE:
+			return read_tagfile(arg);
+		case OPT_XML_ENCODING:
+			handler = xmlFindCharEncodingHandler(arg);
+			if (!handler) {
+				fprintf(stderr, "--xml-encoding %s is not an encoding that libxml2 knows about\n", arg);
+				return -1;
+			}
+			xmlCharEncCloseFunc(handler);
+			xml_encoding = arg;
+			break;
+		case OPT_FHS_LAYOUT:
+			man_dirs = 1;
+			compress = 1;
+			page_suffix = ".gz";
+			break;
+		case 'O':
+		        header_src_dir = arg;
+			break;
+		case OPT_SYNOPSIS_WIDTH:
+			if (get_number(arg, "--synopsis-width", 40, LINE_LENGTH_MAX, &synopsis_width)) {
+				return -1;
+			}
+			break;
+		case OPT_WRAP_WIDTH:
+			if (get_number(arg, "--wrap-width", 40, LINE_LENGTH_MAX, &wrap_width)) {
+				return -1;
+			}
+			break;
+		case OPT_MAX_PARAM_TYPE:
+			if (get_number(arg, "--max-param-type", 1, LINE_LENGTH_MAX, &max_param_type)) {
+				return -1;
+			}
+			break;
+		case OPT_EXAMPLE_MACROS:
+			example_macros = 1;
+			break;
+		case OPT_DIALECT:
+			if (set_dialect(arg)) {
+				return -1;
+			}
+			break;
+		case OPT_STYLE:
+			if (set_styles(arg)) {
+				return -1;
+			}
+			break;
+		case OPT_NO_PAGER:
+			no_pager = 1;
+			break;
+		case OPT_TEXT_PAGES:
+			text_pages = 1;
+			break;
+		case OPT_ALL:
+			all_headers_wanted = 1;
+			break;
+		case OPT_SYMBOL_DB:
+			symbol_db_file = arg;
+			break;
+		case OPT_WRITE_THREADS:
+			if (get_number(arg, "--write-threads", 1, MAX_THREADS, &write_threads)) {
+				return -1;
+			}
+			break;
+		case OPT_HEADER_INCLUDES:
+			header_includes = 1;
+			break;
+		case OPT_REFERENCES:
+			references = 1;
+			break;
+		case OPT_SOURCE_LOCATION:
+			source_location = 1;
+			break;
+		case 'j':
+			if (get_number(arg, "-j", 1, MAX_THREADS, &num_jobs)) {
+				return -1;
+			}
+			break;
+		case OPT_SELFTEST:
+			if (set_mode(opt, progname)) {
+				return -1;
+			}
+			selftest = 1;
+			break;
+		case OPT_VERIFY_REPRODUCIBLE:
+			if (set_mode(opt, progname)) {
+				return -1;
+			}
+			print_man = 1;
+			verify_reproducible = 1;
+			break;
+		case OPT_CONFIG:
+			if (read_config_file(arg)) {
+				return -1;
+			}
+			break;
+		case OPT_BUGS_URL:
+			bugs_url = arg;
+			break;
+		case OPT_BUGS_EMAIL:
+			bugs_email = arg;
+			break;
+		case OPT_LICENSE_FILE:
+			license_file = arg;
+			break;
+		case OPT_REQUIRE_SPDX:
+			require_spdx = 1;
+			break;
+		case OPT_EXAMPLES_DIR:
+			examples_dir = arg;
+			break;
+		case OPT_STRUCT_PAGES:
+			struct_pages = 1;
+			break;
+		case OPT_ENUM_PAGES:
+			enum_pages = 1;
+			break;
+		case OPT_CONSTANTS_ALL:
+			constants_all = 1;
+			/* fallthrough */
+		case OPT_CONSTANTS_PAGE:
+			constants_page = 1;
+			break;
+		case OPT_FORCE:
+			force = 1;
+			break;
+		case OPT_NO_CLOBBER:
+			no_clobber = 1;
+			break;
+		case OPT_BACKUP:
+			backup = 1;
+			break;
+		case OPT_OLD_MANIFEST:
+			old_manifest = arg;
+			break;
+		case OPT_ALIAS_STUBS:
+			alias_stubs = 1;
+			break;
+		case OPT_ALIAS_LINKS:
+			if (strcmp(arg, "so") == 0) {
+				alias_links = ALIAS_SO;
+			} else if (strcmp(arg, "symlink") == 0) {
+				alias_links = ALIAS_SYMLINK;
+			} else if (strcmp(arg, "hardlink") == 0) {
+				alias_links = ALIAS_HARDLINK;
+			} else {
+				fprintf(stderr, "--alias-links must be one of so, symlink or hardlink\n");
+				return -1;
+			}
+			break;
+		case OPT_CLEAN:
+			clean = 1;
+			break;
+		case OPT_INCLUDE_SYMBOLS:
+			return compile_symbol_regex(&include_symbols_re, &include_symbols, arg, "--include-symbols");
+		case OPT_EXCLUDE_SYMBOLS:
+			return compile_symbol_regex(&exclude_symbols_re, &exclude_symbols, arg, "--exclude-symbols");
+		case OPT_SYMBOL_PREFIX:
+			symbol_prefix = arg;
+			break;
+		case OPT_COMBINE_BY_PREFIX:
+			combine_prefix = arg;
+			break;
+		case OPT_EXCLUDE_STATIC:
+			exclude_static = 1;
+			break;
+		case OPT_EXCLUDE_INLINE:
+			exclude_inline = 1;
+			break;
+		case OPT_EXPORTED_SYMBOLS:
+			exported_symbols_file = arg;
+			break;
+		case OPT_SYMBOL_INFO:
+			symbol_info_file = arg;
+			break;
+		case OPT_SEE_ALSO:
+			return add_extra_see_also(arg);
+		case OPT_SEE_ALSO_GROUP:
+			see_also_group = 1;
+			break;
+		case OPT_SORT:
+			if (strcmp(arg, "source") == 0) {
+				sort_order = SORT_SOURCE;
+			} else if (strcmp(arg, "alpha") == 0) {
+				sort_order = SORT_ALPHA;
+			} else if (strcmp(arg, "group") == 0) {
+				sort_order = SORT_GROUP;
+			} else {
+				fprintf(stderr, "--sort must be one of source, alpha or group\n");
+				return -1;
+			}
+			break;
+		case OPT_LIST_SYMBOLS:
+			if (set_mode(opt, progname)) {
+				return -1;
+			}
+			list_symbols = 1;
+			break;
+		case OPT_KEEP_GOING:
+			keep_going = 1;
+			break;
+		case OPT_MANIFEST:
+			manifest_file = arg;
+			break;
+		case OPT_WHATIS_FILE:
+			whatis_file = arg;
+			break;
+		case OPT_INSTALL_METADATA:
+			install_metadata = arg;
+			break;
+		case OPT_PAGE_HOOK:
+			page_hook = arg;
+			break;
+		case OPT_INDEX_PAGE:
+			index_page = arg;
+			break;
+		case OPT_DEPRECATED_PAGE:
+			deprecated_page = arg;
+			break;
+		case OPT_PREVIEW:
+			if (set_mode(opt, progname)) {
+				return -1;
+			}
+			preview_name = arg;
+			break;
+		case OPT_LINT_OUTPUT:
+			lint_output = 1;
+			break;
+		case OPT_STRICT:
+			lint_strict = 1;
+			break;
+		case OPT_LINT_DOCS:
+			lint_docs = 1;
+			break;
+		case OPT_NO_LINT:
+			if (set_lint_levels(arg, "--no-lint")) {
+				return -1;
+			}
+			break;
+		case OPT_SPELL_CHECK:
+			spell_command = arg;
+			break;
+		case OPT_SPELL_WORDS:
+			spell_words_file = arg;
+			break;
+		case OPT_LINT_LEVEL:
+			if (set_lint_levels(arg, "--lint-level")) {
+				return -1;
+			}
+			break;
+		case OPT_EMIT_INSTALL_RULES:
+			if (strcmp(arg, "make") == 0) {
+				install_rules = RULES_MAKE;
+			} else if (strcmp(arg, "meson") == 0) {
+				install_rules = RULES_MESON;
+			} else if (strcmp(arg, "cmake") == 0) {
+				install_rules = RULES_CMAKE;
+			} else {
+				fprintf(stderr, "--emit-install-rules must be one of make, meson or cmake\n");
+				return -1;
+			}
+			break;
+		case OPT_EMIT_RULES:
+			if (set_mode(opt, progname)) {
+				return -1;
+			}
+			if (strcmp(arg, "automake") == 0) {
+				build_rules = RULES_MAKE;
+			} else if (strcmp(arg, "meson") == 0) {
+				build_rules = RULES_MESON;
+			} else if (strcmp(arg, "cmake") == 0) {
+				build_rules = RULES_CMAKE;
+			} else {
+				fprintf(stderr, "--emit-rules must be one of automake, meson or cmake\n");
+				return -1;
+			}
+			break;
+		case 'h':
+			usage(progname);
+			return 1;
+		case '?':
+			usage(progname);
+			return -1;
+	}
+	return 0;
+}
+
+/*
+ * How the run went, as an exit code. A page that couldn't be written is
+ * always reported, even if --keep-going wrote others.
+ */
+static int get_exit_code(void)
+{
+	if (worst_error >= D2M_ERR_LINT && worst_error < D2M_ERR_IO && keep_going && pages_written) {
+		return EXIT_PARTIAL;
+	}
+	switch (worst_error) {
+	case D2M_ERR_IO:
+		return EXIT_IO;
+	case D2M_ERR_PARSE:
+		return EXIT_PARSE;
+	case D2M_ERR_LINT:
+		return EXIT_LINT;
+	default:
+		return 0;
+	}
+}
+
+/* For merging the exit codes of the -j workers, a worker that died counts as EXIT_IO */
+static int exit_code_severity(int code)
+{
+	switch (code) {
+	case 0:
+		return 0;
+	case EXIT_PARTIAL:
+		return 1;
+	case EXIT_LINT:
+		return 2;
+	case EXIT_PARSE:
+		return 3;
+	case EXIT_USAGE:
+		return 4;
+	default:
+		return 5;
+	}
+}
+
+static int worse_exit_code(int a, int b)
+{
+	return exit_code_severity(b) > exit_code_severity(a) ? b : a;
+}
+
+/* Exported functions that no XML file documented */
+static void check_exported_symbols(void)
+{
+	struct exported_symbol *es;
+	struct qb_list_head *iter;
+
+	qb_list_for_each(iter, &exported_symbols_list) {
+		es = qb_list_entry(iter, struct exported_symbol, list);
+		if (!es->documented && !strpbrk(es->name, "*?[")) {
+			lint_problem(exported_symbols_file, es->name, "exported but not documented");
+		}
+	}
+}
+
+/*
+ * What each worker does, all the pages for all the files and then anything
+ * for the whole run. Returns the exit code.
+ */
+static int run_job(char **xml_files, size_t num_files)
+{
+	size_t i;
+
+	for (i = 0; i < num_files; i++) {
+		if (process_file(xml_files[i]) && !keep_going) {
+			finish_writes();
+			return get_exit_code();
+		}
+	}
+
+	if (exported_symbols_file) {
+		check_exported_symbols();
+	}
+	if (index_page && print_man && !list_symbols) {
+		print_index_page();
+	}
+	if (deprecated_page && print_man && !list_symbols) {
+		print_deprecated_page();
+	}
+	if (combine_prefix && print_man && !list_symbols) {
+		write_alias_pages();
+	}
+	/* Everything after this wants the pages to be there */
+	finish_writes();
+	/* With --clean the manifest from the last run tells us what we wrote */
+	if ((old_manifest || clean) && print_man && !list_symbols) {
+		check_old_manifest(old_manifest ? old_manifest : manifest_file);
+	}
+	if (manifest_file && print_man && !list_symbols) {
+		write_manifest();
+	}
+	if (install_metadata && print_man && !list_symbols) {
+		write_install_metadata();
+	}
+	if (whatis_file && print_man && !list_symbols) {
+		write_whatis();
+	}
+	if (json_index_file && !list_symbols) {
+		write_json_index();
+	}
+	if (tags_file && !list_symbols) {
+		write_tags_file();
+	}
+	if (todo_report && !list_symbols) {
+		write_todo_report();
+	}
+	if (generate_tagfile && print_man && !list_symbols) {
+		write_generate_tagfile();
+	}
+	if (symbol_db_file && print_man && !list_symbols) {
+		write_symbol_db();
+	}
+	if (install_rules && print_man && !list_symbols) {
+		write_install_rules(stdout);
+	}
+	return get_exit_code();
+}
+
+/* mkdir -p */
+static int make_dirs(const char *dir)
+{
+	char path[PATH_MAX];
+	char *slash;
+
+	snprintf(path, sizeof(path), "%s", dir);
+	for (slash = strchr(path + 1, '/'); ; slash = strchr(slash + 1, '/')) {
+		if (slash) {
+			*slash = '\0';
+		}
+		if (mkdir(path, 0755) == -1 && errno != EEXIST) {
+			report_error(D2M_ERR_IO, path, NULL, "unable to create directory: %s", strerror(errno));
+			return -1;
+		}
+		if (!slash) {
+			break;
+		}
+		*slash = '/';
+	}
+	return 0;
+}
+
+static int remove_entry(const char *path, const struct stat *st, int flag, struct FTW *ftw)
+{
+	return remove(path);
+}
+
+static int archive_filter(const struct dirent *d)
+{
+	return strcmp(d->d_name, ".") != 0 && strcmp(d->d_name, "..") != 0;
+}
+
+/* Run tar or gzip, argv[0] is the program */
+static int run_tool(char **argv)
+{
+	int status;
+	pid_t pid;
+
+	fflush(stdout);
+	pid = fork();
+	if (pid == 0) {
+		execvp(argv[0], argv);
+		fprintf(stderr, "Unable to run %s: %s\n", argv[0], strerror(errno));
+		_exit(127);
+	}
+	if (pid == -1 || waitpid(pid, &status, 0) == -1 ||
+	    !WIFEXITED(status) || WEXITSTATUS(status) != 0) {
+		return -1;
+	}
+	return 0;
+}
+
+/*
+ * --fhs-layout, gzip the pages once they have all been written. The
+ * workers don't know when the others have finished with the SEE ALSO
+ * lists and alias pages, so this is done at the very end. Only our own
+ * pages are compressed, anything else in the directory is left alone.
+ */
+static int compress_pages(const char *dir)
+{
+	struct dirent **names = NULL;
+	char filename[PATH_MAX * 2];
+	char linkname[PATH_MAX * 2];
+	struct stat st;
+	char **argv;
+	char **links;
+	ino_t *inodes;
+	const char *dot;
+	int num_names;
+	int num_links = 0;
+	int argc = 0;
+	int ret = 0;
+	int i;
+	int j;
+
+	num_names = scandir(dir, &names, archive_filter, alphasort);
+	if (num_names < 0) {
+		report_error(D2M_ERR_IO, dir, NULL, "unable to read directory: %s", strerror(errno));
+		return -1;
+	}
+	argv = calloc(num_names + 4, sizeof(char *));
+	links = calloc(num_names + 1, 2 * sizeof(char *));
+	inodes = calloc(num_names + 4, sizeof(ino_t));
+	if (!argv || !links || !inodes) {
+		free(argv);
+		free(links);
+		free(inodes);
+		return -1;
+	}
+	argv[argc++] = (char *)"gzip";
+	argv[argc++] = (char *)"-9nf";
+	for (i = 0; i < num_names; i++) {
+		dot = strrchr(names[i]->d_name, '.');
+		snprintf(filename, sizeof(filename), "%s/%s", dir, names[i]->d_name);
+		if (dot && (strcmp(dot + 1, man_section) == 0 || strcmp(dot + 1, type_section()) == 0 ||
+			    (deprecated_page && strcmp(dot + 1, "7") == 0)) &&
+		    lstat(filename, &st) == 0 &&
+		    S_ISREG(st.st_mode) && is_generated_file(filename) == 1) {
+			/* A hard link from --alias-links is linked to the compressed page instead */
+			for (j = 2; st.st_nlink > 1 && j < argc; j++) {
+				if (inodes[j] == st.st_ino) {
+					links[num_links * 2] = strdup(filename);
+					links[num_links * 2 + 1] = argv[j];
+					num_links++;
+					break;
 				}
-				break;
-			case 'p':
-				package_name = optarg;
-				break;
-			case 'H':
-				header = optarg;
-				break;
-			case 'o':
-				output_dir = optarg;
-				break;
-			case 'O':
-			        header_src_dir = optarg;
-				break;
-			case '?':
-			case 'h':
-				usage(argv[0]);
-				return 0;
+			}
+			if (st.st_nlink <= 1 || j == argc) {
+				inodes[argc] = st.st_ino;
+				argv[argc++] = strdup(filename);
+			}
 		}
+		free(names[i]);
 	}
+	free(names);
 
-	if (argv[optind]) {
-		xml_file = argv[optind];
+	if (argc > 2) {
+		ret = run_tool(argv);
+		if (ret) {
+			report_error(D2M_ERR_IO, dir, NULL, "unable to compress pages");
+		} else {
+			log_msg(LOG_VERBOSE, "Compressed %d page%s\n", argc - 2, argc == 3 ? "" : "s");
+		}
+	}
+	for (i = 0; ret == 0 && i < num_links; i++) {
+		snprintf(filename, sizeof(filename), "%s.gz", links[i * 2 + 1]);
+		snprintf(linkname, sizeof(linkname), "%s.gz", links[i * 2]);
+		unlink(links[i * 2]);
+		unlink(linkname);
+		if (link(filename, linkname) == -1) {
+			report_error(D2M_ERR_IO, linkname, NULL, "unable to link to %s: %s", filename, strerror(errno));
+			ret = -1;
+		}
 	}
-	if (!xml_file) {
-		usage(argv[0]);
-		exit(1);
+	for (i = 0; i < num_links; i++) {
+		free(links[i * 2]);
 	}
+	for (i = 2; i < argc; i++) {
+		free(argv[i]);
+	}
+	free(argv);
+	free(links);
+	free(inodes);
+	return ret;
+}
 
-	if (!quiet) {
-		printf("reading %s ... ", xml_file);
+/*
+ * --output-archive, the pages were written to archive_dir and go into
+ * the archive from there. tar picks the compression from the name. The
+ * files are listed in order, rather than letting tar walk the directory,
+ * so that the same pages always make the same archive.
+ */
+static int write_archive(void)
+{
+	char archive[PATH_MAX * 2];
+	char cwd[PATH_MAX];
+	char **argv;
+	struct dirent **top = NULL;
+	struct dirent **sub;
+	struct stat st;
+	int num_top;
+	int num_sub;
+	int argc = 0;
+	int status;
+	int i;
+	int j;
+
+	/* tar runs in archive_dir */
+	if (output_archive[0] != '/' && getcwd(cwd, sizeof(cwd))) {
+		snprintf(archive, sizeof(archive), "%s/%s", cwd, output_archive);
+	} else {
+		snprintf(archive, sizeof(archive), "%s", output_archive);
 	}
 
-	snprintf(xml_filename, sizeof(xml_filename), "%s/%s", xml_dir, xml_file);
-	doc = xmlParseFile(xml_filename);
-	if (doc == NULL) {
-		fprintf(stderr, "Error: unable to read xml file %s\n", xml_filename);
-		exit(1);
+	num_top = scandir(archive_dir, &top, archive_filter, alphasort);
+	if (num_top < 0) {
+		report_error(D2M_ERR_IO, archive_dir, NULL, "unable to read directory: %s", strerror(errno));
+		return -1;
+	}
+	argv = calloc(8 + num_top, sizeof(char *));
+	if (!argv) {
+		return -1;
+	}
+	argv[argc++] = (char *)"tar";
+	argv[argc++] = (char *)"-caf";
+	argv[argc++] = archive;
+	argv[argc++] = (char *)"--no-recursion";
+	argv[argc++] = (char *)"-C";
+	argv[argc++] = archive_dir;
+	for (i = 0; i < num_top; i++) {
+		char subdir[PATH_MAX * 2];
+
+		argv[argc++] = strdup(top[i]->d_name);
+
+		/* --man-dirs, and not every filesystem fills in d_type */
+		snprintf(subdir, sizeof(subdir), "%s/%s", archive_dir, top[i]->d_name);
+		if (top[i]->d_type != DT_DIR &&
+		    (top[i]->d_type != DT_UNKNOWN || stat(subdir, &st) == -1 || !S_ISDIR(st.st_mode))) {
+			continue;
+		}
+		num_sub = scandir(subdir, &sub, archive_filter, alphasort);
+		if (num_sub < 0) {
+			continue;
+		}
+		argv = realloc(argv, (argc + num_sub + num_top - i + 2) * sizeof(char *));
+		if (!argv) {
+			return -1;
+		}
+		for (j = 0; j < num_sub; j++) {
+			snprintf(subdir, sizeof(subdir), "%s/%s", top[i]->d_name, sub[j]->d_name);
+			argv[argc++] = strdup(subdir);
+			free(sub[j]);
+		}
+		free(sub);
 	}
+	argv[argc] = NULL;
 
-	rootdoc = xmlDocGetRootElement(doc);
-	if (!rootdoc) {
-		fprintf(stderr, "Can't find \"document root\"\n");
-		exit(1);
+	status = run_tool(argv);
+	if (status) {
+		report_error(D2M_ERR_IO, output_archive, NULL, "unable to write archive");
+	} else {
+		log_msg(LOG_INFO, "Wrote %s\n", output_archive);
 	}
-	if (!quiet)
-		printf("done.\n");
 
-	/* Get our header file name */
-	if (!headerfile) {
-		traverse_node(rootdoc, "compounddef", read_headername, &headerfile);
+	for (i = 6; i < argc; i++) {
+		free(argv[i]);
+	}
+	free(argv);
+	for (i = 0; i < num_top; i++) {
+		free(top[i]);
+	}
+	free(top);
+	return status;
+}
 
-		if (use_header_copyright) {
-			/* And get the copyright line from this file if we can */
-			char file_path[PATH_MAX];
-			char file_line[256];
-			FILE *hfile;
-			int lineno = 0;
-
-			snprintf(file_path, sizeof(file_path), "%s/%s", header_src_dir, headerfile);
-			hfile = fopen(file_path, "r");
-			if (hfile) {
-				/* Don't look too far, this should be at the top */
-				while (!feof(hfile) && (lineno++ < 10)) {
-					if (fgets(file_line, sizeof(file_line)-1, hfile)) {
-						if (strncmp(file_line, " * Copyright", 12) == 0) {
-							/* Keep the NL at the end of the buffer, it save us printing one */
-							strncpy(header_copyright, file_line+3, sizeof(header_copyright)-1);
-							break;
-						}
-					}
-				}
-				fclose(hfile);
+/*
+ * --preview, generate just one page into a temporary directory and
+ * show it with man(1). Returns the exit code.
+ */
+static int run_preview(char **xml_files, size_t num_files)
+{
+	char tmpdir[PATH_MAX];
+	char pagefile[PATH_MAX * 2];
+	const char *tmpbase;
+	struct stat st;
+	int status;
+	int ret;
+	pid_t pid;
+
+	tmpbase = getenv("TMPDIR");
+	if (!tmpbase) {
+		tmpbase = "/tmp";
+	}
+	snprintf(tmpdir, sizeof(tmpdir), "%s/doxygen2man-preview.XXXXXX", tmpbase);
+	if (!mkdtemp(tmpdir)) {
+		report_error(D2M_ERR_IO, tmpdir, NULL, "unable to create preview directory: %s", strerror(errno));
+		return EXIT_IO;
+	}
+	output_dir = tmpdir;
+
+	ret = run_job(xml_files, num_files);
+
+	snprintf(pagefile, sizeof(pagefile), "%s/%s.%s", tmpdir, preview_name, man_section);
+	if ((struct_pages || enum_pages) && stat(pagefile, &st) == -1) {
+		snprintf(pagefile, sizeof(pagefile), "%s/%s.%s", tmpdir, preview_name, type_section());
+	}
+	if (ret == 0 && stat(pagefile, &st) == -1) {
+		report_error(D2M_ERR_PARSE, NULL, preview_name, "no page generated, is it a documented function?");
+		ret = EXIT_PARSE;
+	}
+
+	if (ret == 0) {
+		fflush(stdout);
+		pid = fork();
+		if (pid == 0) {
+			execlp("man", "man", "-l", pagefile, NULL);
+			fprintf(stderr, "Unable to run man: %s\n", strerror(errno));
+			_exit(127);
+		}
+		if (pid == -1 || waitpid(pid, &status, 0) == -1 ||
+		    !WIFEXITED(status) || WEXITSTATUS(status) != 0) {
+			ret = EXIT_IO;
+		}
+	}
+
+	/* Options like --index-page write more pages than the one shown */
+	nftw(tmpdir, remove_entry, 16, FTW_DEPTH | FTW_PHYS);
+	return ret;
+}
+
+/* Returns 1 if the two files have the same contents */
+static int same_contents(const char *file1, const char *file2)
+{
+	char buffer1[4096];
+	char buffer2[4096];
+	size_t len1;
+	size_t len2;
+	FILE *f1;
+	FILE *f2;
+	int same = 0;
+
+	f1 = fopen(file1, "r");
+	f2 = fopen(file2, "r");
+	if (f1 && f2) {
+		do {
+			len1 = fread(buffer1, 1, sizeof(buffer1), f1);
+			len2 = fread(buffer2, 1, sizeof(buffer2), f2);
+			same = len1 == len2 && memcmp(buffer1, buffer2, len1) == 0;
+		} while (same && len1 > 0);
+	}
+	if (f1) {
+		fclose(f1);
+	}
+	if (f2) {
+		fclose(f2);
+	}
+	return same;
+}
+
+/*
+ * Report the pages that differ between the two --verify-reproducible runs.
+ * Returns how many do, *num_pages is set to how many there are altogether.
+ */
+static int compare_runs(const char *dir1, const char *dir2, int *num_pages)
+{
+	char path1[PATH_MAX * 2];
+	char path2[PATH_MAX * 2];
+	struct dirent **names1 = NULL;
+	struct dirent **names2 = NULL;
+	int num1;
+	int num2;
+	int i = 0;
+	int j = 0;
+	int cmp;
+	int differences = 0;
+
+	*num_pages = 0;
+	num1 = scandir(dir1, &names1, archive_filter, alphasort);
+	num2 = scandir(dir2, &names2, archive_filter, alphasort);
+	if (num1 < 0 || num2 < 0) {
+		report_error(D2M_ERR_IO, num1 < 0 ? dir1 : dir2, NULL, "unable to read directory: %s", strerror(errno));
+		num1 = num1 < 0 ? 0 : num1;
+		num2 = num2 < 0 ? 0 : num2;
+		differences++;
+	}
+
+	/* Both lists are sorted, so walk along them together */
+	while (i < num1 || j < num2) {
+		if (i == num1) {
+			cmp = 1;
+		} else if (j == num2) {
+			cmp = -1;
+		} else {
+			cmp = strcmp(names1[i]->d_name, names2[j]->d_name);
+		}
+
+		(*num_pages)++;
+		if (cmp < 0) {
+			report_error(D2M_ERR_LINT, names1[i]->d_name, NULL, "only generated by the first run");
+			differences++;
+			free(names1[i++]);
+		} else if (cmp > 0) {
+			report_error(D2M_ERR_LINT, names2[j]->d_name, NULL, "only generated by the second run");
+			differences++;
+			free(names2[j++]);
+		} else {
+			snprintf(path1, sizeof(path1), "%s/%s", dir1, names1[i]->d_name);
+			snprintf(path2, sizeof(path2), "%s/%s", dir2, names2[j]->d_name);
+			if (!same_contents(path1, path2)) {
+				report_error(D2M_ERR_LINT, names1[i]->d_name, NULL, "differs between runs");
+				differences++;
 			}
+			free(names1[i++]);
+			free(names2[j++]);
 		}
 	}
+	free(names1);
+	free(names2);
+	return differences;
+}
 
-	/* Default to *something* if it all goes wrong */
-	if (!headerfile) {
-		headerfile = "unknown.h";
+/*
+ * --verify-reproducible, generate all of the pages twice into a temporary
+ * directory and check that they are byte-for-byte the same. The second
+ * run reads the XML files backwards and uses bigger hashtables, so any
+ * page that depends on the order things happen to be read in shows up.
+ * Returns the exit code.
+ */
+static int run_verify(char **xml_files, size_t num_files)
+{
+	char tmpdir[PATH_MAX];
+	char rundir[2][PATH_MAX + 8];
+	char **reversed;
+	const char *tmpbase;
+	size_t i;
+	int differences;
+	int num_pages;
+	int status;
+	int code;
+	int ret = 0;
+	int run;
+	pid_t pid;
+
+	reversed = calloc(num_files + 1, sizeof(*reversed));
+	if (!reversed) {
+		return EXIT_IO;
+	}
+	for (i = 0; i < num_files; i++) {
+		reversed[i] = xml_files[num_files - 1 - i];
 	}
 
-	qb_list_init(&params_list);
-	qb_list_init(&retval_list);
-	structures_map = qb_hashtable_create(10);
-	function_map = qb_hashtable_create(10);
-	used_structures_map = qb_hashtable_create(10);
+	tmpbase = getenv("TMPDIR");
+	if (!tmpbase) {
+		tmpbase = "/tmp";
+	}
+	snprintf(tmpdir, sizeof(tmpdir), "%s/doxygen2man-verify.XXXXXX", tmpbase);
+	if (!mkdtemp(tmpdir)) {
+		report_error(D2M_ERR_IO, tmpdir, NULL, "unable to create verify directory: %s", strerror(errno));
+		free(reversed);
+		return EXIT_IO;
+	}
 
-	/* Collect functions */
-	traverse_node(rootdoc, "memberdef", collect_functions, NULL);
+	/* Each run has a process of its own, so nothing is left over from the first one */
+	fflush(stdout);
+	for (run = 0; run < 2; run++) {
+		snprintf(rundir[run], sizeof(rundir[run]), "%s/%d", tmpdir, run + 1);
+		if (mkdir(rundir[run], 0755) == -1) {
+			report_error(D2M_ERR_IO, rundir[run], NULL, "unable to create directory: %s", strerror(errno));
+			ret = EXIT_IO;
+			break;
+		}
+		pid = fork();
+		if (pid == 0) {
+			output_dir = rundir[run];
+			if (run == 1) {
+				hashtable_size = 1024;
+				exit(run_job(reversed, num_files));
+			}
+			exit(run_job(xml_files, num_files));
+		}
+		if (pid == -1 || waitpid(pid, &status, 0) == -1) {
+			ret = EXIT_IO;
+			break;
+		}
+		code = WIFEXITED(status) ? WEXITSTATUS(status) : EXIT_IO;
+		ret = worse_exit_code(ret, code);
+		if (code != 0 && code != EXIT_PARTIAL) {
+			break;
+		}
+	}
 
-	/* Collect enums */
-	traverse_node(rootdoc, "memberdef", collect_enums, NULL);
+	if (ret == 0 || ret == EXIT_PARTIAL) {
+		differences = compare_runs(rundir[0], rundir[1], &num_pages);
+		printf("verify-reproducible: %d page%s, %d different\n",
+		       num_pages, num_pages == 1 ? "" : "s", differences);
+		if (differences) {
+			ret = EXIT_LINT;
+		}
+	}
 
-	/* print pages */
-	traverse_node(rootdoc, "memberdef", traverse_members, NULL);
+	free(reversed);
+	nftw(tmpdir, remove_entry, 16, FTW_DEPTH | FTW_PHYS);
+	return ret;
+}
 
-	if (print_general) {
-		/* Generate and print a page for the headerfile itself */
-		traverse_node(rootdoc, "compounddef", traverse_members, (char *)headerfile);
+/*
+ * XML file names can be glob patterns (quoted, so the shell leaves them alone),
+ * eg 'qb*_8h.xml'. They are matched in the XML directory and each pattern's
+ * matches are sorted so that pages are always generated in the same order.
+ */
+static int expand_xml_files(int num_args, char *args[], glob_t *files)
+{
+	char pattern[PATH_MAX];
+	size_t dirlen;
+	size_t first;
+	size_t i;
+	int flags = GLOB_NOCHECK;
+	int ret = 0;
+	int n;
+
+	memset(files, 0, sizeof(*files));
+	snprintf(pattern, sizeof(pattern), "%s/", xml_dir);
+	dirlen = strlen(pattern);
+
+	for (n = 0; n < num_args; n++) {
+		first = files->gl_pathc;
+		if (strpbrk(args[n], "*?[")) {
+			snprintf(pattern + dirlen, sizeof(pattern) - dirlen, "%s", args[n]);
+			if (glob(pattern, flags & ~GLOB_NOCHECK, NULL, files) != 0 ||
+			    files->gl_pathc == first) {
+				report_error(D2M_ERR_PARSE, pattern, NULL, "no XML files match");
+				ret = -1;
+				flags |= GLOB_APPEND;
+				continue;
+			}
+			/* process_file() wants names relative to the XML directory */
+			for (i = first; i < files->gl_pathc; i++) {
+				memmove(files->gl_pathv[i], files->gl_pathv[i] + dirlen,
+					strlen(files->gl_pathv[i] + dirlen) + 1);
+			}
+		} else {
+			/* Not a pattern, just pass it through as it is */
+			glob(args[n], flags | GLOB_NOESCAPE, NULL, files);
+		}
+		flags |= GLOB_APPEND;
 	}
-	return 0;
+	return ret;
+}
+
+/*
+ * --all, every header that doxygen found in one run, so they share the
+ * structures read in and the index pages cover all of them. The list is in
+ * index.xml, or if that's not there it's all the *_8h.xml files.
+ */
+static int find_header_files(glob_t *files)
+{
+	char pattern[] = "*_8h.xml";
+	char *patterns[] = {pattern};
+	qb_map_t *headers;
+	qb_map_iter_t *map_iter;
+	const char *xml_file;
+	char **names;
+	void *data;
+	int num_names = 0;
+	int ret;
+
+	headers = read_index_headers();
+	if (!headers || qb_map_count_get(headers) == 0) {
+		log_msg(LOG_VERBOSE, "No headers in doxygen's index, looking for %s\n", pattern);
+		if (headers) {
+			qb_map_destroy(headers);
+		}
+		return expand_xml_files(1, patterns, files);
+	}
+
+	names = calloc(qb_map_count_get(headers), sizeof(char *));
+	if (!names) {
+		qb_map_destroy(headers);
+		memset(files, 0, sizeof(*files));
+		return -1;
+	}
+	map_iter = qb_map_iter_create(headers);
+	for (xml_file = qb_map_iter_next(map_iter, &data); xml_file; xml_file = qb_map_iter_next(map_iter, &data)) {
+		names[num_names++] = (char *)xml_file;
+	}
+	qb_map_iter_free(map_iter);
+
+	ret = expand_xml_files(num_names, names, files);
+	free(names);
+	qb_map_destroy(headers);
+	return ret;
+}
+
+int main(int argc, char *argv[])
+{
+	int opt;
+	int ret;
+	pid_t pager = 0;
+	glob_t xml_files;
+
+	/* doxygen XML is always UTF-8, we need to know how wide those characters are */
+	if (!setlocale(LC_CTYPE, "C.UTF-8")) {
+		setlocale(LC_CTYPE, "");
+	}
+
+	while ( (opt = getopt_long(argc, argv, "H:amqvgcPD:Y:s:S:d:o:p:f:I:i:C:O:j:h?", long_options, NULL)) != EOF)
+	{
+		ret = handle_option(opt, optarg, argv[0]);
+		if (ret) {
+			return ret == 1 ? 0 : EXIT_USAGE;
+		}
+	}
+	/* Either of them can come from a config file, so this waits until they've all been read */
+	if (manpage_year != LONG_MIN && start_year > manpage_year) {
+		fprintf(stderr, "The start year given to -S (%ld) is after the year given to -Y (%ld)\n",
+			start_year, manpage_year);
+		return EXIT_USAGE;
+	}
+
+	if (sort_order == SORT_GROUP || see_also_group) {
+		read_member_groups();
+	}
+	if (references) {
+		read_header_functions();
+	}
+	if (license_file && read_license_file()) {
+		return EXIT_IO;
+	}
+	if (source_revision && strcmp(source_revision, "auto") == 0) {
+		if (get_git_revision()) {
+			return EXIT_IO;
+		}
+		source_revision = git_revision;
+	}
+	if (exported_symbols_file && read_exported_symbols()) {
+		return EXIT_IO;
+	}
+	if (spell_words_file && read_spell_words()) {
+		return EXIT_IO;
+	}
+	if (symbol_info_file && read_symbol_info()) {
+		return EXIT_IO;
+	}
+	if (symbol_db_file && read_symbol_db()) {
+		return EXIT_IO;
+	}
+	options_hash = hash_options();
+	all_pages = qb_skiplist_create();
+	all_headers = qb_skiplist_create();
+	qb_map_notify_add(all_pages, NULL, map_free_page, QB_MAP_NOTIFY_FREE, NULL);
+	qb_map_notify_add(all_headers, NULL, map_free_entry, QB_MAP_NOTIFY_FREE, NULL);
+	index_symbols = qb_skiplist_create();
+	qb_map_notify_add(index_symbols, NULL, map_free_index_symbol, QB_MAP_NOTIFY_FREE, NULL);
+	deprecated_map = qb_skiplist_create();
+	qb_map_notify_add(deprecated_map, NULL, map_free_deprecated, QB_MAP_NOTIFY_FREE, NULL);
+
+	if (selftest) {
+		if (!xml_dir_set) {
+			xml_dir = FIXTURE_DIR;
+		}
+		return run_selftest();
+	}
+
+	if (build_rules) {
+		return write_build_rules(stdout) ? EXIT_PARSE : 0;
+	}
+
+	if (all_headers_wanted) {
+		if (optind < argc) {
+			fprintf(stderr, "--all processes every header, it can't be given XML files as well\n");
+			return EXIT_USAGE;
+		}
+		if (find_header_files(&xml_files) && !keep_going) {
+			globfree(&xml_files);
+			return EXIT_PARSE;
+		}
+	} else {
+		if (optind >= argc) {
+			usage(argv[0]);
+			exit(EXIT_USAGE);
+		}
+		if (expand_xml_files(argc - optind, argv + optind, &xml_files) && !keep_going) {
+			globfree(&xml_files);
+			return EXIT_PARSE;
+		}
+	}
+
+	if (clean && !manifest_file && !old_manifest) {
+		fprintf(stderr, "--clean needs --manifest or --old-manifest\n");
+		globfree(&xml_files);
+		return EXIT_USAGE;
+	}
+
+	/*
+	 * Preview one page, exactly as it would be written. --verify-reproducible
+	 * doesn't write anything either, only the pages themselves are compared.
+	 */
+	if (preview_name || verify_reproducible) {
+		print_man = 1;
+		print_ascii = 0;
+		force = 1;
+		clean = 0;
+		manifest_file = NULL;
+		old_manifest = NULL;
+		install_metadata = NULL;
+		json_index_file = NULL;
+		todo_report = NULL;
+		tags_file = NULL;
+		generate_tagfile = NULL;
+		symbol_db_file = NULL;
+		whatis_file = NULL;
+		install_rules = RULES_NONE;
+		if (verbosity == LOG_INFO) {
+			verbosity = LOG_QUIET;
+		}
+	}
+	if (verify_reproducible) {
+		output_archive = NULL;
+		man_dirs = 0;
+		num_jobs = 1;
+	}
+
+	/* ASCII output goes to stdout and would get jumbled up */
+	if ((print_ascii && !text_pages) || list_symbols || preview_name) {
+		num_jobs = 1;
+	}
+
+	/*
+	 * Fill -a output to --wrap-width, or the terminal, or $COLUMNS. Text
+	 * pages are 80 columns, wherever they were generated.
+	 */
+	if (print_ascii) {
+		struct winsize ws;
+		const char *columns = getenv("COLUMNS");
+
+		if (wrap_width) {
+			term_width = wrap_width;
+		} else if (text_pages) {
+			term_width = 80;
+		} else if (isatty(STDOUT_FILENO) && ioctl(STDOUT_FILENO, TIOCGWINSZ, &ws) == 0 && ws.ws_col) {
+			term_width = ws.ws_col;
+		} else if (columns && atoi(columns) > 0) {
+			term_width = atoi(columns);
+		}
+		if (term_width < 40) {
+			term_width = 40;
+		}
+		if (!text_pages && isatty(STDOUT_FILENO)) {
+			term_fonts = 1;
+			if (!no_pager) {
+				pager = start_pager();
+			}
+		}
+	}
+
+	/*
+	 * Pages dropped by a --page-hook have to come out of the lists of
+	 * pages, and a worker can't take them out of ours
+	 */
+	if (page_hook && (manifest_file || install_metadata || whatis_file || install_rules ||
+			  old_manifest || clean)) {
+		num_jobs = 1;
+	}
+
+	/* These need each page on disk as soon as it has been generated */
+	if (write_threads && (wrap_width || page_hook || lint_output || alias_links == ALIAS_HARDLINK)) {
+		fprintf(stderr, "Warning: --write-threads is ignored with %s, pages are written one at a time\n",
+			wrap_width ? "--wrap-width" : page_hook ? "--page-hook" :
+			lint_output ? "--lint-output" : "--alias-links hardlink");
+		write_threads = 0;
+	}
+
+	/* Only the list (or install rules) goes to stdout */
+	if ((list_symbols || install_rules) && verbosity == LOG_INFO) {
+		verbosity = LOG_QUIET;
+	}
+
+	/* The pages for an archive are put together in a temporary directory */
+	if (output_archive && print_man && !list_symbols && !preview_name) {
+		const char *tmpbase = getenv("TMPDIR");
+
+		snprintf(archive_dir, sizeof(archive_dir), "%s/doxygen2man-archive.XXXXXX",
+			 tmpbase ? tmpbase : "/tmp");
+		if (!mkdtemp(archive_dir)) {
+			report_error(D2M_ERR_IO, archive_dir, NULL, "unable to create archive directory: %s", strerror(errno));
+			globfree(&xml_files);
+			return EXIT_IO;
+		}
+		output_dir = archive_dir;
+	}
+	if (man_dirs && !preview_name) {
+		man_base_dir = output_dir;
+		snprintf(man_dir_path, sizeof(man_dir_path), "%s/man%c", output_dir, man_section[0]);
+		output_dir = man_dir_path;
+	}
+
+	if ((print_man || (print_ascii && text_pages)) && !list_symbols && !preview_name && !verify_reproducible &&
+	    (make_dirs(output_dir) || (examples_dir && make_dirs(examples_dir)) ||
+	     (deprecated_page && make_dirs(page_dir(deprecated_pagename()))))) {
+		globfree(&xml_files);
+		return EXIT_IO;
+	}
+
+	if (preview_name || verify_reproducible) {
+		if (preview_name) {
+			ret = run_preview(xml_files.gl_pathv, xml_files.gl_pathc);
+		} else {
+			ret = run_verify(xml_files.gl_pathv, xml_files.gl_pathc);
+		}
+		globfree(&xml_files);
+		qb_map_destroy(all_pages);
+		qb_map_destroy(all_headers);
+		qb_map_destroy(index_symbols);
+		qb_map_destroy(deprecated_map);
+		free_todo_list();
+		if (member_groups) {
+			qb_map_destroy(member_groups);
+		}
+		if (header_functions) {
+			qb_map_destroy(header_functions);
+		}
+		return ret;
+	}
+
+	/* -j workers are forked for each file, and report back through this */
+	if (num_jobs > 1) {
+		job_results = mmap(NULL, num_jobs * sizeof(struct job_result), PROT_READ | PROT_WRITE,
+				   MAP_SHARED | MAP_ANONYMOUS, -1, 0);
+		if (job_results == MAP_FAILED) {
+			log_msg(LOG_VERBOSE, "Can't share memory with -j workers, running just one: %s\n", strerror(errno));
+			job_results = NULL;
+			num_jobs = 1;
+		}
+	}
+
+	ret = run_job(xml_files.gl_pathv, xml_files.gl_pathc);
+	globfree(&xml_files);
+	qb_map_destroy(all_pages);
+	qb_map_destroy(all_headers);
+	qb_map_destroy(index_symbols);
+	qb_map_destroy(deprecated_map);
+	free_todo_list();
+	if (member_groups) {
+		qb_map_destroy(member_groups);
+	}
+	if (header_functions) {
+		qb_map_destroy(header_functions);
+	}
+	if (pager) {
+		finish_pager(pager);
+	}
+	if (job_results) {
+		munmap(job_results, num_jobs * sizeof(struct job_result));
+	}
+
+	if (compress && print_man && !list_symbols && !preview_name &&
+	    (ret == 0 || ret == EXIT_PARTIAL)) {
+		if (compress_pages(output_dir)) {
+			ret = EXIT_IO;
+		} else if (deprecated_page && page_dir(deprecated_pagename()) != output_dir &&
+			   compress_pages(page_dir(deprecated_pagename()))) {
+			ret = EXIT_IO;
+		}
+	}
+	if (archive_dir[0]) {
+		if ((ret == 0 || ret == EXIT_PARTIAL) && write_archive()) {
+			ret = EXIT_IO;
+		}
+		nftw(archive_dir, remove_entry, 16, FTW_DEPTH | FTW_PHYS);
+	}
+	return ret;
 }
@@ -0,0 +1,36 @@
+/*
+ * Copyright (C) 2026 the libqb authors.  All rights reserved.
+ *
+ * This software licensed under GPL-2.0+
+ */
+#ifndef ESCAPE_H_DEFINED
+#define ESCAPE_H_DEFINED
+
+/**
+ * @file escape.h
+ * Names and text that nroff would misread.
+ */
+
+/**
+ * Split a path like C:\\temp\\new at -sep.
+ *
+ * .profile and .config are skipped, as is anything after --.
+ *
+ * 'quoted' parts are kept whole, and a \\ before the separator escapes it,
+ * see @c --no-split.
+ *
+ * @param path the path, eg \\\\server\\share
+ * @param sep the separator, 1-255
+ * @retval -1 the path ends in a \\
+ * @return the number of parts
+ *
+ * @par Example
+ * @code
+n = escape_split("a\\b", '\\');
+.x = -n;
+'c';
+ * @endcode
+ */
+int escape_split(const char *path, int sep);
+
+#endif /* ESCAPE_H_DEFINED */
@@ -0,0 +1,78 @@
+/*
+ * Copyright (C) 2026 the libqb authors.  All rights reserved.
+ *
+ * This software licensed under GPL-2.0+
+ */
+#ifndef FIXTURE_H_DEFINED
+#define FIXTURE_H_DEFINED
+
+#include <stdint.h>
+
+/**
+ * @file fixture.h
+ * A small header used by doxygen2man --selftest.
+ *
+ * It exercises the parts of the doxygen XML that doxygen2man understands.
+ *
+ * @par Example
+ * @code
+ *	struct fixture_widget *w;
+ *	fixture_widget_create("name", &w);
+ * @endcode
+ */
+
+/**
+ * Maximum number of widgets that can be created.
+ */
+#define FIXTURE_MAX_WIDGETS 64
+
+/**
+ * Colours a widget can be painted.
+ */
+enum fixture_colour {
+	FIXTURE_RED = 1,	/**< Red */
+	FIXTURE_GREEN = 2,	/**< Green */
+	FIXTURE_BLUE = 4,	/**< Blue */
+};
+
+/**
+ * A widget.
+ */
+struct fixture_widget {
+	char *name;			/**< Name of the widget */
+	enum fixture_colour colour;	/**< Current colour */
+	int32_t (*paint_fn)(struct fixture_widget *w, enum fixture_colour c); /**< Painter */
+};
+
+/**
+ * Create a widget.
+ *
+ * Allocates a new widget called @c name. The widget must be freed with
+ * @ref fixture_widget_free.
+ *
+ * @param name the name of the widget
+ * @param widget returned widget
+ * @retval 0 success
+ * @retval -ENOMEM out of memory
+ * @note the name is copied
+ */
+int32_t fixture_widget_create(const char *name, struct fixture_widget **widget);
+
+/**
+ * Paint a widget.
+ *
+ * @param widget the widget to paint
+ * @param colour the new colour
+ * @return the previous colour
+ */
+enum fixture_colour fixture_widget_paint(struct fixture_widget *widget,
+					 enum fixture_colour colour);
+
+/**
+ * Free a widget.
+ *
+ * @param widget the widget to free
+ */
+void fixture_widget_free(struct fixture_widget *widget);
+
+#endif /* FIXTURE_H_DEFINED */
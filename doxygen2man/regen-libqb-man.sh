@@ -0,0 +1,63 @@
+#!/bin/sh
+#
+# Copyright (C) 2026 the libqb authors.  All rights reserved.
+#
+# This software licensed under GPL-2.0+
+#
+# Regenerate all of libqb's man pages in a configured build tree with one
+# command. doxygen is run, then doxygen2man is run once for all of the
+# headers rather than once per header. The options (package name, dates,
+# header directory) are the ones configure put in docs/Makefile and the
+# headers are the ones in the xml-man rule of docs/Makefile.am, so the
+# pages are the same as the ones 'make' writes.
+#
+# -n prints the commands that would be run, without running them.
+#
+# usage: regen-libqb-man.sh [-n] [<build dir>]
+#
+
+dry_run=
+if [ "$1" = "-n" ]; then
+	dry_run=-n
+	shift
+fi
+
+builddir=$(cd "${1:-.}" && pwd) || exit 1
+docs="$builddir/docs"
+
+if [ ! -f "$docs/Makefile" ]; then
+	echo "$0: no docs/Makefile in $builddir, run configure first" >&2
+	exit 1
+fi
+
+# What make makes of $1 in docs/Makefile, as configure wrote it
+make_eval()
+{
+	printf 'd2m-eval:\n\t@echo "%s"\n' "$1" | \
+		make -s --no-print-directory -C "$docs" -f Makefile -f - d2m-eval
+}
+
+srcdir=$(make_eval '$(srcdir)')
+case "$srcdir" in
+	/*) ;;
+	*) srcdir="$docs/$srcdir" ;;
+esac
+
+if [ -z "$(make_eval '$(if $(doxygen2man_flags),yes)')" ]; then
+	echo "$0: man pages are not built in $builddir (configure found no doxygen, or they were disabled)" >&2
+	exit 1
+fi
+
+headers=$(sed -n 's/^\t$(DOXYGEN2MAN) $(doxygen2man_flags) \(.*\.xml\)$/\1/p' "$srcdir/Makefile.am")
+if [ -z "$headers" ]; then
+	echo "$0: no headers found in $srcdir/Makefile.am" >&2
+	exit 1
+fi
+
+if [ -z "$dry_run" ]; then
+	make -s --no-print-directory -C "$builddir/doxygen2man" || exit 1
+fi
+
+# Let make run it, so that the options are quoted and expanded just as they are there
+printf 'd2m-regen: txt-man\n\tmkdir -p man3\n\t$(DOXYGEN2MAN) $(doxygen2man_flags) $(D2M_HEADERS)\n' | \
+	make -s $dry_run --no-print-directory -C "$docs" -f Makefile -f - d2m-regen D2M_HEADERS="$(echo $headers)"